@@ -0,0 +1,92 @@
+//! Interactive Forth.
+//!
+//! `forth [--trace] [FILE]...` runs the files, then reads lines from the
+//! terminal and answers each one with ` ok` and the stack, or with the error
+//! and where it happened. The words defined so far survive errors. Lines are
+//! kept in `~/.forth_history`. With `--trace` every instruction is printed
+//! as it runs.
+
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process;
+
+use exercism::forth_two::{Forth, StdoutTracer, Value};
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+
+fn main() {
+    let mut forth = Forth::new();
+    let (options, paths): (Vec<String>, Vec<String>) =
+        env::args().skip(1).partition(|arg| arg.starts_with("--"));
+    for option in options {
+        match option.as_str() {
+            "--trace" => forth.set_tracer(StdoutTracer),
+            _ => {
+                eprintln!("unknown option {}", option);
+                process::exit(2);
+            }
+        }
+    }
+    for path in paths {
+        if let Err(err) = forth.include_file(Path::new(&path)) {
+            if err.file.is_none() {
+                // the file itself couldn't be read
+                eprintln!("{}: {}", path, err);
+            } else {
+                eprintln!("{}", err);
+            }
+            process::exit(1);
+        }
+    }
+
+    let mut editor = match DefaultEditor::new() {
+        Ok(editor) => editor,
+        Err(err) => {
+            eprintln!("can't read the terminal: {}", err);
+            process::exit(1);
+        }
+    };
+    let history = history_path();
+    if let Some(history) = &history {
+        // there is no history before the first session
+        let _ = editor.load_history(history);
+    }
+
+    loop {
+        match editor.readline("") {
+            Ok(line) => {
+                let _ = editor.add_history_entry(line.as_str());
+                match forth.eval(&line) {
+                    Ok(()) => println!(" ok {}", show(forth.stack())),
+                    Err(err) => println!("error: {}", err),
+                }
+            }
+            // ^C drops the line being typed, ^D ends the session
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(err) => {
+                eprintln!("{}", err);
+                break;
+            }
+        }
+    }
+
+    if let Some(history) = &history {
+        if let Err(err) = editor.save_history(history) {
+            eprintln!("can't save the history: {}", err);
+        }
+    }
+}
+
+fn history_path() -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(".forth_history"))
+}
+
+/// The stack the way `.s` shows it: its depth, then the values from the
+/// bottom up.
+fn show(stack: &[Value]) -> String {
+    let values: Vec<String> = stack.iter().map(Value::to_string).collect();
+    format!("<{}> {}", stack.len(), values.join(" "))
+        .trim_end()
+        .to_string()
+}
@@ -1,22 +1,81 @@
-#[allow(unused)]
 use std::collections::HashMap;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+mod error;
+mod input;
+mod trace;
+
+pub use error::{Error, ErrorKind};
+use input::Input;
+pub use input::Position;
+pub use trace::{Event, RecordingTracer, StdoutTracer, Tracer};
 
 pub type Value = i32;
 pub type Result = std::result::Result<(), Error>;
 
+/// Most values the return stack holds. Every word call takes two of them.
+pub const RETURN_STACK_SIZE: usize = 1 << 16;
+
+/// Most cells that can be allotted in memory.
+pub const MEMORY_SIZE: usize = 1 << 16;
+
+/// Address of the first character of the strings in compiled code, which
+/// are kept apart from the memory. There's room for `MEMORY_SIZE` of them.
+const STRINGS: Value = MEMORY_SIZE as Value;
+
+/// Address of the string of the latest `s"` run outside a definition.
+const BUFFER: Value = 2 * MEMORY_SIZE as Value;
+
+/// Most files that can be included one inside the other.
+pub const INCLUDE_DEPTH: usize = 64;
+
+/// A Forth interpreter. Words are compiled once, when they are defined, into
+/// bytecode that is kept in one code space, so calling a word is a jump to
+/// its code rather than a copy of it.
 pub struct Forth {
     stack: Vec<Value>,
-    definitions: HashMap<String, Vec<Operation>>,
-}
-
-#[derive(Debug, PartialEq, Clone)]
-pub enum ExpressionType {
-    Definition,
-    Loop,
-    If,
+    /// Where running words return to, their loops and what they put aside
+    /// with `>r`.
+    return_stack: Vec<Value>,
+    /// Start of the running word's part of the return stack. Below it are
+    /// the address to return to and the caller's frame.
+    frame: usize,
+    /// Cells allotted by `variable`, `allot` and `,`, addressed by index.
+    /// The next free cell is `here`, the end of the memory.
+    memory: Vec<Value>,
+    /// The input being run. Defining words read the name of the word they
+    /// define from here, also when they run inside other words.
+    input: Input,
+    /// Characters of the strings in compiled code, from address `STRINGS`
+    /// on, out of reach of `allot` and `,`.
+    strings: Vec<Value>,
+    /// Characters of the latest `s"` run outside a definition, at address
+    /// `BUFFER`. The next one replaces them.
+    buffer: Vec<Value>,
+    /// Address of the code of the latest word made by `create`, which
+    /// `does>` changes.
+    created: Option<usize>,
+    /// Told about everything the interpreter does, when there is one.
+    tracer: Option<Box<dyn Tracer>>,
+    /// Files being included, the innermost last. A file can't include
+    /// itself, even through others.
+    including: Vec<PathBuf>,
+    /// Where `.`, `emit`, `type` and the like write, standard output
+    /// unless set otherwise.
+    output: Box<dyn Write>,
+    /// Compiled code of every word defined so far.
+    code: Vec<Instruction>,
+    /// Address of each word's code in `code`. A redefinition replaces the
+    /// address, words compiled before keep calling the old code.
+    definitions: HashMap<String, usize>,
+    /// Name each word's code was defined with, by address, for errors.
+    names: HashMap<usize, String>,
 }
 
-#[derive(Debug, PartialEq, Clone)]
+/// Built-in words working on the stack.
+#[derive(Debug, PartialEq, Clone, Copy)]
 pub enum Operation {
     Add,
     Sub,
@@ -27,25 +86,145 @@ pub enum Operation {
     Drop,
     Swap,
     Over,
-    ExpStart(ExpressionType),
-    ExpEnd,
-    Value(i32),
-    Symbol(String),
+    Equal,
+    Less,
+    Greater,
+    ZeroEqual,
+    And,
+    Or,
+    Invert,
+    /// Index of the innermost loop.
+    I,
+    /// Index of the loop around the innermost one.
+    J,
+    ToR,
+    FromR,
+    RFetch,
+    /// Reads a name and defines a word giving the address of a new cell.
+    Variable,
+    /// Reads a name and defines a word giving a value taken off the stack.
+    Constant,
+    /// Reads a name and defines a word giving the address `here`.
+    Create,
+    Fetch,
+    Store,
+    AddStore,
+    Here,
+    Allot,
+    /// `,` stores a value in a newly allotted cell.
+    Comma,
+    /// `.` writes a number.
+    Print,
+    /// `.s` writes the whole stack, leaving it as it is.
+    PrintStack,
+    /// Writes the character with the code taken off the stack.
+    Emit,
+    Cr,
+    Space,
+    Spaces,
+    /// Writes the string at an address with a length, like `s"` gives.
+    Type,
+}
+
+/// Name of every built-in word.
+const OPERATIONS: [(&str, Operation); 37] = [
+    ("+", Operation::Add),
+    ("-", Operation::Sub),
+    ("*", Operation::Mul),
+    ("/", Operation::Div),
+    ("%", Operation::Mod),
+    ("dup", Operation::Dup),
+    ("drop", Operation::Drop),
+    ("swap", Operation::Swap),
+    ("over", Operation::Over),
+    ("=", Operation::Equal),
+    ("<", Operation::Less),
+    (">", Operation::Greater),
+    ("0=", Operation::ZeroEqual),
+    ("and", Operation::And),
+    ("or", Operation::Or),
+    ("invert", Operation::Invert),
+    ("i", Operation::I),
+    ("j", Operation::J),
+    (">r", Operation::ToR),
+    ("r>", Operation::FromR),
+    ("r@", Operation::RFetch),
+    ("variable", Operation::Variable),
+    ("constant", Operation::Constant),
+    ("create", Operation::Create),
+    ("@", Operation::Fetch),
+    ("!", Operation::Store),
+    ("+!", Operation::AddStore),
+    ("here", Operation::Here),
+    ("allot", Operation::Allot),
+    (",", Operation::Comma),
+    (".", Operation::Print),
+    (".s", Operation::PrintStack),
+    ("emit", Operation::Emit),
+    ("cr", Operation::Cr),
+    ("space", Operation::Space),
+    ("spaces", Operation::Spaces),
+    ("type", Operation::Type),
+];
+
+impl Operation {
+    /// The word that runs the operation.
+    pub fn name(self) -> &'static str {
+        OPERATIONS.iter().find(|(_, op)| *op == self).unwrap().0
+    }
 }
 
-#[derive(Debug, PartialEq)]
-pub enum Error {
-    DivisionByZero,
-    StackUnderflow,
-    UnknownWord,
-    InvalidWord,
+/// One step of compiled code. Jumps are relative to the instruction after
+/// them so that code can be compiled before knowing where it will be placed.
+#[derive(Debug, PartialEq, Clone, Copy)]
+pub enum Instruction {
+    Push(Value),
+    Op(Operation),
+    /// Runs the word whose code starts at the address, then carries on.
+    Call(usize),
+    /// Ends a word, or the whole run when no word called it.
+    Return,
+    /// Placeholder for a word calling itself, until the word has an address.
+    Recurse,
+    Jump(isize),
+    /// Pops a flag and jumps if it is false.
+    JumpIfZero(isize),
+    /// Starts a counted loop with the limit and index on the stack. The
+    /// offset is where the loop ends, for `leave`.
+    Do(isize),
+    /// Adds one to the loop index and jumps back unless the loop is done.
+    Loop(isize),
+    /// Like `Loop`, with the increment popped from the stack.
+    PlusLoop(isize),
+    Leave,
+    /// Makes the latest word made by `create` run the code that follows
+    /// after giving its address, and returns.
+    Does,
+}
+
+impl Default for Forth {
+    fn default() -> Forth {
+        Forth::new()
+    }
 }
 
 impl Forth {
     pub fn new() -> Forth {
         Self {
             stack: Vec::new(),
+            return_stack: Vec::new(),
+            frame: 0,
+            memory: Vec::new(),
+            input: Input::default(),
+            strings: Vec::new(),
+            buffer: Vec::new(),
+            created: None,
+            tracer: None,
+            including: Vec::new(),
+            output: Box::new(io::stdout()),
+            code: Vec::new(),
             definitions: HashMap::new(),
+            names: HashMap::new(),
         }
     }
 
@@ -53,93 +232,656 @@ impl Forth {
         &self.stack
     }
 
+    /// Makes the output words write to `output`.
+    pub fn set_output(&mut self, output: impl Write + 'static) {
+        self.output = Box::new(output);
+    }
+
+    /// Starts telling `tracer` what the interpreter does. There is no
+    /// tracer unless one is set.
+    pub fn set_tracer(&mut self, tracer: impl Tracer + 'static) {
+        self.tracer = Some(Box::new(tracer));
+    }
+
+    /// Stops tracing, giving the tracer back.
+    pub fn take_tracer(&mut self) -> Option<Box<dyn Tracer>> {
+        self.tracer.take()
+    }
+
+    fn trace(&mut self, event: Event) {
+        if let Some(tracer) = &mut self.tracer {
+            tracer.trace(&event);
+        }
+    }
+
+    /// Runs the input word by word. Definitions are compiled as soon as they
+    /// are complete, so they can be used later in the same input.
+    /// Errors tell which word failed and where in the input, or in the
+    /// included file, the input stopped.
     pub fn eval(&mut self, input: &str) -> Result {
-        let mut operations = Forth::parse_string(input);
-        self.eval_ops(&mut operations)?;
+        let input = std::mem::replace(&mut self.input, Input::new(input));
+        let result = self.eval_input().map_err(|mut err| {
+            // an included file failing has already told where
+            if let Some(token) = self.input.last() {
+                err.word.get_or_insert_with(|| token.text.clone());
+                err.position.get_or_insert(token.position);
+            }
+            err
+        });
+        self.input = input;
+        result
+    }
+
+    fn eval_input(&mut self) -> Result {
+        while let Some(word) = self.next_word() {
+            match word.as_str() {
+                ":" => self.define()?,
+                "include" => self.include()?,
+                // control structures are compiled whole and run at once
+                "if" | "do" | "begin" => {
+                    let mut code = Vec::new();
+                    self.compile(&word, &mut code)?;
+                    self.execute(code)?;
+                }
+                ".\"" => {
+                    let text = self.read_string()?;
+                    self.write(&text)?;
+                }
+                "s\"" => {
+                    self.buffer = self.read_string()?.chars().map(|c| c as Value).collect();
+                    self.stack.push(BUFFER);
+                    self.stack.push(self.buffer.len() as Value);
+                }
+                _ => self.interpret(&word)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn interpret(&mut self, word: &str) -> Result {
+        match self.compile_word(word)? {
+            Instruction::Call(address) => self.run(address).map_err(|mut err| {
+                err.calls.insert(0, word.to_string());
+                err
+            }),
+            instruction => {
+                // nothing to jump within, so the address doesn't matter
+                let mut ip = 0;
+                self.step(instruction, &mut ip, self.frame)?;
+                Ok(())
+            }
+        }
+    }
 
-        println!("[*] done! {:?}", self.stack);
+    /// Compiles `: name ... ;`, with the `:` already read.
+    fn define(&mut self) -> Result {
+        let name = self.read_name()?;
+        let mut code = Vec::new();
+        self.compile_until(&[";"], &mut code)?;
+        code.push(Instruction::Return);
+
+        let address = self.code.len();
+        for instruction in &mut code {
+            if *instruction == Instruction::Recurse {
+                *instruction = Instruction::Call(address);
+            }
+        }
+        self.trace(Event::Defined {
+            name: name.clone(),
+            size: code.len(),
+        });
+        self.definitions.insert(name.clone(), address);
+        self.names.insert(address, name);
+        self.code.append(&mut code);
         Ok(())
     }
 
-    fn define_symbol(&mut self, name: String) -> Option<Vec<Operation>> {
-        let result = if let Some(ops) = self.definitions.get(&name) {
-            Some(ops.to_vec())
-        } else if let Some(op) = Forth::match_op(name.clone()) {
-            Some(vec![op])
-        } else {
-            None
-        };
-        println!("[|]\t => '{}' {:?}", name, result.clone().unwrap_or(vec![]));
+    /// Reads the next word of the input. Words are case-insensitive.
+    fn next_word(&mut self) -> Option<String> {
+        self.input.word().map(|token| token.text.to_lowercase())
+    }
+
+    /// Reads the text of a string up to the closing `"`.
+    fn read_string(&mut self) -> std::result::Result<String, Error> {
+        self.input
+            .until('"')
+            .ok_or_else(|| ErrorKind::InvalidWord.into())
+    }
+
+    /// Keeps `text` for compiled code, a character in each cell, and gives
+    /// its address and length.
+    fn store_string(&mut self, text: &str) -> std::result::Result<(Value, Value), Error> {
+        let address = STRINGS + self.strings.len() as Value;
+        let length = self.strings.len();
+        self.strings.extend(text.chars().map(|c| c as Value));
+        if self.strings.len() > MEMORY_SIZE {
+            self.strings.truncate(length);
+            return Err(ErrorKind::OutOfMemory.into());
+        }
+        Ok((address, (self.strings.len() - length) as Value))
+    }
+
+    /// Runs `include file`, with the `include` already read: the words of
+    /// the file run as if they were typed in its place.
+    fn include(&mut self) -> Result {
+        let path = self.input.word().ok_or(ErrorKind::InvalidWord)?.text;
+        self.include_file(Path::new(&path))
+    }
+
+    /// Runs the words of a file, like `include` does for a path without
+    /// whitespace in it.
+    pub fn include_file(&mut self, path: &Path) -> Result {
+        let canonical = fs::canonicalize(path).map_err(|err| ErrorKind::Io(err.kind()))?;
+        if self.including.len() == INCLUDE_DEPTH || self.including.contains(&canonical) {
+            return Err(ErrorKind::InvalidWord.into());
+        }
+        let source = fs::read_to_string(&canonical).map_err(|err| ErrorKind::Io(err.kind()))?;
+        self.including.push(canonical);
+        let result = self.eval(&source).map_err(|mut err| {
+            // the position is in this file unless a file it included has
+            // already told which one
+            if err.file.is_none() {
+                err.file = Some(path.to_path_buf());
+            }
+            err
+        });
+        self.including.pop();
         result
     }
 
-    fn eval_ops(&mut self, operations: &mut Vec<Operation>) -> Result {
-        while let Some(op) = operations.pop() {
-            self.eval_op(operations, op)?;
+    /// Reads the name of a word to define, which can't be a number.
+    fn read_name(&mut self) -> std::result::Result<String, Error> {
+        let name = self.next_word().ok_or(ErrorKind::InvalidWord)?;
+        if name.parse::<Value>().is_ok() {
+            return Err(ErrorKind::InvalidWord.into());
         }
+        Ok(name)
+    }
+
+    /// Defines a word for code made while running, which gives `value`.
+    fn define_value(&mut self, value: Value) -> Result {
+        let name = self.read_name()?;
+        self.trace(Event::Defined {
+            name: name.clone(),
+            size: 2,
+        });
+        self.definitions.insert(name.clone(), self.code.len());
+        self.names.insert(self.code.len(), name);
+        self.code.push(Instruction::Push(value));
+        self.code.push(Instruction::Return);
         Ok(())
     }
 
-    fn eval_op(&mut self, operations: &mut Vec<Operation>, op: Operation) -> Result {
-        println!("[*] {:?} {:?}", op, self.stack);
-        match op {
-            Operation::Value(n) => {
-                self.stack.push(n);
+    /// Compiles words onto `code` up to one of `ends`, and tells which one
+    /// it was.
+    fn compile_until(
+        &mut self,
+        ends: &[&str],
+        code: &mut Vec<Instruction>,
+    ) -> std::result::Result<usize, Error> {
+        loop {
+            let word = self.next_word().ok_or(ErrorKind::InvalidWord)?;
+            if let Some(end) = ends.iter().position(|end| *end == word) {
+                return Ok(end);
+            }
+            self.compile(&word, code)?;
+        }
+    }
+
+    /// Compiles a word onto `code`, along with the rest of the structure it
+    /// starts. A definition inside another one is compiled on its own and
+    /// defined right away.
+    fn compile(&mut self, word: &str, code: &mut Vec<Instruction>) -> Result {
+        match word {
+            ":" => self.define(),
+            "if" => self.compile_if(code),
+            "do" => self.compile_do(code),
+            "begin" => self.compile_begin(code),
+            "recurse" => {
+                code.push(Instruction::Recurse);
                 Ok(())
             }
-            Operation::Symbol(sym) => {
-                if let Some(mut ops) = self.define_symbol(sym) {
-                    operations.append(&mut ops);
-                    Ok(())
-                } else {
-                    Err(Error::UnknownWord)
+            "leave" => {
+                code.push(Instruction::Leave);
+                Ok(())
+            }
+            "does>" => {
+                code.push(Instruction::Does);
+                Ok(())
+            }
+            // the text is kept with the code, for the code to use
+            ".\"" | "s\"" => {
+                let text = self.read_string()?;
+                let (address, length) = self.store_string(&text)?;
+                code.push(Instruction::Push(address));
+                code.push(Instruction::Push(length));
+                if word == ".\"" {
+                    code.push(Instruction::Op(Operation::Type));
+                }
+                Ok(())
+            }
+            _ => {
+                code.push(self.compile_word(word)?);
+                Ok(())
+            }
+        }
+    }
+
+    fn compile_word(&self, word: &str) -> std::result::Result<Instruction, Error> {
+        if let Ok(n) = word.parse::<Value>() {
+            Ok(Instruction::Push(n))
+        } else if let Some(&address) = self.definitions.get(word) {
+            Ok(Instruction::Call(address))
+        } else if let Some(op) = Forth::match_op(word) {
+            Ok(Instruction::Op(op))
+        } else if Forth::is_compile_only(word) {
+            Err(ErrorKind::InvalidWord.into())
+        } else {
+            Err(ErrorKind::UnknownWord.into())
+        }
+    }
+
+    /// Compiles `if ... then` or `if ... else ... then`, with the `if`
+    /// already read.
+    fn compile_if(&mut self, code: &mut Vec<Instruction>) -> Result {
+        let branch = code.len();
+        code.push(Instruction::JumpIfZero(0));
+        if self.compile_until(&["else", "then"], code)? == 0 {
+            let skip = code.len();
+            code.push(Instruction::Jump(0));
+            code[branch] = Instruction::JumpIfZero(Forth::offset(branch, code.len()));
+            self.compile_until(&["then"], code)?;
+            code[skip] = Instruction::Jump(Forth::offset(skip, code.len()));
+        } else {
+            code[branch] = Instruction::JumpIfZero(Forth::offset(branch, code.len()));
+        }
+        Ok(())
+    }
+
+    /// Compiles `do ... loop` or `do ... +loop`, with the `do` already read.
+    fn compile_do(&mut self, code: &mut Vec<Instruction>) -> Result {
+        let start = code.len();
+        code.push(Instruction::Do(0));
+        let end = self.compile_until(&["loop", "+loop"], code)?;
+        let back = Forth::offset(code.len(), start + 1);
+        if end == 0 {
+            code.push(Instruction::Loop(back));
+        } else {
+            code.push(Instruction::PlusLoop(back));
+        }
+        code[start] = Instruction::Do(Forth::offset(start, code.len()));
+        Ok(())
+    }
+
+    /// Compiles `begin ... until` or `begin ... while ... repeat`, with the
+    /// `begin` already read.
+    fn compile_begin(&mut self, code: &mut Vec<Instruction>) -> Result {
+        let start = code.len();
+        if self.compile_until(&["until", "while"], code)? == 0 {
+            code.push(Instruction::JumpIfZero(Forth::offset(code.len(), start)));
+            return Ok(());
+        }
+        let exit = code.len();
+        code.push(Instruction::JumpIfZero(0));
+        self.compile_until(&["repeat"], code)?;
+        code.push(Instruction::Jump(Forth::offset(code.len(), start)));
+        code[exit] = Instruction::JumpIfZero(Forth::offset(exit, code.len()));
+        Ok(())
+    }
+
+    /// Offset for a jump at `from` to `to`.
+    fn offset(from: usize, to: usize) -> isize {
+        to as isize - (from as isize + 1)
+    }
+
+    /// Words that only mean something as part of a structure.
+    fn is_compile_only(word: &str) -> bool {
+        matches!(
+            word,
+            ";" | "else"
+                | "then"
+                | "recurse"
+                | "loop"
+                | "+loop"
+                | "leave"
+                | "until"
+                | "while"
+                | "repeat"
+                | "does>"
+        )
+    }
+
+    /// Runs code that isn't part of any word, then drops it again unless
+    /// it defined words of its own.
+    fn execute(&mut self, mut code: Vec<Instruction>) -> Result {
+        if code.contains(&Instruction::Recurse) || code.contains(&Instruction::Does) {
+            return Err(ErrorKind::InvalidWord.into());
+        }
+        let address = self.code.len();
+        let strings = self.strings.len();
+        self.code.append(&mut code);
+        let end = self.code.len();
+        let result = self.run_inline(address, end);
+        if self.code.len() == end {
+            self.code.truncate(address);
+            self.strings.truncate(strings);
+        }
+        result
+    }
+
+    /// Runs the code from `address` up to `end` in the running frame rather
+    /// than a frame of its own, so it can take what was put aside with `>r`
+    /// before it. The loops it is in when it fails are dropped.
+    fn run_inline(&mut self, address: usize, end: usize) -> Result {
+        let depth = self.return_stack.len();
+        let start = self.frame;
+        let mut ip = address;
+        while ip != end {
+            let instruction = self.code[ip];
+            ip += 1;
+            if let Err(err) = self.step(instruction, &mut ip, start) {
+                self.return_stack.truncate(depth);
+                self.frame = start;
+                return Err(err);
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the code at `address` until it returns. Whatever it leaves on
+    /// the return stack, also when it fails, is dropped.
+    fn run(&mut self, address: usize) -> Result {
+        let depth = self.return_stack.len();
+        let frame = std::mem::replace(&mut self.frame, depth);
+        let result = self.interpreter_loop(address);
+        self.return_stack.truncate(depth);
+        self.frame = frame;
+        result
+    }
+
+    fn interpreter_loop(&mut self, address: usize) -> Result {
+        let start = self.frame;
+        let mut ip = address;
+        loop {
+            let instruction = self.code[ip];
+            ip += 1;
+            if !self.step(instruction, &mut ip, start)? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Runs one instruction, with `ip` already past it, and tells whether
+    /// the run started at frame `start` goes on.
+    fn step(
+        &mut self,
+        instruction: Instruction,
+        ip: &mut usize,
+        start: usize,
+    ) -> std::result::Result<bool, Error> {
+        let before = self.tracer.is_some().then(|| self.stack.clone());
+        let result = self.perform(instruction, ip, start);
+        if let Some(before) = before {
+            let after = self.stack.clone();
+            self.trace(Event::Executed {
+                instruction,
+                before,
+                after,
+            });
+        }
+        result.map_err(|mut err| {
+            if err.word.is_none() {
+                err.word = self.instruction_name(instruction);
+            }
+            err.calls = self.calls(start);
+            err
+        })
+    }
+
+    /// The word an instruction was compiled from, when it can tell.
+    fn instruction_name(&self, instruction: Instruction) -> Option<String> {
+        match instruction {
+            Instruction::Op(op) => Some(op.name().to_string()),
+            Instruction::Call(address) => self.names.get(&address).cloned(),
+            Instruction::Do(_) => Some("do".to_string()),
+            Instruction::Loop(_) => Some("loop".to_string()),
+            Instruction::PlusLoop(_) => Some("+loop".to_string()),
+            Instruction::Leave => Some("leave".to_string()),
+            Instruction::Does => Some("does>".to_string()),
+            _ => None,
+        }
+    }
+
+    /// Names of the words called by the code run from frame `start`, the
+    /// outermost first.
+    fn calls(&self, start: usize) -> Vec<String> {
+        let mut calls = Vec::new();
+        let mut frame = self.frame;
+        while frame != start {
+            let ip = self.return_stack[frame - 2] as usize;
+            if let Instruction::Call(address) = self.code[ip - 1] {
+                calls.extend(self.names.get(&address).cloned());
+            }
+            frame = self.return_stack[frame - 1] as usize;
+        }
+        calls.reverse();
+        calls
+    }
+
+    fn perform(
+        &mut self,
+        instruction: Instruction,
+        ip: &mut usize,
+        start: usize,
+    ) -> std::result::Result<bool, Error> {
+        match instruction {
+            Instruction::Push(n) => self.stack.push(n),
+            Instruction::Op(op) => self.eval_op(op)?,
+            Instruction::Call(address) => {
+                self.push_return(*ip as Value)?;
+                self.push_return(self.frame as Value)?;
+                self.frame = self.return_stack.len();
+                *ip = address;
+            }
+            Instruction::Return => match self.exit(start) {
+                Some(address) => *ip = address,
+                None => return Ok(false),
+            },
+            Instruction::Recurse => unreachable!("recurse outside of a definition"),
+            Instruction::Jump(offset) => *ip = (*ip).wrapping_add_signed(offset),
+            Instruction::JumpIfZero(offset) => {
+                if self.stack.pop().ok_or(ErrorKind::StackUnderflow)? == 0 {
+                    *ip = (*ip).wrapping_add_signed(offset);
+                }
+            }
+            Instruction::Do(offset) => {
+                let index = self.stack.pop().ok_or(ErrorKind::StackUnderflow)?;
+                let limit = self.stack.pop().ok_or(ErrorKind::StackUnderflow)?;
+                let exit = (*ip).wrapping_add_signed(offset);
+                self.push_return(exit as Value)?;
+                self.push_return(limit)?;
+                self.push_return(index)?;
+            }
+            Instruction::Loop(offset) => {
+                if !self.step_loop(1)? {
+                    *ip = (*ip).wrapping_add_signed(offset);
+                }
+            }
+            Instruction::PlusLoop(offset) => {
+                let step = self.stack.pop().ok_or(ErrorKind::StackUnderflow)?;
+                if !self.step_loop(step)? {
+                    *ip = (*ip).wrapping_add_signed(offset);
+                }
+            }
+            Instruction::Does => {
+                let created = self.created.ok_or(ErrorKind::InvalidWord)?;
+                self.code[created + 1] = Instruction::Jump(Forth::offset(created + 1, *ip));
+                // the rest is the created word's code
+                match self.exit(start) {
+                    Some(address) => *ip = address,
+                    None => return Ok(false),
                 }
             }
+            Instruction::Leave => {
+                self.loop_index(0)?;
+                let exit = self.return_stack[self.return_stack.len() - 3];
+                self.return_stack.truncate(self.return_stack.len() - 3);
+                *ip = exit as usize;
+            }
+        }
+        Ok(true)
+    }
+
+    /// Leaves the running word, and tells where its caller carries on unless
+    /// the word is the one the run started with, at frame `start`. The
+    /// word's loops and `>r` values end with it.
+    fn exit(&mut self, start: usize) -> Option<usize> {
+        self.return_stack.truncate(self.frame);
+        if self.frame == start {
+            return None;
+        }
+        self.frame = self.return_stack.pop().unwrap() as usize;
+        Some(self.return_stack.pop().unwrap() as usize)
+    }
+
+    /// Adds `step` to the innermost loop's index and tells whether that
+    /// ended the loop, which it does when the index crosses from just below
+    /// the limit to the limit, either way. The loop is dropped once it ends.
+    fn step_loop(&mut self, step: Value) -> std::result::Result<bool, Error> {
+        let index = self.loop_index(0)?;
+        let len = self.return_stack.len();
+        let limit = self.return_stack[len - 2];
+        // with the limit moved to `Value::MIN`, crossing it overflows
+        let (_, done) = (index.wrapping_sub(limit) ^ Value::MIN).overflowing_add(step);
+        if done {
+            self.return_stack.truncate(len - 3);
+        } else {
+            self.return_stack[len - 1] = index.wrapping_add(step);
+        }
+        Ok(done)
+    }
+
+    /// Index of the loop `depth` loops out from the innermost one.
+    fn loop_index(&self, depth: usize) -> std::result::Result<Value, Error> {
+        let len = self.return_stack.len();
+        if len < self.frame + 3 * (depth + 1) {
+            return Err(ErrorKind::ReturnStackUnderflow.into());
+        }
+        Ok(self.return_stack[len - 1 - 3 * depth])
+    }
+
+    /// Index of the memory cell at `address`.
+    fn cell(&self, address: Value) -> std::result::Result<usize, Error> {
+        match usize::try_from(address) {
+            Ok(cell) if cell < self.memory.len() => Ok(cell),
+            _ => Err(ErrorKind::InvalidAddress.into()),
+        }
+    }
+
+    /// The characters of a string, in memory, with the compiled strings or
+    /// in the `s"` buffer.
+    fn string(&self, address: Value, length: Value) -> std::result::Result<&[Value], Error> {
+        let (area, start) = if address >= BUFFER {
+            (&self.buffer, BUFFER)
+        } else if address >= STRINGS {
+            (&self.strings, STRINGS)
+        } else {
+            (&self.memory, 0)
+        };
+        if length == 0 {
+            return Ok(&[]);
+        }
+        usize::try_from(address - start)
+            .ok()
+            .zip(usize::try_from(length).ok())
+            .and_then(|(offset, length)| area.get(offset..offset.checked_add(length)?))
+            .ok_or_else(|| ErrorKind::InvalidAddress.into())
+    }
+
+    /// Allots `n` more cells, or frees them for a negative `n`.
+    fn allot(&mut self, n: Value) -> Result {
+        let here = self.memory.len() as i64 + n as i64;
+        if here < 0 {
+            return Err(ErrorKind::InvalidAddress.into());
+        }
+        if here as usize > MEMORY_SIZE {
+            return Err(ErrorKind::OutOfMemory.into());
+        }
+        self.memory.resize(here as usize, 0);
+        Ok(())
+    }
+
+    fn push_return(&mut self, n: Value) -> Result {
+        if self.return_stack.len() == RETURN_STACK_SIZE {
+            return Err(ErrorKind::ReturnStackOverflow.into());
+        }
+        self.return_stack.push(n);
+        Ok(())
+    }
+
+    /// Pops a value of the running word's frame: a word can't see where it
+    /// returns to.
+    fn pop_return(&mut self) -> std::result::Result<Value, Error> {
+        if self.return_stack.len() == self.frame {
+            return Err(ErrorKind::ReturnStackUnderflow.into());
+        }
+        Ok(self.return_stack.pop().unwrap())
+    }
+
+    fn eval_op(&mut self, op: Operation) -> Result {
+        match op {
             Operation::Add => {
-                let n2 = self.stack.pop().ok_or(Error::StackUnderflow)?;
-                let n1 = self.stack.pop().ok_or(Error::StackUnderflow)?;
-                self.stack.push(n1 + n2);
+                let n2 = self.stack.pop().ok_or(ErrorKind::StackUnderflow)?;
+                let n1 = self.stack.pop().ok_or(ErrorKind::StackUnderflow)?;
+                self.stack.push(n1.wrapping_add(n2));
                 Ok(())
             }
             Operation::Sub => {
-                let n2 = self.stack.pop().ok_or(Error::StackUnderflow)?;
-                let n1 = self.stack.pop().ok_or(Error::StackUnderflow)?;
-                self.stack.push(n1 - n2);
+                let n2 = self.stack.pop().ok_or(ErrorKind::StackUnderflow)?;
+                let n1 = self.stack.pop().ok_or(ErrorKind::StackUnderflow)?;
+                self.stack.push(n1.wrapping_sub(n2));
                 Ok(())
             }
             Operation::Mul => {
-                let n2 = self.stack.pop().ok_or(Error::StackUnderflow)?;
-                let n1 = self.stack.pop().ok_or(Error::StackUnderflow)?;
-                self.stack.push(n1 * n2);
+                let n2 = self.stack.pop().ok_or(ErrorKind::StackUnderflow)?;
+                let n1 = self.stack.pop().ok_or(ErrorKind::StackUnderflow)?;
+                self.stack.push(n1.wrapping_mul(n2));
                 Ok(())
             }
             Operation::Mod => {
-                let n2 = self.stack.pop().ok_or(Error::StackUnderflow)?;
-                let n1 = self.stack.pop().ok_or(Error::StackUnderflow)?;
-                self.stack.push(n1 % n2);
+                let n2 = self.stack.pop().ok_or(ErrorKind::StackUnderflow)?;
+                let n1 = self.stack.pop().ok_or(ErrorKind::StackUnderflow)?;
+                if n2 == 0 {
+                    return Err(ErrorKind::DivisionByZero.into());
+                }
+                self.stack.push(n1.wrapping_rem(n2));
                 Ok(())
             }
             Operation::Div => {
-                let n2 = self.stack.pop().ok_or(Error::StackUnderflow)?;
-                let n1 = self.stack.pop().ok_or(Error::StackUnderflow)?;
+                let n2 = self.stack.pop().ok_or(ErrorKind::StackUnderflow)?;
+                let n1 = self.stack.pop().ok_or(ErrorKind::StackUnderflow)?;
                 if n2 == 0 {
-                    return Err(Error::DivisionByZero);
+                    return Err(ErrorKind::DivisionByZero.into());
                 }
-                self.stack.push(n1 / n2);
+                self.stack.push(n1.wrapping_div(n2));
                 Ok(())
             }
             Operation::Dup => {
-                let n = self.stack.last().copied().ok_or(Error::StackUnderflow)?;
+                let n = self
+                    .stack
+                    .last()
+                    .copied()
+                    .ok_or(ErrorKind::StackUnderflow)?;
                 self.stack.push(n);
                 Ok(())
             }
             Operation::Drop => {
-                self.stack.pop().ok_or(Error::StackUnderflow)?;
+                self.stack.pop().ok_or(ErrorKind::StackUnderflow)?;
                 Ok(())
             }
             Operation::Swap => {
-                let n2 = self.stack.pop().ok_or(Error::StackUnderflow)?;
-                let n1 = self.stack.pop().ok_or(Error::StackUnderflow)?;
+                let n2 = self.stack.pop().ok_or(ErrorKind::StackUnderflow)?;
+                let n1 = self.stack.pop().ok_or(ErrorKind::StackUnderflow)?;
                 self.stack.push(n2);
                 self.stack.push(n1);
                 Ok(())
@@ -149,126 +891,178 @@ impl Forth {
                     .stack
                     .get(self.stack.len().wrapping_sub(2))
                     .copied()
-                    .ok_or(Error::StackUnderflow)?;
+                    .ok_or(ErrorKind::StackUnderflow)?;
+                self.stack.push(n);
+                Ok(())
+            }
+            Operation::I => {
+                let n = self.loop_index(0)?;
+                self.stack.push(n);
+                Ok(())
+            }
+            Operation::J => {
+                let n = self.loop_index(1)?;
+                self.stack.push(n);
+                Ok(())
+            }
+            Operation::ToR => {
+                let n = self.stack.pop().ok_or(ErrorKind::StackUnderflow)?;
+                self.push_return(n)
+            }
+            Operation::FromR => {
+                let n = self.pop_return()?;
+                self.stack.push(n);
+                Ok(())
+            }
+            Operation::RFetch => {
+                let n = self.pop_return()?;
+                self.return_stack.push(n);
                 self.stack.push(n);
                 Ok(())
             }
-            Operation::ExpStart(ExpressionType::Definition) => {
-                let name: String;
-                if let Some(Operation::Symbol(n)) = operations.pop() {
-                    if n.chars().all(|c| !c.is_numeric()) {
-                        name = n.to_lowercase();
-                    } else {
-                        return Err(Error::InvalidWord);
-                    }
+            Operation::Variable => {
+                self.define_value(self.memory.len() as Value)?;
+                self.allot(1)
+            }
+            Operation::Constant => {
+                let n = self.stack.pop().ok_or(ErrorKind::StackUnderflow)?;
+                self.define_value(n)
+            }
+            Operation::Create => {
+                self.define_value(self.memory.len() as Value)?;
+                self.created = Some(self.code.len() - 2);
+                Ok(())
+            }
+            Operation::Fetch => {
+                let address = self.stack.pop().ok_or(ErrorKind::StackUnderflow)?;
+                let n = self.memory[self.cell(address)?];
+                self.stack.push(n);
+                Ok(())
+            }
+            Operation::Store | Operation::AddStore => {
+                let address = self.stack.pop().ok_or(ErrorKind::StackUnderflow)?;
+                let n = self.stack.pop().ok_or(ErrorKind::StackUnderflow)?;
+                let cell = self.cell(address)?;
+                if op == Operation::Store {
+                    self.memory[cell] = n;
                 } else {
-                    return Err(Error::InvalidWord);
+                    self.memory[cell] = self.memory[cell].wrapping_add(n);
                 }
-                let new_ops = self.take_op_until(operations, Operation::ExpEnd, true)?;
-
-                self.definitions.insert(name, new_ops);
                 Ok(())
             }
-            Operation::ExpStart(ExpressionType::Loop) => {
-                let mut n_ops = self.take_op_until(
-                    operations,
-                    Operation::ExpStart(ExpressionType::Definition),
-                    true,
-                )?;
-                self.eval_ops(&mut n_ops)?;
-                let n = self.stack.pop().ok_or(Error::StackUnderflow)?;
-                let mut loop_ops = self.take_op_until(operations, Operation::ExpEnd, true)?;
-                println!("loop {:?}", loop_ops);
-                for _ in 0..n {
-                    operations.append(&mut loop_ops.clone());
+            Operation::Here => {
+                self.stack.push(self.memory.len() as Value);
+                Ok(())
+            }
+            Operation::Allot => {
+                let n = self.stack.pop().ok_or(ErrorKind::StackUnderflow)?;
+                self.allot(n)
+            }
+            Operation::Comma => {
+                let n = self.stack.pop().ok_or(ErrorKind::StackUnderflow)?;
+                self.allot(1)?;
+                *self.memory.last_mut().unwrap() = n;
+                Ok(())
+            }
+            Operation::Print => {
+                let n = self.stack.pop().ok_or(ErrorKind::StackUnderflow)?;
+                self.write(&format!("{} ", n))
+            }
+            Operation::PrintStack => {
+                let mut text = format!("<{}> ", self.stack.len());
+                for n in &self.stack {
+                    text += &format!("{} ", n);
+                }
+                self.write(&text)
+            }
+            Operation::Emit => {
+                let n = self.stack.pop().ok_or(ErrorKind::StackUnderflow)?;
+                self.write(&Forth::char(n).to_string())
+            }
+            Operation::Cr => self.write("\n"),
+            Operation::Space => self.write(" "),
+            Operation::Spaces => {
+                let n = self.stack.pop().ok_or(ErrorKind::StackUnderflow)?;
+                // a chunk at a time, however many are asked for
+                let spaces = [b' '; 64];
+                let mut left = n.max(0) as usize;
+                while left > 0 {
+                    let chunk = left.min(spaces.len());
+                    self.output
+                        .write_all(&spaces[..chunk])
+                        .map_err(|err| ErrorKind::Io(err.kind()))?;
+                    left -= chunk;
                 }
                 Ok(())
             }
-            _ => Err(Error::InvalidWord),
+            Operation::Type => {
+                let length = self.stack.pop().ok_or(ErrorKind::StackUnderflow)?;
+                let address = self.stack.pop().ok_or(ErrorKind::StackUnderflow)?;
+                let text: String = self
+                    .string(address, length)?
+                    .iter()
+                    .map(|&n| Forth::char(n))
+                    .collect();
+                self.write(&text)
+            }
+            Operation::ZeroEqual => {
+                let n = self.stack.pop().ok_or(ErrorKind::StackUnderflow)?;
+                self.stack.push(Forth::flag(n == 0));
+                Ok(())
+            }
+            Operation::Invert => {
+                let n = self.stack.pop().ok_or(ErrorKind::StackUnderflow)?;
+                self.stack.push(!n);
+                Ok(())
+            }
+            Operation::Equal
+            | Operation::Less
+            | Operation::Greater
+            | Operation::And
+            | Operation::Or => {
+                let n2 = self.stack.pop().ok_or(ErrorKind::StackUnderflow)?;
+                let n1 = self.stack.pop().ok_or(ErrorKind::StackUnderflow)?;
+                self.stack.push(match op {
+                    Operation::Equal => Forth::flag(n1 == n2),
+                    Operation::Less => Forth::flag(n1 < n2),
+                    Operation::Greater => Forth::flag(n1 > n2),
+                    Operation::And => n1 & n2,
+                    _ => n1 | n2,
+                });
+                Ok(())
+            }
         }
     }
 
-    fn take_op_until(
-        &mut self,
-        operations: &mut Vec<Operation>,
-        cmp_op: Operation,
-        eval_symbols: bool,
-    ) -> std::result::Result<Vec<Operation>, Error> {
-        let mut new_ops: Vec<Operation> = Vec::new();
-        let mut has_end = false;
-
-        while let Some(op) = operations.pop() {
-            println!("[|]\t {:?}", op);
-            match op {
-                Operation::Symbol(name) => match if eval_symbols {
-                    self.define_symbol(name.clone())
-                } else {
-                    Forth::match_op(name.clone()).and_then(|op| Some(vec![op]))
-                } {
-                    // Some([Operation::Drop]) => {}
-                    Some(mut ops) => {
-                        let first_op = ops.get(0).unwrap_or(&Operation::Add);
-                        if first_op == &cmp_op {
-                            has_end = true;
-                            break;
-                        } else if first_op == &Operation::ExpStart(ExpressionType::Definition) {
-                            let mut def_ops =
-                                self.take_op_until(operations, Operation::ExpEnd, false)?;
-                            ops.append(&mut def_ops);
-                        }
-
-                        ops.reverse();
-
-                        if eval_symbols {
-                            new_ops.append(&mut ops);
-                        } else {
-                            new_ops.push(Operation::Symbol(name));
-                        }
-                    }
-                    _ => return Err(Error::InvalidWord),
-                },
-                _ => new_ops.push(op),
-            }
-        }
-        if !has_end {
-            return Err(Error::InvalidWord);
-        }
-        new_ops.reverse();
-        Ok(new_ops)
-    }
-
-    fn match_op(op: String) -> Option<Operation> {
-        match op.as_str() {
-            "+" => Some(Operation::Add),
-            "-" => Some(Operation::Sub),
-            "*" => Some(Operation::Mul),
-            "/" => Some(Operation::Div),
-            "%" => Some(Operation::Mod),
-            "dup" => Some(Operation::Dup),
-            "drop" => Some(Operation::Drop),
-            "swap" => Some(Operation::Swap),
-            "over" => Some(Operation::Over),
-            ":" => Some(Operation::ExpStart(ExpressionType::Definition)),
-            "loop" => Some(Operation::ExpStart(ExpressionType::Loop)),
-            ";" => Some(Operation::ExpEnd),
-            _ => None,
+    /// Forth's true is all bits set.
+    fn flag(b: bool) -> Value {
+        if b {
+            -1
+        } else {
+            0
         }
     }
 
-    fn parse_string(input: &str) -> Vec<Operation> {
-        input
-            .to_lowercase()
-            .split(' ')
-            .map(|op| {
-                let num = op.parse::<i32>();
-                if let Ok(n) = num {
-                    Operation::Value(n)
-                } else {
-                    Operation::Symbol(op.to_string())
-                }
-            })
-            .rev()
-            .collect()
+    fn match_op(op: &str) -> Option<Operation> {
+        OPERATIONS
+            .iter()
+            .find(|(name, _)| *name == op)
+            .map(|&(_, op)| op)
+    }
+
+    fn write(&mut self, text: &str) -> Result {
+        self.output
+            .write_all(text.as_bytes())
+            .map_err(|err| ErrorKind::Io(err.kind()).into())
+    }
+
+    /// The character a cell holds, or the replacement character when it
+    /// isn't one.
+    fn char(n: Value) -> char {
+        u32::try_from(n)
+            .ok()
+            .and_then(char::from_u32)
+            .unwrap_or(char::REPLACEMENT_CHARACTER)
     }
 }
 
@@ -316,6 +1110,11 @@ pub fn sing(start: u32, end: u32) -> String {
 mod tests {
     use super::*;
 
+    /// Just the kind of the error, to compare.
+    fn kind(result: Result) -> std::result::Result<(), ErrorKind> {
+        result.map_err(|err| err.kind)
+    }
+
     #[test]
     fn test_song_3_0() {
         assert_eq!(sing(3, 0), "3 bottles of beer on the wall, 3 bottles of beer.\nTake one down and pass it around, 2 bottles of beer on the wall.\n\n2 bottles of beer on the wall, 2 bottles of beer.\nTake one down and pass it around, 1 bottle of beer on the wall.\n\n1 bottle of beer on the wall, 1 bottle of beer.\nTake it down and pass it around, no more bottles of beer on the wall.\n\nNo more bottles of beer on the wall, no more bottles of beer.\nGo to the store and buy some more, 99 bottles of beer on the wall.\n");
@@ -336,7 +1135,8 @@ mod tests {
         let mut f = Forth::new();
 
         assert!(f.eval(": fib over over + ;").is_ok());
-        println!("{:?}", f.eval("1 1 loop 10 : fib ;"));
+        assert!(f.eval("1 1 10 0 do fib loop").is_ok());
+        assert_eq!(vec![1, 1, 2, 3, 5, 8, 13, 21, 34, 55, 89, 144], f.stack());
     }
 
     #[test]
@@ -372,8 +1172,8 @@ mod tests {
     fn addition_error() {
         let mut f = Forth::new();
 
-        assert_eq!(Err(Error::StackUnderflow), f.eval("1 +"));
-        assert_eq!(Err(Error::StackUnderflow), f.eval("+"));
+        assert_eq!(Err(ErrorKind::StackUnderflow), kind(f.eval("1 +")));
+        assert_eq!(Err(ErrorKind::StackUnderflow), kind(f.eval("+")));
     }
 
     #[test]
@@ -389,8 +1189,8 @@ mod tests {
     fn subtraction_error() {
         let mut f = Forth::new();
 
-        assert_eq!(Err(Error::StackUnderflow), f.eval("1 -"));
-        assert_eq!(Err(Error::StackUnderflow), f.eval("-"));
+        assert_eq!(Err(ErrorKind::StackUnderflow), kind(f.eval("1 -")));
+        assert_eq!(Err(ErrorKind::StackUnderflow), kind(f.eval("-")));
     }
 
     #[test]
@@ -406,8 +1206,8 @@ mod tests {
     fn multiplication_error() {
         let mut f = Forth::new();
 
-        assert_eq!(Err(Error::StackUnderflow), f.eval("1 *"));
-        assert_eq!(Err(Error::StackUnderflow), f.eval("*"));
+        assert_eq!(Err(ErrorKind::StackUnderflow), kind(f.eval("1 *")));
+        assert_eq!(Err(ErrorKind::StackUnderflow), kind(f.eval("*")));
     }
 
     #[test]
@@ -430,15 +1230,34 @@ mod tests {
     fn division_error() {
         let mut f = Forth::new();
 
-        assert_eq!(Err(Error::StackUnderflow), f.eval("1 /"));
-        assert_eq!(Err(Error::StackUnderflow), f.eval("/"));
+        assert_eq!(Err(ErrorKind::StackUnderflow), kind(f.eval("1 /")));
+        assert_eq!(Err(ErrorKind::StackUnderflow), kind(f.eval("/")));
     }
 
     #[test]
     fn errors_if_dividing_by_zero() {
         let mut f = Forth::new();
 
-        assert_eq!(Err(Error::DivisionByZero), f.eval("4 0 /"));
+        assert_eq!(Err(ErrorKind::DivisionByZero), kind(f.eval("4 0 /")));
+    }
+
+    #[test]
+    fn errors_if_taking_remainder_by_zero() {
+        let mut f = Forth::new();
+
+        assert_eq!(Err(ErrorKind::DivisionByZero), kind(f.eval("1 0 %")));
+        assert!(f.eval("7 3 % -7 3 %").is_ok());
+        assert_eq!(vec![1, -1], f.stack());
+    }
+
+    #[test]
+    fn arithmetic_wraps_around() {
+        let mut f = Forth::new();
+
+        assert!(f
+            .eval("2147483647 1 + -2147483648 1 - 65536 65536 * -2147483648 -1 / -2147483648 -1 %")
+            .is_ok());
+        assert_eq!(vec![Value::MIN, Value::MAX, 0, Value::MIN, 0], f.stack());
     }
 
     #[test]
@@ -490,7 +1309,7 @@ mod tests {
     fn dup_error() {
         let mut f = Forth::new();
 
-        assert_eq!(Err(Error::StackUnderflow), f.eval("dup"));
+        assert_eq!(Err(ErrorKind::StackUnderflow), kind(f.eval("dup")));
     }
 
     #[test]
@@ -524,7 +1343,7 @@ mod tests {
     fn drop_error() {
         let mut f = Forth::new();
 
-        assert_eq!(Err(Error::StackUnderflow), f.eval("drop"));
+        assert_eq!(Err(ErrorKind::StackUnderflow), kind(f.eval("drop")));
     }
 
     #[test]
@@ -558,8 +1377,8 @@ mod tests {
     fn swap_error() {
         let mut f = Forth::new();
 
-        assert_eq!(Err(Error::StackUnderflow), f.eval("1 swap"));
-        assert_eq!(Err(Error::StackUnderflow), f.eval("swap"));
+        assert_eq!(Err(ErrorKind::StackUnderflow), kind(f.eval("1 swap")));
+        assert_eq!(Err(ErrorKind::StackUnderflow), kind(f.eval("swap")));
     }
 
     #[test]
@@ -593,9 +1412,9 @@ mod tests {
     fn over_error() {
         let mut f = Forth::new();
 
-        assert_eq!(Err(Error::StackUnderflow), f.eval("1 over"));
+        assert_eq!(Err(ErrorKind::StackUnderflow), kind(f.eval("1 over")));
 
-        assert_eq!(Err(Error::StackUnderflow), f.eval("over"));
+        assert_eq!(Err(ErrorKind::StackUnderflow), kind(f.eval("over")));
     }
 
     // User-defined words
@@ -699,23 +1518,23 @@ mod tests {
     fn defining_a_number() {
         let mut f = Forth::new();
 
-        assert_eq!(Err(Error::InvalidWord), f.eval(": 1 2 ;"));
+        assert_eq!(Err(ErrorKind::InvalidWord), kind(f.eval(": 1 2 ;")));
     }
 
     #[test]
     fn malformed_word_definition() {
         let mut f = Forth::new();
 
-        assert_eq!(Err(Error::InvalidWord), f.eval(":"));
-        assert_eq!(Err(Error::InvalidWord), f.eval(": foo"));
-        assert_eq!(Err(Error::InvalidWord), f.eval(": foo 1"));
+        assert_eq!(Err(ErrorKind::InvalidWord), kind(f.eval(":")));
+        assert_eq!(Err(ErrorKind::InvalidWord), kind(f.eval(": foo")));
+        assert_eq!(Err(ErrorKind::InvalidWord), kind(f.eval(": foo 1")));
     }
 
     #[test]
     fn calling_non_existing_word() {
         let mut f = Forth::new();
 
-        assert_eq!(Err(Error::UnknownWord), f.eval("1 foo"));
+        assert_eq!(Err(ErrorKind::UnknownWord), kind(f.eval("1 foo")));
     }
 
     #[test]
@@ -747,4 +1566,873 @@ mod tests {
 
         assert_eq!(vec![6, 6], f.stack());
     }
+
+    // Compiled words
+
+    #[test]
+    fn nested_words_are_compiled_once() {
+        let mut f = Forth::new();
+
+        assert!(f.eval(": w0 1 ;").is_ok());
+        for n in 1..=50 {
+            let definition = format!(": w{} w{} w{} + ;", n, n - 1, n - 1);
+            assert!(f.eval(&definition).is_ok());
+        }
+        assert!(f.code.len() < 50 * 5);
+
+        assert!(f.eval("w10").is_ok());
+        assert_eq!(vec![1024], f.stack());
+    }
+
+    // Conditionals
+
+    #[test]
+    fn comparisons() {
+        let mut f = Forth::new();
+
+        assert!(f.eval("1 2 < 1 2 > 3 3 = 0 0= 5 0=").is_ok());
+        assert_eq!(vec![-1, 0, -1, -1, 0], f.stack());
+    }
+
+    #[test]
+    fn logic() {
+        let mut f = Forth::new();
+
+        assert!(f
+            .eval("-1 0 and -1 0 or 12 10 and 0 invert 5 invert")
+            .is_ok());
+        assert_eq!(vec![0, -1, 8, -1, -6], f.stack());
+    }
+
+    #[test]
+    fn if_then() {
+        let mut f = Forth::new();
+
+        assert!(f.eval(": ?double dup 10 < if 2 * then ;").is_ok());
+        assert!(f.eval("3 ?double 20 ?double").is_ok());
+        assert_eq!(vec![6, 20], f.stack());
+    }
+
+    #[test]
+    fn if_else_then() {
+        let mut f = Forth::new();
+
+        assert!(f
+            .eval(": sign dup 0 < if drop -1 else 0= if 0 else 1 then then ;")
+            .is_ok());
+        assert!(f.eval("-5 sign 0 sign 7 sign").is_ok());
+        assert_eq!(vec![-1, 0, 1], f.stack());
+    }
+
+    #[test]
+    fn nested_conditionals() {
+        let mut f = Forth::new();
+
+        assert!(f
+            .eval(": classify if if 11 else 10 then else if 1 else 0 then then ;")
+            .is_ok());
+        assert!(f
+            .eval("-1 -1 classify 0 -1 classify -1 0 classify 0 0 classify")
+            .is_ok());
+        assert_eq!(vec![11, 10, 1, 0], f.stack());
+    }
+
+    #[test]
+    fn conditionals_outside_definitions() {
+        let mut f = Forth::new();
+
+        assert!(f
+            .eval("1 if 2 else 3 then 0 if 4 else 5 if 6 then then")
+            .is_ok());
+        assert_eq!(vec![2, 6], f.stack());
+    }
+
+    #[test]
+    fn malformed_conditionals() {
+        let mut f = Forth::new();
+
+        assert_eq!(Err(ErrorKind::InvalidWord), kind(f.eval("1 if 2")));
+        assert_eq!(
+            Err(ErrorKind::InvalidWord),
+            kind(f.eval(": foo if 2 else 3 ;"))
+        );
+        assert_eq!(Err(ErrorKind::InvalidWord), kind(f.eval("then")));
+        assert_eq!(Err(ErrorKind::InvalidWord), kind(f.eval(": foo else ;")));
+        assert_eq!(
+            Err(ErrorKind::StackUnderflow),
+            kind(Forth::new().eval("if 1 then"))
+        );
+    }
+
+    #[test]
+    fn recursion() {
+        let mut f = Forth::new();
+
+        assert!(f.eval(": fact dup 1 > if dup 1 - recurse * then ;").is_ok());
+        assert!(f.eval("5 fact").is_ok());
+        assert_eq!(vec![120], f.stack());
+
+        assert!(f.eval(": countdown dup if 1 - recurse then ;").is_ok());
+        assert!(f.eval("drop 10000 countdown").is_ok());
+        assert_eq!(vec![0], f.stack());
+
+        assert_eq!(Err(ErrorKind::InvalidWord), kind(f.eval("recurse")));
+        assert_eq!(
+            Err(ErrorKind::InvalidWord),
+            kind(f.eval("1 if recurse then"))
+        );
+    }
+
+    // Loops
+
+    #[test]
+    fn do_loop() {
+        let mut f = Forth::new();
+
+        assert!(f.eval(": squares 0 do i i * loop ;").is_ok());
+        assert!(f.eval("4 squares").is_ok());
+        assert_eq!(vec![0, 1, 4, 9], f.stack());
+    }
+
+    #[test]
+    fn plus_loop() {
+        let mut f = Forth::new();
+
+        assert!(f.eval("10 0 do i 3 +loop").is_ok());
+        assert_eq!(vec![0, 3, 6, 9], f.stack());
+
+        let mut f = Forth::new();
+        assert!(f.eval("0 4 do i -2 +loop").is_ok());
+        assert_eq!(vec![4, 2, 0], f.stack());
+    }
+
+    #[test]
+    fn nested_loops() {
+        let mut f = Forth::new();
+
+        assert!(f.eval(": table 3 1 do 3 1 do i j * loop loop ;").is_ok());
+        assert!(f.eval("table").is_ok());
+        assert_eq!(vec![1, 2, 2, 4], f.stack());
+        assert!(f.return_stack.is_empty());
+    }
+
+    #[test]
+    fn leave() {
+        let mut f = Forth::new();
+
+        assert!(f
+            .eval(": first-over 100 0 do dup i * 20 > if i leave then loop ;")
+            .is_ok());
+        assert!(f.eval("7 first-over").is_ok());
+        assert_eq!(vec![7, 3], f.stack());
+
+        assert!(f
+            .eval("3 0 do 3 0 do i 1 = if leave then i loop 10 loop")
+            .is_ok());
+        assert_eq!(vec![7, 3, 0, 10, 0, 10, 0, 10], f.stack());
+        assert!(f.return_stack.is_empty());
+    }
+
+    #[test]
+    fn begin_until() {
+        let mut f = Forth::new();
+
+        assert!(f.eval(": halve begin 2 / dup 2 < until ;").is_ok());
+        assert!(f.eval("100 halve").is_ok());
+        assert_eq!(vec![1], f.stack());
+    }
+
+    #[test]
+    fn begin_while_repeat() {
+        let mut f = Forth::new();
+
+        assert!(f
+            .eval(": digits 0 swap begin dup while 10 / swap 1 + swap repeat drop ;")
+            .is_ok());
+        assert!(f.eval("12345 digits 0 digits").is_ok());
+        assert_eq!(vec![5, 0], f.stack());
+    }
+
+    #[test]
+    fn loops_are_not_expanded() {
+        let mut f = Forth::new();
+
+        assert!(f.eval(": count 0 swap 0 do 1 + loop ;").is_ok());
+        let size = f.code.len();
+        assert!(f.eval("10000 count").is_ok());
+        assert_eq!(vec![10000], f.stack());
+        assert_eq!(size, f.code.len());
+    }
+
+    #[test]
+    fn malformed_loops() {
+        let mut f = Forth::new();
+
+        assert_eq!(Err(ErrorKind::InvalidWord), kind(f.eval("3 0 do i")));
+        assert_eq!(
+            Err(ErrorKind::InvalidWord),
+            kind(f.eval(": foo begin 1 while ;"))
+        );
+        assert_eq!(Err(ErrorKind::InvalidWord), kind(f.eval("loop")));
+        assert_eq!(Err(ErrorKind::InvalidWord), kind(f.eval("leave")));
+        assert_eq!(Err(ErrorKind::ReturnStackUnderflow), kind(f.eval("i")));
+        assert_eq!(
+            Err(ErrorKind::StackUnderflow),
+            kind(Forth::new().eval("1 do i loop"))
+        );
+        assert!(f.code.is_empty());
+        assert!(f.return_stack.is_empty());
+    }
+
+    #[test]
+    fn errors_drop_unfinished_loops() {
+        let mut f = Forth::new();
+
+        assert_eq!(
+            Err(ErrorKind::DivisionByZero),
+            kind(f.eval("3 0 do 1 0 / loop"))
+        );
+        assert!(f.return_stack.is_empty());
+    }
+
+    // Return stack
+
+    #[test]
+    fn return_stack_words() {
+        let mut f = Forth::new();
+
+        assert!(f.eval("1 2 >r 3 r@ r>").is_ok());
+        assert_eq!(vec![1, 3, 2, 2], f.stack());
+        assert!(f.return_stack.is_empty());
+    }
+
+    #[test]
+    fn return_stack_in_words() {
+        let mut f = Forth::new();
+
+        assert!(f.eval(": rot >r swap r> swap ;").is_ok());
+        assert!(f.eval(": sum-of-squares dup * >r dup * r> + ;").is_ok());
+        assert!(f.eval("1 2 3 rot 3 4 sum-of-squares").is_ok());
+        assert_eq!(vec![2, 3, 1, 25], f.stack());
+    }
+
+    #[test]
+    fn return_stack_underflow() {
+        let mut f = Forth::new();
+
+        assert_eq!(Err(ErrorKind::ReturnStackUnderflow), kind(f.eval("r>")));
+        assert_eq!(Err(ErrorKind::ReturnStackUnderflow), kind(f.eval("r@")));
+        assert_eq!(Err(ErrorKind::StackUnderflow), kind(f.eval(">r")));
+
+        // a word can't take where it returns to
+        assert!(f.eval(": steal r> ;").is_ok());
+        assert_eq!(Err(ErrorKind::ReturnStackUnderflow), kind(f.eval("steal")));
+        assert!(f.eval(": index i ;").is_ok());
+        assert_eq!(
+            Err(ErrorKind::ReturnStackUnderflow),
+            kind(f.eval("3 0 do index loop"))
+        );
+        assert!(f.return_stack.is_empty());
+    }
+
+    #[test]
+    fn return_stack_across_control_structures() {
+        let mut f = Forth::new();
+
+        assert!(f.eval("5 >r 1 if r> then").is_ok());
+        assert_eq!(vec![5], f.stack());
+        assert!(f.eval("7 >r 3 0 do i loop r>").is_ok());
+        assert_eq!(vec![5, 0, 1, 2, 7], f.stack());
+        assert!(f.return_stack.is_empty());
+
+        assert!(f.eval("9 >r").is_ok());
+        assert_eq!(
+            Err(ErrorKind::DivisionByZero),
+            kind(f.eval("3 0 do 1 0 / loop"))
+        );
+        assert_eq!(vec![9], f.return_stack);
+    }
+
+    #[test]
+    fn words_drop_what_they_leave_on_the_return_stack() {
+        let mut f = Forth::new();
+
+        assert!(f
+            .eval(": keep 5 >r ; : leaves 3 0 do i 1 = if keep then loop ;")
+            .is_ok());
+        assert!(f.eval("leaves 7").is_ok());
+        assert_eq!(vec![7], f.stack());
+        assert!(f.return_stack.is_empty());
+    }
+
+    #[test]
+    fn return_stack_overflow() {
+        let mut f = Forth::new();
+
+        assert!(f.eval(": forever recurse ;").is_ok());
+        assert_eq!(Err(ErrorKind::ReturnStackOverflow), kind(f.eval("forever")));
+        assert!(f.return_stack.is_empty());
+        assert_eq!(0, f.frame);
+
+        assert!(f.eval("1 2 +").is_ok());
+        assert_eq!(vec![3], f.stack());
+    }
+
+    // Memory
+
+    #[test]
+    fn variables() {
+        let mut f = Forth::new();
+
+        assert!(f.eval("variable x variable y 5 x ! 7 y ! x @ y @").is_ok());
+        assert!(f.eval("3 x +! x @").is_ok());
+        assert_eq!(vec![5, 7, 8], f.stack());
+    }
+
+    #[test]
+    fn variables_in_words() {
+        let mut f = Forth::new();
+
+        assert!(f
+            .eval("variable total : add total +! ; : sum 0 total ! 5 1 do i add loop total @ ;")
+            .is_ok());
+        assert!(f.eval("sum sum").is_ok());
+        assert_eq!(vec![10, 10], f.stack());
+    }
+
+    #[test]
+    fn constants() {
+        let mut f = Forth::new();
+
+        assert!(f
+            .eval("6 7 * constant answer : twice answer 2 * ; answer twice")
+            .is_ok());
+        assert_eq!(vec![42, 84], f.stack());
+        assert_eq!(
+            Err(ErrorKind::StackUnderflow),
+            kind(Forth::new().eval("constant none"))
+        );
+        assert_eq!(Err(ErrorKind::InvalidWord), kind(f.eval("1 constant 2")));
+        assert_eq!(Err(ErrorKind::InvalidWord), kind(f.eval("1 constant")));
+    }
+
+    #[test]
+    fn allot_and_comma() {
+        let mut f = Forth::new();
+
+        assert!(f.eval("here 3 allot here 10 , 20 , here").is_ok());
+        assert_eq!(vec![0, 3, 5], f.stack());
+        assert!(f.eval("3 @ 4 @ 0 @").is_ok());
+        assert_eq!(vec![0, 3, 5, 10, 20, 0], f.stack());
+        assert!(f.eval("-5 allot here").is_ok());
+        assert_eq!(0, *f.stack().last().unwrap());
+    }
+
+    #[test]
+    fn memory_is_bounds_checked() {
+        let mut f = Forth::new();
+
+        assert_eq!(Err(ErrorKind::InvalidAddress), kind(f.eval("0 @")));
+        assert!(f.eval("variable x").is_ok());
+        assert_eq!(Err(ErrorKind::InvalidAddress), kind(f.eval("1 x 1 + !")));
+        assert_eq!(Err(ErrorKind::InvalidAddress), kind(f.eval("-1 @")));
+        assert_eq!(Err(ErrorKind::InvalidAddress), kind(f.eval("-2 allot")));
+        assert_eq!(Err(ErrorKind::OutOfMemory), kind(f.eval("65536 allot")));
+        assert_eq!(1, f.memory.len());
+    }
+
+    #[test]
+    fn create() {
+        let mut f = Forth::new();
+
+        assert!(f.eval("create primes 2 , 3 , 5 , 7 ,").is_ok());
+        assert!(f.eval(": prime primes + @ ; 0 prime 3 prime").is_ok());
+        assert_eq!(vec![2, 7], f.stack());
+    }
+
+    #[test]
+    fn create_does() {
+        let mut f = Forth::new();
+
+        assert!(f.eval(": const create , does> @ ;").is_ok());
+        assert!(f.eval(": array create allot does> + ;").is_ok());
+        assert!(f
+            .eval("12 const dozen 3 array xs 7 1 xs ! dozen 1 xs @ 0 xs here")
+            .is_ok());
+        assert_eq!(vec![12, 7, 1, 4], f.stack());
+    }
+
+    #[test]
+    fn does_needs_create() {
+        let mut f = Forth::new();
+
+        assert!(f.eval(": broken does> 1 ;").is_ok());
+        assert_eq!(Err(ErrorKind::InvalidWord), kind(f.eval("broken")));
+        assert_eq!(Err(ErrorKind::InvalidWord), kind(f.eval("does>")));
+    }
+
+    // Files
+
+    #[test]
+    fn include() {
+        let dir = std::env::temp_dir().join(format!("forth-include-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let squares = dir.join("Squares.fs");
+        fs::write(
+            &squares,
+            ": square dup * ;\n\n: squares\n\t0 do i square loop ;\n",
+        )
+        .unwrap();
+        let main = dir.join("main.fs");
+        fs::write(&main, format!("include {}\n3 squares", squares.display())).unwrap();
+
+        let mut f = Forth::new();
+        assert!(f
+            .eval(&format!("1 include {} 4 square", main.display()))
+            .is_ok());
+        assert_eq!(vec![1, 0, 1, 4, 16], f.stack());
+
+        assert_eq!(
+            Err(ErrorKind::Io(io::ErrorKind::NotFound)),
+            kind(f.eval(&format!("include {}", dir.join("missing.fs").display())))
+        );
+        assert_eq!(Err(ErrorKind::InvalidWord), kind(f.eval("include")));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // Source text
+
+    #[test]
+    fn comments_and_whitespace() {
+        let mut f = Forth::new();
+
+        assert!(f
+            .eval(": square ( n -- n*n )\n\tdup * \\ ( not a comment\r\n;\n\n3  square\t( 9 ) 2")
+            .is_ok());
+        assert_eq!(vec![9, 2], f.stack());
+    }
+
+    #[test]
+    fn error_positions() {
+        let mut f = Forth::new();
+
+        let err = f.eval("1 2\n  + ( sum ) Foo bar").unwrap_err();
+        assert_eq!(err, ErrorKind::UnknownWord);
+        assert_eq!(Some("Foo"), err.word.as_deref());
+        assert_eq!(
+            Some(Position {
+                line: 2,
+                column: 13
+            }),
+            err.position
+        );
+        assert_eq!("2:13: Foo: unknown word", err.to_string());
+
+        assert!(f.eval(": boom 0 / ;").is_ok());
+        let err = f.eval("\\ fails\n\n 1 boom 2").unwrap_err();
+        assert_eq!(Some("/"), err.word.as_deref());
+        assert_eq!(Some(Position { line: 3, column: 4 }), err.position);
+        assert_eq!("3:4: /: division by zero (in boom)", err.to_string());
+
+        let err = f.eval("  .\" no end").unwrap_err();
+        assert_eq!(err, ErrorKind::InvalidWord);
+        assert_eq!(Some(Position { line: 1, column: 3 }), err.position);
+    }
+
+    #[test]
+    fn error_positions_in_included_files() {
+        let dir = std::env::temp_dir().join(format!("forth-positions-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let broken = dir.join("broken.fs");
+        fs::write(&broken, ": ok 1 ;\nok\n  ok drop drop drop\n").unwrap();
+
+        let mut f = Forth::new();
+        let err = f
+            .eval(&format!("include {} 1", broken.display()))
+            .unwrap_err();
+        assert_eq!(err, ErrorKind::StackUnderflow);
+        assert_eq!(
+            Some(Position {
+                line: 3,
+                column: 16
+            }),
+            err.position
+        );
+        assert_eq!(Some(&broken), err.file.as_ref());
+
+        let outer = dir.join("outer.fs");
+        fs::write(&outer, format!("\n\ninclude {}", broken.display())).unwrap();
+        let err = f.include_file(&outer).unwrap_err();
+        assert_eq!(
+            Some(Position {
+                line: 3,
+                column: 16
+            }),
+            err.position
+        );
+        assert_eq!(
+            format!("{}:3:16: drop: stack underflow", broken.display()),
+            err.to_string()
+        );
+
+        let missing = dir.join("missing.fs").display().to_string();
+        let err = f.eval(&format!("\n include {}", missing)).unwrap_err();
+        assert_eq!(err, ErrorKind::Io(io::ErrorKind::NotFound));
+        assert_eq!(Some(missing.clone()), err.word);
+        assert_eq!(
+            Some(Position {
+                line: 2,
+                column: 10
+            }),
+            err.position
+        );
+        assert_eq!(None, err.file);
+
+        // a file missing from an included file is reported in that file
+        fs::write(&outer, format!("\ninclude {}", missing)).unwrap();
+        let err = f.include_file(&outer).unwrap_err();
+        assert_eq!(Some(Position { line: 2, column: 9 }), err.position);
+        assert_eq!(Some(&outer), err.file.as_ref());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn error_call_chains() {
+        let mut f = Forth::new();
+
+        assert!(f
+            .eval(": inner drop drop ; : middle 1 inner ; : outer middle ;")
+            .is_ok());
+        let err = f.eval("outer").unwrap_err();
+        assert_eq!(err, ErrorKind::StackUnderflow);
+        assert_eq!(Some("drop"), err.word.as_deref());
+        assert_eq!(vec!["outer", "middle", "inner"], err.calls);
+        assert_eq!(
+            "1:1: drop: stack underflow (in outer → middle → inner)",
+            err.to_string()
+        );
+
+        // nothing is left of the calls for the next error
+        let err = f.eval("drop").unwrap_err();
+        assert!(err.calls.is_empty());
+        let err = f.eval("1 if inner then").unwrap_err();
+        assert_eq!(vec!["inner"], err.calls);
+
+        assert!(f
+            .eval(": down dup if 1 - recurse then drop drop ; : deep 4 down ;")
+            .is_ok());
+        let err = f.eval("deep").unwrap_err();
+        assert_eq!(
+            vec!["deep", "down", "down", "down", "down", "down"],
+            err.calls
+        );
+        assert_eq!(
+            "1:1: drop: stack underflow (in deep → down × 5)",
+            err.to_string()
+        );
+    }
+
+    #[test]
+    fn errors_at_run_time_name_the_word() {
+        let mut f = Forth::new();
+
+        assert!(f.eval(": forever recurse ; : define variable ;").is_ok());
+        let err = f.eval("forever").unwrap_err();
+        assert_eq!(err, ErrorKind::ReturnStackOverflow);
+        assert_eq!(Some("forever"), err.word.as_deref());
+        assert_eq!(RETURN_STACK_SIZE / 2 + 1, err.calls.len());
+
+        let err = f.eval("define").unwrap_err();
+        assert_eq!(err, ErrorKind::InvalidWord);
+        assert_eq!(Some("variable"), err.word.as_deref());
+        assert_eq!(vec!["define"], err.calls);
+        let err = f.eval("2 1 do +loop").unwrap_err();
+        assert_eq!(Some("+loop"), err.word.as_deref());
+    }
+
+    // Output
+
+    /// Output that can be read after it's given to a `Forth`.
+    #[derive(Clone, Default)]
+    struct Capture(std::rc::Rc<std::cell::RefCell<Vec<u8>>>);
+
+    impl Capture {
+        fn text(&self) -> String {
+            String::from_utf8(self.0.take()).unwrap()
+        }
+    }
+
+    impl Write for Capture {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn capturing() -> (Forth, Capture) {
+        let capture = Capture::default();
+        let mut f = Forth::new();
+        f.set_output(capture.clone());
+        (f, capture)
+    }
+
+    #[test]
+    fn print_numbers() {
+        let (mut f, out) = capturing();
+
+        assert!(f.eval("1 2 3 .s . cr -4 . .s").is_ok());
+        assert_eq!("<3> 1 2 3 3 \n-4 <2> 1 2 ", out.text());
+        assert_eq!(Err(ErrorKind::StackUnderflow), kind(f.eval("drop drop .")));
+    }
+
+    #[test]
+    fn emit_and_spaces() {
+        let (mut f, out) = capturing();
+
+        assert!(f
+            .eval("72 emit 105 emit space 3 spaces 0 spaces -1 spaces 33 emit")
+            .is_ok());
+        assert_eq!("Hi    !", out.text());
+        assert!(f.eval("150 spaces").is_ok());
+        assert_eq!(" ".repeat(150), out.text());
+        assert!(f.eval("-1 emit").is_ok());
+        assert_eq!("\u{fffd}", out.text());
+    }
+
+    #[test]
+    fn print_strings() {
+        let (mut f, out) = capturing();
+
+        assert!(f.eval(".\" Hello,  World!\" cr").is_ok());
+        assert_eq!("Hello,  World!\n", out.text());
+
+        assert!(f.eval(": greet .\" Hi \" . ; 3 greet 4 GREET").is_ok());
+        assert_eq!("Hi 3 Hi 4 ", out.text());
+        assert!(f.stack().is_empty());
+    }
+
+    #[test]
+    fn string_literals() {
+        let (mut f, out) = capturing();
+
+        assert!(f.eval("s\" abc\" swap over type").is_ok());
+        assert_eq!("abc", out.text());
+        assert_eq!(vec![3], f.stack());
+        assert!(f.eval("drop").is_ok());
+
+        assert!(f
+            .eval(": name s\" Forth\" ; name type name swap drop")
+            .is_ok());
+        assert_eq!("Forth", out.text());
+        assert_eq!(vec![5], f.stack());
+    }
+
+    #[test]
+    fn strings_are_kept_out_of_memory() {
+        let (mut f, out) = capturing();
+
+        assert!(f
+            .eval("create table 1 , : hi .\" hi\" ; 2 , table @ table 1 + @ here")
+            .is_ok());
+        assert_eq!(vec![1, 2, 2], f.stack());
+
+        assert!(f.eval("variable x -1 allot 0 , 0 , hi").is_ok());
+        assert_eq!("hi", out.text());
+    }
+
+    #[test]
+    fn interpreted_strings_reuse_a_buffer() {
+        let (mut f, out) = capturing();
+
+        assert!(f
+            .eval("here s\" first\" s\" second\" type type here")
+            .is_ok());
+        assert_eq!("secondsecon", out.text());
+        assert_eq!(vec![0, 0], f.stack());
+
+        // the longer string before is gone
+        assert!(f.eval("s\" long\" s\" ab\" drop 4 type").is_err());
+        assert_eq!("", out.text());
+    }
+
+    #[test]
+    fn unfinished_strings() {
+        let (mut f, _) = capturing();
+
+        assert_eq!(Err(ErrorKind::InvalidWord), kind(f.eval(".\" oops")));
+        assert_eq!(
+            Err(ErrorKind::InvalidWord),
+            kind(f.eval(": oops s\" oops ;"))
+        );
+        assert_eq!(Err(ErrorKind::UnknownWord), kind(f.eval("oops")));
+    }
+
+    #[test]
+    fn type_checks_addresses() {
+        let (mut f, out) = capturing();
+
+        assert!(f.eval("5 0 type").is_ok());
+        assert_eq!(Err(ErrorKind::InvalidAddress), kind(f.eval("0 1 type")));
+        assert!(f.eval("s\" ab\" 1 - swap 1 + swap type").is_ok());
+        assert_eq!("b", out.text());
+        assert_eq!(Err(ErrorKind::InvalidAddress), kind(f.eval("0 3 type")));
+        assert_eq!(Err(ErrorKind::InvalidAddress), kind(f.eval("0 -1 type")));
+        assert_eq!(Err(ErrorKind::InvalidAddress), kind(f.eval("-1 2 type")));
+    }
+
+    #[test]
+    fn include_file_with_spaces() {
+        let dir = std::env::temp_dir().join(format!("forth include {}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("two words.fs");
+        fs::write(&path, ": two 2 ; two").unwrap();
+
+        let mut f = Forth::new();
+        assert!(f.include_file(&path).is_ok());
+        assert!(f.eval("two").is_ok());
+        assert_eq!(vec![2, 2], f.stack());
+        assert_eq!(
+            Err(ErrorKind::Io(io::ErrorKind::NotFound)),
+            kind(f.include_file(&dir.join("missing.fs")))
+        );
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn include_loops() {
+        let dir = std::env::temp_dir().join(format!("forth-include-loops-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let a = dir.join("a.fs");
+        let b = dir.join("b.fs");
+        fs::write(&a, format!("1 include {}", b.display())).unwrap();
+        fs::write(&b, format!("2 include {}", a.display())).unwrap();
+        let once = dir.join("once.fs");
+        fs::write(&once, "3").unwrap();
+
+        let mut f = Forth::new();
+        let err = f.eval(&format!("include {}", a.display())).unwrap_err();
+        assert_eq!(err, ErrorKind::InvalidWord);
+        assert_eq!(vec![1, 2], f.stack());
+
+        // the same file twice in a row is no loop
+        let mut f = Forth::new();
+        assert!(f
+            .eval(&format!("include {0} include {0}", once.display()))
+            .is_ok());
+        assert_eq!(vec![3, 3], f.stack());
+
+        // a chain of different files that is too long
+        for n in 0..=INCLUDE_DEPTH {
+            let next = dir.join(format!("{}.fs", n + 1));
+            fs::write(
+                dir.join(format!("{}.fs", n)),
+                format!("include {}", next.display()),
+            )
+            .unwrap();
+        }
+        fs::write(dir.join(format!("{}.fs", INCLUDE_DEPTH + 1)), "4").unwrap();
+        let mut f = Forth::new();
+        let err = f
+            .eval(&format!("include {}", dir.join("1.fs").display()))
+            .unwrap_err();
+        assert_eq!(err, ErrorKind::InvalidWord);
+        assert!(f
+            .eval(&format!("include {}", dir.join("2.fs").display()))
+            .is_ok());
+        assert_eq!(vec![4], f.stack());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    // Tracing
+
+    #[test]
+    fn trace_instructions() {
+        let tracer = RecordingTracer::new();
+        let mut f = Forth::new();
+        f.set_tracer(tracer.clone());
+
+        assert!(f.eval(": inc 1 + ; 2 inc").is_ok());
+        assert_eq!(
+            vec![
+                Event::Defined {
+                    name: "inc".to_string(),
+                    size: 3
+                },
+                Event::Executed {
+                    instruction: Instruction::Push(2),
+                    before: vec![],
+                    after: vec![2]
+                },
+                Event::Executed {
+                    instruction: Instruction::Push(1),
+                    before: vec![2],
+                    after: vec![2, 1]
+                },
+                Event::Executed {
+                    instruction: Instruction::Op(Operation::Add),
+                    before: vec![2, 1],
+                    after: vec![3]
+                },
+                Event::Executed {
+                    instruction: Instruction::Return,
+                    before: vec![3],
+                    after: vec![3]
+                },
+            ],
+            tracer.events()
+        );
+    }
+
+    #[test]
+    fn trace_failures_and_values() {
+        let tracer = RecordingTracer::new();
+        let mut f = Forth::new();
+        f.set_tracer(tracer.clone());
+
+        assert_eq!(Err(ErrorKind::StackUnderflow), kind(f.eval("1 drop drop")));
+        assert_eq!(
+            Some(&Event::Executed {
+                instruction: Instruction::Op(Operation::Drop),
+                before: vec![],
+                after: vec![]
+            }),
+            tracer.events().last()
+        );
+
+        tracer.clear();
+        assert!(f.eval("5 constant five variable v").is_ok());
+        let defined: Vec<Event> = tracer
+            .events()
+            .into_iter()
+            .filter(|event| matches!(event, Event::Defined { .. }))
+            .collect();
+        assert_eq!(
+            vec![
+                Event::Defined {
+                    name: "five".to_string(),
+                    size: 2
+                },
+                Event::Defined {
+                    name: "v".to_string(),
+                    size: 2
+                },
+            ],
+            defined
+        );
+    }
+
+    #[test]
+    fn no_tracer_by_default() {
+        let tracer = RecordingTracer::new();
+        let mut f = Forth::new();
+        f.set_tracer(tracer.clone());
+        assert!(f.take_tracer().is_some());
+
+        assert!(f.eval("1 2 +").is_ok());
+        assert!(tracer.events().is_empty());
+        assert!(Forth::new().take_tracer().is_none());
+    }
 }
@@ -0,0 +1,152 @@
+//! What went wrong, and where.
+
+use std::error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use super::Position;
+
+/// The kinds of errors, to match on.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorKind {
+    DivisionByZero,
+    StackUnderflow,
+    UnknownWord,
+    InvalidWord,
+    ReturnStackUnderflow,
+    ReturnStackOverflow,
+    InvalidAddress,
+    OutOfMemory,
+    /// A file to `include` couldn't be read, or the output couldn't be
+    /// written.
+    Io(io::ErrorKind),
+}
+
+/// An error and what the interpreter was doing when it happened.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Error {
+    pub kind: ErrorKind,
+    /// The word that failed, as it was written.
+    pub word: Option<String>,
+    /// Where the input stopped: at the failing word, or at the word run
+    /// from the input that called it.
+    pub position: Option<Position>,
+    /// The included file the position is in, none for the input given to
+    /// `eval`.
+    pub file: Option<PathBuf>,
+    /// The words defined with `:` and the like that were running, the
+    /// outermost first.
+    pub calls: Vec<String>,
+}
+
+impl From<ErrorKind> for Error {
+    fn from(kind: ErrorKind) -> Error {
+        Error {
+            kind,
+            word: None,
+            position: None,
+            file: None,
+            calls: Vec::new(),
+        }
+    }
+}
+
+/// Errors compare equal to their kind, so `err == ErrorKind::UnknownWord`
+/// works whatever the context.
+impl PartialEq<ErrorKind> for Error {
+    fn eq(&self, kind: &ErrorKind) -> bool {
+        self.kind == *kind
+    }
+}
+
+impl fmt::Display for ErrorKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ErrorKind::DivisionByZero => write!(f, "division by zero"),
+            ErrorKind::StackUnderflow => write!(f, "stack underflow"),
+            ErrorKind::UnknownWord => write!(f, "unknown word"),
+            ErrorKind::InvalidWord => write!(f, "invalid word"),
+            ErrorKind::ReturnStackUnderflow => write!(f, "return stack underflow"),
+            ErrorKind::ReturnStackOverflow => write!(f, "return stack overflow"),
+            ErrorKind::InvalidAddress => write!(f, "invalid address"),
+            ErrorKind::OutOfMemory => write!(f, "out of memory"),
+            ErrorKind::Io(kind) => write!(f, "{}", io::Error::from(*kind)),
+        }
+    }
+}
+
+/// Written like `lib.fs:2:5: foo: stack underflow (in bar → foo)`. A word calling
+/// itself shows up once with a count, `(in countdown × 100)`.
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(file) = &self.file {
+            write!(f, "{}:", file.display())?;
+        }
+        if let Some(position) = self.position {
+            write!(f, "{}: ", position)?;
+        }
+        if let Some(word) = &self.word {
+            write!(f, "{}: ", word)?;
+        }
+        write!(f, "{}", self.kind)?;
+
+        let mut calls = self.calls.iter().peekable();
+        if calls.peek().is_some() {
+            write!(f, " (in ")?;
+        }
+        let mut first = true;
+        while let Some(call) = calls.next() {
+            let mut times = 1;
+            while calls.next_if_eq(&call).is_some() {
+                times += 1;
+            }
+            if !first {
+                write!(f, " → ")?;
+            }
+            first = false;
+            write!(f, "{}", call)?;
+            if times > 1 {
+                write!(f, " × {}", times)?;
+            }
+        }
+        if !first {
+            write!(f, ")")?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for Error {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display() {
+        let mut err = Error::from(ErrorKind::UnknownWord);
+        assert_eq!("unknown word", err.to_string());
+
+        err.word = Some("Foo".to_string());
+        err.position = Some(Position { line: 2, column: 5 });
+        assert_eq!("2:5: Foo: unknown word", err.to_string());
+
+        err.calls = ["main", "loop", "loop", "loop", "step"]
+            .map(String::from)
+            .to_vec();
+        assert_eq!(
+            "2:5: Foo: unknown word (in main → loop × 3 → step)",
+            err.to_string()
+        );
+        err.file = Some(PathBuf::from("lib/a.fs"));
+        assert_eq!(
+            "lib/a.fs:2:5: Foo: unknown word (in main → loop × 3 → step)",
+            err.to_string()
+        );
+        assert_eq!(
+            "entity not found",
+            Error::from(ErrorKind::Io(io::ErrorKind::NotFound)).to_string()
+        );
+    }
+}
@@ -0,0 +1,183 @@
+//! Reading source text, mostly word by word.
+
+use std::fmt;
+
+/// Where something is in the source text. Lines and columns count from 1,
+/// columns in characters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Position {
+    pub line: usize,
+    pub column: usize,
+}
+
+impl Default for Position {
+    fn default() -> Position {
+        Position { line: 1, column: 1 }
+    }
+}
+
+impl fmt::Display for Position {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.line, self.column)
+    }
+}
+
+/// A word of the source text and where it starts.
+#[derive(Debug, Clone, PartialEq)]
+pub(super) struct Token {
+    pub text: String,
+    pub position: Position,
+}
+
+/// Source text and how much of it has been read.
+#[derive(Debug, Default)]
+pub(super) struct Input {
+    chars: Vec<char>,
+    next: usize,
+    /// Position of the next character.
+    position: Position,
+    /// The last word read.
+    last: Option<Token>,
+}
+
+impl Input {
+    pub fn new(text: &str) -> Input {
+        Input {
+            chars: text.chars().collect(),
+            ..Input::default()
+        }
+    }
+
+    /// Reads the next word, which ends at whitespace, skipping comments:
+    /// `( ... )` and `\` up to the end of the line. The whitespace character
+    /// right after the word is read too, so text read after a word like
+    /// `."` starts with its first character.
+    pub fn word(&mut self) -> Option<Token> {
+        loop {
+            while self.peek()?.is_whitespace() {
+                self.advance();
+            }
+            let position = self.position;
+            let mut text = String::new();
+            while let Some(c) = self.peek().filter(|c| !c.is_whitespace()) {
+                text.push(c);
+                self.advance();
+            }
+            let end = self.peek();
+            self.advance();
+
+            match text.as_str() {
+                // a comment missing its end goes on to the end of the text
+                "(" => {
+                    self.until(')');
+                }
+                // the line may have ended right after the `\`
+                "\\" if end != Some('\n') => {
+                    self.until('\n');
+                }
+                "\\" => {}
+                _ => {
+                    let token = Token { text, position };
+                    self.last = Some(token.clone());
+                    return Some(token);
+                }
+            }
+        }
+    }
+
+    /// Reads the text up to `end`, and `end` itself, which isn't part of the
+    /// text. The rest of the text is read when there's no `end`.
+    pub fn until(&mut self, end: char) -> Option<String> {
+        let mut text = String::new();
+        while let Some(c) = self.peek() {
+            self.advance();
+            if c == end {
+                return Some(text);
+            }
+            text.push(c);
+        }
+        None
+    }
+
+    /// The last word read.
+    pub fn last(&self) -> Option<&Token> {
+        self.last.as_ref()
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.next).copied()
+    }
+
+    fn advance(&mut self) {
+        match self.peek() {
+            Some('\n') => {
+                self.position.line += 1;
+                self.position.column = 1;
+            }
+            Some(_) => self.position.column += 1,
+            None => return,
+        }
+        self.next += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn words(input: &mut Input) -> Vec<(String, usize, usize)> {
+        std::iter::from_fn(|| input.word())
+            .map(|token| (token.text, token.position.line, token.position.column))
+            .collect()
+    }
+
+    fn word(text: &str, line: usize, column: usize) -> (String, usize, usize) {
+        (text.to_string(), line, column)
+    }
+
+    #[test]
+    fn any_whitespace() {
+        assert_eq!(
+            vec![word("1", 1, 2), word("Dup", 1, 6), word("*", 2, 3)],
+            words(&mut Input::new(" 1 \t Dup\r\n\t *  \n"))
+        );
+        assert!(words(&mut Input::new(" \n\t ")).is_empty());
+    }
+
+    #[test]
+    fn comments() {
+        let mut input = Input::new(": sq ( n -- n*n )\n  dup \\ copy it ( no end\n\t(x) *");
+        assert_eq!(
+            vec![
+                word(":", 1, 1),
+                word("sq", 1, 3),
+                word("dup", 2, 3),
+                word("(x)", 3, 2),
+                word("*", 3, 6)
+            ],
+            words(&mut input)
+        );
+        assert_eq!(
+            vec![word("1", 1, 1)],
+            words(&mut Input::new("1 ( never ends"))
+        );
+        assert_eq!(vec![word("2", 2, 1)], words(&mut Input::new("\\ 1\n2")));
+        assert_eq!(
+            vec![word("1", 1, 1), word("2", 2, 1), word("3", 2, 3)],
+            words(&mut Input::new("1 \\\n2 3"))
+        );
+    }
+
+    #[test]
+    fn text_until() {
+        let mut input = Input::new(".\" a  b\" c \"d");
+        assert_eq!(".\"", input.word().unwrap().text);
+        assert_eq!(Some("a  b".to_string()), input.until('"'));
+        assert_eq!(
+            vec![word("c", 1, 10), word("\"d", 1, 12)],
+            words(&mut input)
+        );
+        assert_eq!(Some("\"d"), input.last().map(|token| token.text.as_str()));
+        assert_eq!(None, input.until('"'));
+    }
+}
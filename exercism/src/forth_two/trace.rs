@@ -0,0 +1,71 @@
+//! Watching the interpreter work. A [`Tracer`] set on a
+//! [`Forth`](super::Forth) is told about every instruction it runs and every
+//! word it defines.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use super::{Instruction, Value};
+
+/// Something the interpreter did.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    /// A word was defined with `size` instructions of code.
+    Defined { name: String, size: usize },
+    /// An instruction ran, changing the stack from `before` to `after`. When
+    /// it failed, `after` is the stack it left behind.
+    Executed {
+        instruction: Instruction,
+        before: Vec<Value>,
+        after: Vec<Value>,
+    },
+}
+
+pub trait Tracer {
+    fn trace(&mut self, event: &Event);
+}
+
+/// Prints each event on its own line.
+#[derive(Debug, Default)]
+pub struct StdoutTracer;
+
+impl Tracer for StdoutTracer {
+    fn trace(&mut self, event: &Event) {
+        match event {
+            Event::Defined { name, size } => println!("[*] : {} ({} instructions)", name, size),
+            Event::Executed {
+                instruction,
+                before,
+                after,
+            } => println!("[*] {:?} {:?} -> {:?}", instruction, before, after),
+        }
+    }
+}
+
+/// Keeps the events. Clones share them, so one clone can be given to the
+/// interpreter and the other one read afterwards.
+#[derive(Debug, Default, Clone)]
+pub struct RecordingTracer {
+    events: Rc<RefCell<Vec<Event>>>,
+}
+
+impl RecordingTracer {
+    pub fn new() -> RecordingTracer {
+        RecordingTracer::default()
+    }
+
+    /// The events so far, oldest first.
+    pub fn events(&self) -> Vec<Event> {
+        self.events.borrow().clone()
+    }
+
+    pub fn clear(&self) {
+        self.events.borrow_mut().clear();
+    }
+}
+
+impl Tracer for RecordingTracer {
+    fn trace(&mut self, event: &Event) {
+        self.events.borrow_mut().push(event.clone());
+    }
+}
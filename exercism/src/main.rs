@@ -4,7 +4,6 @@ mod anagram;
 mod clock;
 mod fibonacci;
 mod forth;
-mod forth_two;
 mod gigasecond;
 mod health_statistics;
 mod low_power_game;
@@ -34,11 +33,6 @@ mod alphametic;
 mod alphametics2;
 
 fn main() {
-    let mut f = forth_two::Forth::new();
-
-    f.eval(": fib over over + ;").unwrap();
-    println!("{:?}", f.eval("1 1 loop 10 : fib ;"));
-    println!("{:?}", f.stack());
     // println!("{:?}", resistor_color::color_to_value(resistor_color::ResistorColor::Green));
     // println!("Hello, world!");
 }
@@ -0,0 +1,193 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::formula::ParseError;
+use crate::reference::CellRef;
+use crate::sheet::{Cell, Sheet};
+
+/// What [`Sheet::to_csv`] writes for formula cells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CsvExport {
+    /// The cell input, `=A1*2` for formulas, so the file can be loaded again.
+    Formulas,
+    /// The calculated value of every cell.
+    Values,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CsvError {
+    /// A quoted field that is still open at the end of the input. `line` is
+    /// the one based line the field starts on.
+    UnterminatedQuote { line: usize },
+    /// A field starting with `=` that isn't a valid formula.
+    Formula { cell: CellRef, error: ParseError },
+}
+
+impl fmt::Display for CsvError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            CsvError::UnterminatedQuote { line } => {
+                write!(f, "line {}: unterminated quoted field", line)
+            }
+            CsvError::Formula { cell, error } => write!(f, "cell {}: {}", cell, error),
+        }
+    }
+}
+
+impl Error for CsvError {}
+
+impl Sheet {
+    /// Loads a sheet from CSV, one record per row. Fields starting with `=`
+    /// are formulas, other fields are read as literals and empty fields
+    /// leave the cell empty.
+    pub fn from_csv(input: &str) -> Result<Sheet, CsvError> {
+        let mut sheet = Sheet::new();
+        for (row, record) in parse_records(input)?.into_iter().enumerate() {
+            for (col, field) in record.into_iter().enumerate() {
+                if field.is_empty() {
+                    continue;
+                }
+                let at = CellRef::new(row, col);
+                let cell =
+                    Cell::parse(&field).map_err(|error| CsvError::Formula { cell: at, error })?;
+                sheet.set(at, cell);
+            }
+        }
+        Ok(sheet)
+    }
+
+    /// Writes the sheet as CSV covering every row and column up to the last
+    /// non-empty cell.
+    pub fn to_csv(&mut self, export: CsvExport) -> String {
+        let (rows, cols) = self.dimensions();
+        let mut output = String::new();
+        for row in 0..rows {
+            let fields: Vec<String> = (0..cols)
+                .map(|col| {
+                    let at = CellRef::new(row, col);
+                    let field = match export {
+                        CsvExport::Formulas => self
+                            .get(at)
+                            .map(|cell| cell.to_string())
+                            .unwrap_or_default(),
+                        CsvExport::Values => self.calculate(at).to_string(),
+                    };
+                    quote(&field)
+                })
+                .collect();
+            output.push_str(&fields.join(","));
+            output.push('\n');
+        }
+        output
+    }
+}
+
+fn quote(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Splits CSV input into records of unquoted fields (RFC 4180, accepting
+/// both `\n` and `\r\n` line endings).
+fn parse_records(input: &str) -> Result<Vec<Vec<String>>, CsvError> {
+    let mut records = Vec::new();
+    let mut record = Vec::new();
+    let mut field = String::new();
+    let mut chars = input.chars().peekable();
+    let mut line = 1;
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if field.is_empty() => {
+                let start = line;
+                loop {
+                    match chars.next() {
+                        None => return Err(CsvError::UnterminatedQuote { line: start }),
+                        Some('"') if chars.peek() == Some(&'"') => {
+                            chars.next();
+                            field.push('"');
+                        }
+                        Some('"') => break,
+                        Some(c) => {
+                            if c == '\n' {
+                                line += 1;
+                            }
+                            field.push(c);
+                        }
+                    }
+                }
+            }
+            ',' => record.push(std::mem::take(&mut field)),
+            '\r' if chars.peek() == Some(&'\n') => {}
+            '\n' => {
+                record.push(std::mem::take(&mut field));
+                records.push(std::mem::take(&mut record));
+                line += 1;
+            }
+            c => field.push(c),
+        }
+    }
+    if !field.is_empty() || !record.is_empty() {
+        record.push(field);
+        records.push(record);
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value::Value;
+
+    #[test]
+    fn import() {
+        let mut sheet = Sheet::from_csv("1,2,=A1+B1\r\n\"a,\"\"b\"\"\",,TRUE\n").unwrap();
+
+        assert_eq!(sheet.calculate(CellRef::new(0, 2)), Value::Number(3.0));
+        assert_eq!(sheet.calculate(CellRef::new(1, 0)), Value::from("a,\"b\""));
+        assert_eq!(sheet.get(CellRef::new(1, 1)), None);
+        assert_eq!(sheet.calculate(CellRef::new(1, 2)), Value::Bool(true));
+    }
+
+    #[test]
+    fn import_errors() {
+        assert_eq!(
+            Sheet::from_csv("1\n\"open\n,").err(),
+            Some(CsvError::UnterminatedQuote { line: 2 })
+        );
+        let error = Sheet::from_csv("1,=A1+\n").err().unwrap();
+        assert_eq!(
+            error.to_string(),
+            "cell B1: column 4: unexpected end of formula"
+        );
+    }
+
+    #[test]
+    fn export() {
+        let mut sheet = Sheet::from_csv("2,,=A1*3\n\"x,y\",=1/0\n").unwrap();
+
+        assert_eq!(
+            sheet.to_csv(CsvExport::Formulas),
+            "2,,=A1*3\n\"x,y\",=1/0,\n"
+        );
+        assert_eq!(sheet.to_csv(CsvExport::Values), "2,,6\n\"x,y\",#DIV/0!,\n");
+    }
+
+    #[test]
+    fn round_trip() {
+        let input = "1,\"\"\"12\"\"\",=A1-(B1-2)\n";
+        let mut sheet = Sheet::from_csv(input).unwrap();
+        let exported = sheet.to_csv(CsvExport::Formulas);
+
+        assert_eq!(exported, input);
+        assert_eq!(
+            Sheet::from_csv(&exported)
+                .unwrap()
+                .to_csv(CsvExport::Formulas),
+            input
+        );
+    }
+}
@@ -0,0 +1,267 @@
+//! Exact decimal numbers, for sheets where floating point rounding isn't
+//! acceptable, see [`Arithmetic`].
+
+use std::cmp::Ordering;
+use std::fmt;
+use std::str::FromStr;
+
+use bigdecimal::num_bigint::{BigInt, Sign};
+use bigdecimal::{BigDecimal, Signed, ToPrimitive, Zero};
+
+/// How a workbook calculates numbers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Arithmetic {
+    /// 64 bit floating point, like most spreadsheets.
+    #[default]
+    Float,
+    /// Exact decimals up to [`MAX_EXPONENT`] digits. Sums, differences and
+    /// products are exact, quotients are rounded to `scale` decimal places.
+    Decimal { scale: u32, rounding: Rounding },
+}
+
+/// Which way to round a quotient that doesn't fit in the decimal places.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Rounding {
+    /// To the nearest, ties to the even neighbour: 2.5 → 2, 3.5 → 4.
+    #[default]
+    HalfEven,
+    /// To the nearest, ties away from zero: 2.5 → 3, -2.5 → -3.
+    HalfUp,
+    /// To the nearest, ties towards zero: 2.5 → 2, -2.5 → -2.
+    HalfDown,
+    /// Away from zero.
+    Up,
+    /// Towards zero, cutting off the digits.
+    Down,
+    /// Towards positive infinity.
+    Ceiling,
+    /// Towards negative infinity.
+    Floor,
+}
+
+/// How far decimals reach: at most `MAX_EXPONENT` digits before and after
+/// the point. Typed numbers past that don't read as decimals, and sums,
+/// differences, products and quotients past it aren't worked out. Without a
+/// limit, `1e999999999` or squaring a number a few dozen times would take
+/// gigabytes to calculate with.
+pub const MAX_EXPONENT: u32 = 10_000;
+
+/// A decimal number of any size and precision.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Decimal(BigDecimal);
+
+impl Decimal {
+    /// The decimal a floating point number was written as: the shortest one
+    /// that reads back as the same number, so `0.1` is exactly 0.1.
+    pub fn from_f64(n: f64) -> Option<Decimal> {
+        if !n.is_finite() {
+            return None;
+        }
+        n.to_string().parse().ok()
+    }
+
+    /// The closest floating point number.
+    pub fn to_f64(&self) -> f64 {
+        self.0.to_f64().unwrap_or(f64::NAN)
+    }
+
+    pub fn is_zero(&self) -> bool {
+        self.0.is_zero()
+    }
+
+    /// `self + rhs`, `None` past [`MAX_EXPONENT`].
+    pub fn checked_add(&self, rhs: &Decimal) -> Option<Decimal> {
+        Decimal::bounded(&self.0 + &rhs.0)
+    }
+
+    /// `self - rhs`, `None` past [`MAX_EXPONENT`].
+    pub fn checked_sub(&self, rhs: &Decimal) -> Option<Decimal> {
+        Decimal::bounded(&self.0 - &rhs.0)
+    }
+
+    /// `self * rhs`, `None` past [`MAX_EXPONENT`].
+    pub fn checked_mul(&self, rhs: &Decimal) -> Option<Decimal> {
+        Decimal::bounded(&self.0 * &rhs.0)
+    }
+
+    /// `self / rhs` rounded to `scale` decimal places, `None` for a zero
+    /// divisor, past [`MAX_EXPONENT`] or when the digits of the two numbers
+    /// are further apart than that. The quotient is worked out with integers, so it is
+    /// rounded only once.
+    pub fn div(&self, rhs: &Decimal, scale: u32, rounding: Rounding) -> Option<Decimal> {
+        if rhs.is_zero() {
+            return None;
+        }
+        // a / b = (ma / 10^sa) / (mb / 10^sb), times 10^scale to keep the
+        // decimal places in the integer quotient
+        let (ma, sa) = self.0.as_bigint_and_exponent();
+        let (mb, sb) = rhs.0.as_bigint_and_exponent();
+        let shift = scale as i64 + sb - sa;
+        if shift.unsigned_abs() > MAX_EXPONENT as u64 {
+            return None;
+        }
+        let ten = BigInt::from(10);
+        let (numerator, denominator) = if shift >= 0 {
+            (ma * ten.pow(shift as u32), mb)
+        } else {
+            (ma, mb * ten.pow(shift.unsigned_abs() as u32))
+        };
+
+        let quotient = &numerator / &denominator;
+        let remainder = &numerator % &denominator;
+        let negative = (numerator.sign() == Sign::Minus) != (denominator.sign() == Sign::Minus);
+        let away = if remainder.is_zero() {
+            false
+        } else {
+            let half = (remainder.abs() * 2u8).cmp(&denominator.abs());
+            match rounding {
+                Rounding::Up => true,
+                Rounding::Down => false,
+                Rounding::Ceiling => !negative,
+                Rounding::Floor => negative,
+                Rounding::HalfUp => half != Ordering::Less,
+                Rounding::HalfDown => half == Ordering::Greater,
+                Rounding::HalfEven => {
+                    half == Ordering::Greater
+                        || (half == Ordering::Equal && (&quotient % 2u8) != BigInt::zero())
+                }
+            }
+        };
+        let quotient = match (away, negative) {
+            (false, _) => quotient,
+            (true, false) => quotient + 1,
+            (true, true) => quotient - 1,
+        };
+        Decimal::bounded(BigDecimal::new(quotient, scale as i64))
+    }
+
+    /// The decimal, unless it has more than [`MAX_EXPONENT`] digits before
+    /// or after the point.
+    fn bounded(decimal: BigDecimal) -> Option<Decimal> {
+        let (_, scale) = decimal.as_bigint_and_exponent();
+        let integer_digits = decimal.digits() as i64 - scale;
+        let max = MAX_EXPONENT as i64;
+        (scale <= max && integer_digits <= max).then_some(Decimal(decimal))
+    }
+}
+
+impl From<i64> for Decimal {
+    fn from(n: i64) -> Decimal {
+        Decimal(BigDecimal::from(n))
+    }
+}
+
+/// Reads plain decimals, `-12.50`, as well as exponents, `1.5e3`, up to
+/// [`MAX_EXPONENT`].
+impl FromStr for Decimal {
+    type Err = bigdecimal::ParseBigDecimalError;
+
+    fn from_str(text: &str) -> Result<Decimal, Self::Err> {
+        let decimal = BigDecimal::from_str(text.trim())?;
+        Decimal::bounded(decimal).ok_or_else(|| {
+            bigdecimal::ParseBigDecimalError::Other(format!(
+                "exponent out of range: {}",
+                text.trim()
+            ))
+        })
+    }
+}
+
+/// Writes all the digits, without an exponent or trailing zeros.
+impl fmt::Display for Decimal {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.is_zero() {
+            return write!(f, "0");
+        }
+        write!(f, "{}", self.0.normalized().to_plain_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn decimal(text: &str) -> Decimal {
+        text.parse().unwrap()
+    }
+
+    fn div(a: &str, b: &str, scale: u32, rounding: Rounding) -> String {
+        decimal(a)
+            .div(&decimal(b), scale, rounding)
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn exact_arithmetic() {
+        let add = decimal("0.1").checked_add(&decimal("0.2")).unwrap();
+        assert_eq!(add.to_string(), "0.3");
+        let mul = decimal("12345678901234567890.5").checked_mul(&decimal("2"));
+        assert_eq!(mul.unwrap().to_string(), "24691357802469135781");
+        let sub = decimal("1.10").checked_sub(&decimal("1.1")).unwrap();
+        assert_eq!(sub.to_string(), "0");
+        assert_eq!(Decimal::from_f64(0.1), Some(decimal("0.1")));
+        assert_eq!(
+            Decimal::from_f64(1e21).unwrap().to_string(),
+            "1000000000000000000000"
+        );
+        assert_eq!(
+            decimal("0.1000000000000000055").to_string(),
+            "0.1000000000000000055"
+        );
+    }
+
+    #[test]
+    fn rounded_quotients() {
+        assert_eq!(div("1", "3", 4, Rounding::HalfEven), "0.3333");
+        assert_eq!(div("2", "3", 4, Rounding::HalfEven), "0.6667");
+        assert_eq!(div("2", "3", 4, Rounding::Down), "0.6666");
+        assert_eq!(div("-2", "3", 4, Rounding::Floor), "-0.6667");
+        assert_eq!(div("-2", "3", 4, Rounding::Ceiling), "-0.6666");
+        assert_eq!(div("1", "-3", 0, Rounding::Up), "-1");
+        assert_eq!(div("1E3", "0.4", 2, Rounding::Down), "2500");
+        assert!(decimal("1")
+            .div(&decimal("0"), 2, Rounding::HalfEven)
+            .is_none());
+    }
+
+    #[test]
+    fn exponents_are_bounded() {
+        assert!("1e999999999".parse::<Decimal>().is_err());
+        assert!("1e-999999999".parse::<Decimal>().is_err());
+        assert!("1e10000".parse::<Decimal>().is_err());
+        assert_eq!(decimal("1e9999").to_string().len(), 10_000);
+
+        let big = decimal("1e9999");
+        let small = decimal("1e-10000");
+        assert!(small.div(&big, 0, Rounding::HalfEven).is_none());
+        assert_eq!(div("1e-10000", "1e-10000", 2, Rounding::HalfEven), "1");
+        assert!(big.checked_mul(&decimal("10")).is_none());
+        assert!(big.checked_add(&big).is_some());
+        assert!(small.checked_mul(&decimal("0.1")).is_none());
+    }
+
+    #[test]
+    fn ties() {
+        for (rounding, results) in [
+            (Rounding::HalfEven, ["2", "4", "-2"]),
+            (Rounding::HalfUp, ["3", "4", "-3"]),
+            (Rounding::HalfDown, ["2", "3", "-2"]),
+        ] {
+            for ((a, b), result) in [("5", "2"), ("7", "2"), ("-5", "2")]
+                .into_iter()
+                .zip(results)
+            {
+                assert_eq!(
+                    div(a, b, 0, rounding),
+                    result,
+                    "{} / {} {:?}",
+                    a,
+                    b,
+                    rounding
+                );
+            }
+        }
+        assert_eq!(div("0.125", "1", 2, Rounding::HalfEven), "0.12");
+    }
+}
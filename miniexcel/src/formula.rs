@@ -0,0 +1,745 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::function::Function;
+use crate::reference::{same_name, CellRef, Range, Shift};
+use crate::value::{CellError, Value};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Add,
+    Sub,
+    Mult,
+    Div,
+}
+
+impl Operation {
+    fn precedence(&self) -> u8 {
+        match self {
+            Operation::Add | Operation::Sub => 1,
+            Operation::Mult | Operation::Div => 2,
+        }
+    }
+
+    fn symbol(&self) -> char {
+        match self {
+            Operation::Add => '+',
+            Operation::Sub => '-',
+            Operation::Mult => '*',
+            Operation::Div => '/',
+        }
+    }
+}
+
+/// Parsed formula, the part of a cell's input after the `=`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Literal(Value),
+    Ref(CellRef),
+    /// A range, or any reference to another sheet: `A1:B3`, `Sheet2!B3`.
+    /// `sheet` is `None` for the formula's own sheet.
+    Range {
+        sheet: Option<String>,
+        range: Range,
+    },
+    /// A defined name, `TaxRate`, resolved when the formula is calculated.
+    Name(String),
+    Neg(Box<Expr>),
+    Binary(Operation, Box<Expr>, Box<Expr>),
+    Call(Function, Vec<Expr>),
+}
+
+impl Expr {
+    pub fn binary(operation: Operation, lhs: Expr, rhs: Expr) -> Expr {
+        Expr::Binary(operation, Box::new(lhs), Box::new(rhs))
+    }
+
+    pub fn parse(input: &str) -> Result<Expr, ParseError> {
+        let mut parser = Parser {
+            tokens: tokenize(input)?,
+            pos: 0,
+            end: input.chars().count(),
+        };
+        let expr = parser.expr()?;
+        match parser.peek() {
+            None => Ok(expr),
+            Some((column, _)) => Err(ParseError::new(column, "unexpected input")),
+        }
+    }
+
+    /// Every reference in the formula, in order of appearance, with the
+    /// sheet it points to unless that is the formula's own.
+    pub fn references(&self) -> Vec<(Option<&str>, Range)> {
+        let mut references = Vec::new();
+        self.collect_references(&mut references);
+        references
+    }
+
+    fn collect_references<'a>(&'a self, references: &mut Vec<(Option<&'a str>, Range)>) {
+        self.visit(&mut |expr| match expr {
+            Expr::Ref(at) => references.push((None, (*at).into())),
+            Expr::Range { sheet, range } => references.push((sheet.as_deref(), *range)),
+            _ => {}
+        });
+    }
+
+    /// Every defined name the formula uses, in order of appearance. What
+    /// they stand for depends on where the formula is calculated.
+    pub fn names(&self) -> Vec<&str> {
+        let mut names = Vec::new();
+        self.visit(&mut |expr| {
+            if let Expr::Name(name) = expr {
+                names.push(name.as_str());
+            }
+        });
+        names
+    }
+
+    /// Calls `f` with every part of the formula, outermost first.
+    fn visit<'a>(&'a self, f: &mut dyn FnMut(&'a Expr)) {
+        f(self);
+        match self {
+            Expr::Neg(expr) => expr.visit(f),
+            Expr::Binary(_, lhs, rhs) => {
+                lhs.visit(f);
+                rhs.visit(f);
+            }
+            Expr::Call(_, args) => {
+                for arg in args {
+                    arg.visit(f);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// The formula after rows or columns of its own sheet moved, references
+    /// to deleted cells become `#REF!`.
+    pub fn shifted(&self, shift: &Shift) -> Expr {
+        let deleted = || Expr::Literal(Value::Error(CellError::Ref));
+        self.rewrite(&mut |expr| match expr {
+            Expr::Ref(at) => Some(shift.cell(*at).map_or_else(deleted, Expr::Ref)),
+            Expr::Range { sheet: None, range } => Some(match shift.range(*range) {
+                Some(range) => Expr::Range { sheet: None, range },
+                None => deleted(),
+            }),
+            _ => None,
+        })
+    }
+
+    /// The formula after rows or columns of the sheet `sheet` moved, for the
+    /// references that name it. References to deleted cells become `#REF!`.
+    pub fn sheet_shifted(&self, sheet: &str, shift: &Shift) -> Expr {
+        self.rewrite(&mut |expr| match expr {
+            Expr::Range {
+                sheet: Some(name),
+                range,
+            } if same_name(name, sheet) => Some(match shift.range(*range) {
+                Some(range) => Expr::Range {
+                    sheet: Some(name.clone()),
+                    range,
+                },
+                None => Expr::Literal(Value::Error(CellError::Ref)),
+            }),
+            _ => None,
+        })
+    }
+
+    /// The formula after the sheet `from` was renamed to `to`, or deleted
+    /// when `to` is `None`, which turns references to it into `#REF!`.
+    pub fn sheet_renamed(&self, from: &str, to: Option<&str>) -> Expr {
+        self.rewrite(&mut |expr| match expr {
+            Expr::Range {
+                sheet: Some(sheet),
+                range,
+            } if same_name(sheet, from) => Some(match to {
+                Some(to) => Expr::Range {
+                    sheet: Some(to.to_string()),
+                    range: *range,
+                },
+                None => Expr::Literal(Value::Error(CellError::Ref)),
+            }),
+            _ => None,
+        })
+    }
+
+    /// The formula after the defined name `from` was renamed to `to`.
+    pub fn name_renamed(&self, from: &str, to: &str) -> Expr {
+        self.rewrite(&mut |expr| match expr {
+            Expr::Name(name) if same_name(name, from) => Some(Expr::Name(to.to_string())),
+            _ => None,
+        })
+    }
+
+    /// Copy of the formula with every part `replace` returns something for
+    /// replaced by it.
+    fn rewrite(&self, replace: &mut dyn FnMut(&Expr) -> Option<Expr>) -> Expr {
+        if let Some(expr) = replace(self) {
+            return expr;
+        }
+        match self {
+            Expr::Neg(expr) => Expr::Neg(Box::new(expr.rewrite(replace))),
+            Expr::Binary(op, lhs, rhs) => {
+                Expr::binary(*op, lhs.rewrite(replace), rhs.rewrite(replace))
+            }
+            Expr::Call(function, args) => Expr::Call(
+                *function,
+                args.iter().map(|arg| arg.rewrite(replace)).collect(),
+            ),
+            _ => self.clone(),
+        }
+    }
+
+    fn precedence(&self) -> u8 {
+        match self {
+            Expr::Binary(op, _, _) => op.precedence(),
+            _ => 3,
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Literal(Value::Text(text)) => write!(f, "\"{}\"", text.replace('"', "\"\"")),
+            Expr::Literal(value) => write!(f, "{}", value),
+            Expr::Ref(reference) => write!(f, "{}", reference),
+            Expr::Name(name) => write!(f, "{}", name),
+            Expr::Range { sheet, range } => {
+                if let Some(sheet) = sheet {
+                    write_sheet_name(f, sheet)?;
+                    write!(f, "!")?;
+                }
+                write!(f, "{}", range)
+            }
+            Expr::Call(function, args) => {
+                write!(f, "{}(", function.name())?;
+                for (i, arg) in args.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ",")?;
+                    }
+                    write!(f, "{}", arg)?;
+                }
+                write!(f, ")")
+            }
+            Expr::Neg(expr) if expr.precedence() < 3 => write!(f, "-({})", expr),
+            Expr::Neg(expr) => write!(f, "-{}", expr),
+            Expr::Binary(op, lhs, rhs) => {
+                if lhs.precedence() < op.precedence() {
+                    write!(f, "({})", lhs)?;
+                } else {
+                    write!(f, "{}", lhs)?;
+                }
+                write!(f, "{}", op.symbol())?;
+                // `a-(b-c)` and `a/(b*c)` need their parentheses, `a+(b+c)` doesn't
+                let strict = matches!(op, Operation::Sub | Operation::Div);
+                if rhs.precedence() < op.precedence()
+                    || (strict && rhs.precedence() == op.precedence())
+                {
+                    write!(f, "({})", rhs)
+                } else {
+                    write!(f, "{}", rhs)
+                }
+            }
+        }
+    }
+}
+
+/// Writes a sheet name as it has to be typed in a reference: quoted when it
+/// wouldn't read back as a plain name, `'Q1 Report'`.
+fn write_sheet_name(f: &mut fmt::Formatter, sheet: &str) -> fmt::Result {
+    let plain = sheet.starts_with(|c: char| c.is_alphabetic() || c == '_')
+        && sheet
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '.');
+    if plain {
+        write!(f, "{}", sheet)
+    } else {
+        write!(f, "'{}'", sheet.replace('\'', "''"))
+    }
+}
+
+/// Error produced when a formula can't be parsed. `column` is the zero based
+/// character offset in the formula where parsing failed.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseError {
+    pub column: usize,
+    pub message: String,
+}
+
+impl ParseError {
+    fn new(column: usize, message: &str) -> ParseError {
+        ParseError {
+            column,
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "column {}: {}", self.column + 1, self.message)
+    }
+}
+
+impl Error for ParseError {}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(Value),
+    Text(String),
+    Ident(String),
+    /// A quoted sheet name, `'Q1 Report'`.
+    Quoted(String),
+    Error(CellError),
+    Symbol(char),
+}
+
+fn tokenize(input: &str) -> Result<Vec<(usize, Token)>, ParseError> {
+    let chars: Vec<char> = input.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let start = i;
+        let c = chars[i];
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        if c.is_ascii_digit() || c == '.' {
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            // exponent, e.g. `1e-3`
+            if i < chars.len() && (chars[i] == 'e' || chars[i] == 'E') {
+                let mut j = i + 1;
+                if j < chars.len() && (chars[j] == '+' || chars[j] == '-') {
+                    j += 1;
+                }
+                if j < chars.len() && chars[j].is_ascii_digit() {
+                    i = j;
+                    while i < chars.len() && chars[i].is_ascii_digit() {
+                        i += 1;
+                    }
+                }
+            }
+            let text: String = chars[start..i].iter().collect();
+            let number = Value::parse_number(&text)
+                .ok_or_else(|| ParseError::new(start, "invalid number"))?;
+            tokens.push((start, Token::Number(number)));
+        } else if c == '"' {
+            let mut text = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => return Err(ParseError::new(start, "unterminated string")),
+                    Some('"') if chars.get(i + 1) == Some(&'"') => {
+                        text.push('"');
+                        i += 2;
+                    }
+                    Some('"') => {
+                        i += 1;
+                        break;
+                    }
+                    Some(&c) => {
+                        text.push(c);
+                        i += 1;
+                    }
+                }
+            }
+            tokens.push((start, Token::Text(text)));
+        } else if c == '\'' {
+            let mut name = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => return Err(ParseError::new(start, "unterminated sheet name")),
+                    Some('\'') if chars.get(i + 1) == Some(&'\'') => {
+                        name.push('\'');
+                        i += 2;
+                    }
+                    Some('\'') => {
+                        i += 1;
+                        break;
+                    }
+                    Some(&c) => {
+                        name.push(c);
+                        i += 1;
+                    }
+                }
+            }
+            tokens.push((start, Token::Quoted(name)));
+        } else if c.is_alphabetic() || c == '_' {
+            while i < chars.len()
+                && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '.')
+            {
+                i += 1;
+            }
+            tokens.push((start, Token::Ident(chars[start..i].iter().collect())));
+        } else if c == '#' {
+            // error literals, e.g. the `#REF!` left by a deleted reference,
+            // which end in `!` or `?`, or are `#N/A`
+            let code = |end: usize| chars[start..end].iter().collect::<String>();
+            i += 1;
+            while i < chars.len()
+                && chars[i - 1] != '!'
+                && chars[i - 1] != '?'
+                && CellError::from_code(&code(i)).is_none()
+            {
+                i += 1;
+            }
+            let err = CellError::from_code(&code(i))
+                .ok_or_else(|| ParseError::new(start, &format!("unknown error '{}'", code(i))))?;
+            tokens.push((start, Token::Error(err)));
+        } else if "+-*/(),:!".contains(c) {
+            tokens.push((start, Token::Symbol(c)));
+            i += 1;
+        } else {
+            return Err(ParseError::new(
+                start,
+                &format!("unexpected character '{}'", c),
+            ));
+        }
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<(usize, Token)>,
+    pos: usize,
+    end: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<(usize, &Token)> {
+        self.tokens
+            .get(self.pos)
+            .map(|(column, token)| (*column, token))
+    }
+
+    fn next(&mut self) -> Result<(usize, Token), ParseError> {
+        let token = self
+            .tokens
+            .get(self.pos)
+            .cloned()
+            .ok_or_else(|| ParseError::new(self.end, "unexpected end of formula"))?;
+        self.pos += 1;
+        Ok(token)
+    }
+
+    fn eat(&mut self, symbol: char) -> bool {
+        if let Some((_, Token::Symbol(c))) = self.peek() {
+            if *c == symbol {
+                self.pos += 1;
+                return true;
+            }
+        }
+        false
+    }
+
+    fn expr(&mut self) -> Result<Expr, ParseError> {
+        let mut lhs = self.term()?;
+        loop {
+            let op = if self.eat('+') {
+                Operation::Add
+            } else if self.eat('-') {
+                Operation::Sub
+            } else {
+                return Ok(lhs);
+            };
+            lhs = Expr::binary(op, lhs, self.term()?);
+        }
+    }
+
+    fn term(&mut self) -> Result<Expr, ParseError> {
+        let mut lhs = self.unary()?;
+        loop {
+            let op = if self.eat('*') {
+                Operation::Mult
+            } else if self.eat('/') {
+                Operation::Div
+            } else {
+                return Ok(lhs);
+            };
+            lhs = Expr::binary(op, lhs, self.unary()?);
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr, ParseError> {
+        if self.eat('-') {
+            Ok(Expr::Neg(Box::new(self.unary()?)))
+        } else if self.eat('+') {
+            self.unary()
+        } else {
+            self.primary()
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, ParseError> {
+        let (column, token) = self.next()?;
+        match token {
+            Token::Number(n) => Ok(Expr::Literal(n)),
+            Token::Text(text) => Ok(Expr::Literal(Value::Text(text))),
+            Token::Error(err) => Ok(Expr::Literal(Value::Error(err))),
+            Token::Ident(ident) if self.eat('!') => self.reference(Some(ident)),
+            Token::Ident(ident) if self.eat('(') => match Function::parse(&ident) {
+                Some(function) => self.call(function, column),
+                None => Err(ParseError::new(
+                    column,
+                    &format!("unknown function '{}'", ident),
+                )),
+            },
+            Token::Ident(ident) => match ident.to_uppercase().as_str() {
+                "TRUE" => Ok(Expr::Literal(Value::Bool(true))),
+                "FALSE" => Ok(Expr::Literal(Value::Bool(false))),
+                _ => match CellRef::parse(&ident) {
+                    Some(at) => self.range(None, at),
+                    None => Ok(Expr::Name(ident)),
+                },
+            },
+            Token::Quoted(sheet) if self.eat('!') => self.reference(Some(sheet)),
+            Token::Quoted(_) => Err(ParseError::new(column, "expected '!' after sheet name")),
+            Token::Symbol('(') => {
+                let expr = self.expr()?;
+                if self.eat(')') {
+                    Ok(expr)
+                } else {
+                    let column = self.peek().map_or(self.end, |(column, _)| column);
+                    Err(ParseError::new(column, "expected ')'"))
+                }
+            }
+            Token::Symbol(c) => Err(ParseError::new(column, &format!("unexpected '{}'", c))),
+        }
+    }
+
+    /// A reference after its `Sheet!` prefix.
+    fn reference(&mut self, sheet: Option<String>) -> Result<Expr, ParseError> {
+        let at = self.cell()?;
+        self.range(sheet, at)
+    }
+
+    /// The rest of a reference after its first cell, an optional `:B3`.
+    fn range(&mut self, sheet: Option<String>, start: CellRef) -> Result<Expr, ParseError> {
+        let end = if self.eat(':') { self.cell()? } else { start };
+        if sheet.is_none() && start == end {
+            return Ok(Expr::Ref(start));
+        }
+        Ok(Expr::Range {
+            sheet,
+            range: Range::new(start, end),
+        })
+    }
+
+    fn cell(&mut self) -> Result<CellRef, ParseError> {
+        let (column, token) = self.next()?;
+        match token {
+            Token::Ident(ident) => CellRef::parse(&ident),
+            _ => None,
+        }
+        .ok_or_else(|| ParseError::new(column, "expected a cell reference"))
+    }
+
+    /// Arguments of a call after its `(`.
+    fn call(&mut self, function: Function, column: usize) -> Result<Expr, ParseError> {
+        let mut args = Vec::new();
+        if !self.eat(')') {
+            loop {
+                args.push(self.expr()?);
+                if self.eat(')') {
+                    break;
+                }
+                if !self.eat(',') {
+                    let column = self.peek().map_or(self.end, |(column, _)| column);
+                    return Err(ParseError::new(column, "expected ',' or ')'"));
+                }
+            }
+        }
+        let arguments = function.arguments();
+        if !arguments.contains(&args.len()) {
+            let message = if arguments.start() == arguments.end() {
+                format!("{} takes {} arguments", function.name(), arguments.start())
+            } else {
+                format!(
+                    "{} takes {} to {} arguments",
+                    function.name(),
+                    arguments.start(),
+                    arguments.end()
+                )
+            };
+            return Err(ParseError::new(column, &message));
+        }
+        Ok(Expr::Call(function, args))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn a1(text: &str) -> Expr {
+        Expr::Ref(CellRef::parse(text).unwrap())
+    }
+
+    fn num(n: f64) -> Expr {
+        Expr::Literal(Value::Number(n))
+    }
+
+    #[test]
+    fn precedence() {
+        assert_eq!(
+            Expr::parse("A1 + B2 * 2").unwrap(),
+            Expr::binary(
+                Operation::Add,
+                a1("A1"),
+                Expr::binary(Operation::Mult, a1("B2"), num(2.0))
+            )
+        );
+        assert_eq!(
+            Expr::parse("(1-2)-3").unwrap(),
+            Expr::parse("1-2-3").unwrap()
+        );
+        assert_eq!(
+            Expr::parse("-a1*2").unwrap(),
+            Expr::binary(Operation::Mult, Expr::Neg(Box::new(a1("A1"))), num(2.0))
+        );
+    }
+
+    #[test]
+    fn literals() {
+        assert_eq!(Expr::parse("1.5e3").unwrap(), num(1500.0));
+        assert_eq!(
+            Expr::parse("\"say \"\"hi\"\"\"").unwrap(),
+            Expr::Literal(Value::from("say \"hi\""))
+        );
+        assert_eq!(
+            Expr::parse("true").unwrap(),
+            Expr::Literal(Value::Bool(true))
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(Expr::parse("1 +").unwrap_err().column, 3);
+        assert_eq!(Expr::parse("(1").unwrap_err().column, 2);
+        assert_eq!(Expr::parse("1 2").unwrap_err().column, 2);
+        assert_eq!(Expr::parse("1 # 2").unwrap_err().column, 2);
+        assert_eq!(
+            Expr::parse("\"abc").unwrap_err().message,
+            "unterminated string"
+        );
+        assert_eq!(
+            Expr::parse("#FOO!").unwrap_err().message,
+            "unknown error '#FOO!'"
+        );
+    }
+
+    #[test]
+    fn references() {
+        assert_eq!(
+            Expr::parse("sheet2!b3").unwrap(),
+            Expr::Range {
+                sheet: Some("sheet2".to_string()),
+                range: Range::parse("B3").unwrap()
+            }
+        );
+        assert_eq!(
+            Expr::parse("'My Sheet'!C3:A1").unwrap(),
+            Expr::Range {
+                sheet: Some("My Sheet".to_string()),
+                range: Range::parse("A1:C3").unwrap()
+            }
+        );
+        assert_eq!(Expr::parse("A1:A1").unwrap(), a1("A1"));
+        // past XFD1048576 there are no cells
+        assert_eq!(
+            Expr::parse("SUM(B1:ZZZZ1000000)").unwrap_err().message,
+            "expected a cell reference"
+        );
+
+        let expr = Expr::parse("SUM(A1:A2, Other!B1) + C1").unwrap();
+        assert_eq!(
+            expr.references(),
+            [
+                (None, Range::parse("A1:A2").unwrap()),
+                (Some("Other"), Range::parse("B1").unwrap()),
+                (None, Range::parse("C1").unwrap()),
+            ]
+        );
+    }
+
+    #[test]
+    fn names() {
+        let expr = Expr::parse("SUM(Prices) * (1 + tax_rate) + A1").unwrap();
+
+        assert_eq!(expr.names(), ["Prices", "tax_rate"]);
+        assert_eq!(
+            expr.name_renamed("TAX_RATE", "Vat").to_string(),
+            "SUM(Prices)*(1+Vat)+A1"
+        );
+    }
+
+    #[test]
+    fn sheet_renames() {
+        let expr = Expr::parse("Data!A1+data!B2:B3+Other!A1+A1").unwrap();
+
+        assert_eq!(
+            expr.sheet_renamed("DATA", Some("Inputs")).to_string(),
+            "Inputs!A1+Inputs!B2:B3+Other!A1+A1"
+        );
+        assert_eq!(
+            expr.sheet_renamed("Data", None).to_string(),
+            "#REF!+#REF!+Other!A1+A1"
+        );
+    }
+
+    #[test]
+    fn reference_errors() {
+        assert_eq!(
+            Expr::parse("Sheet2!").unwrap_err().message,
+            "unexpected end of formula"
+        );
+        assert_eq!(Expr::parse("A1:3").unwrap_err().column, 3);
+        assert_eq!(
+            Expr::parse("'Sheet 2'").unwrap_err().message,
+            "expected '!' after sheet name"
+        );
+        assert_eq!(
+            Expr::parse("'Sheet 2!A1").unwrap_err().message,
+            "unterminated sheet name"
+        );
+        assert_eq!(
+            Expr::parse("SUMX(1)").unwrap_err().message,
+            "unknown function 'SUMX'"
+        );
+        assert_eq!(Expr::parse("SUM(1 2)").unwrap_err().column, 6);
+        let err = Expr::parse("1+VLOOKUP(A1,B1:C2)").unwrap_err();
+        assert_eq!(err.message, "VLOOKUP takes 3 to 4 arguments");
+        assert_eq!(err.column, 2);
+    }
+
+    #[test]
+    fn display_round_trips() {
+        for formula in [
+            "A1+B2*2",
+            "(A1+B2)*2",
+            "A1-(B1-C1)",
+            "A1/(B1*C1)",
+            "-(A1+1)",
+            "#REF!+1",
+            "\"a\"\"b\"",
+            "SUM(A1:B3,Sheet2!C1,2)*2",
+            "'Q1 Report'!A1+A1!B2:B4",
+            "'it''s'!A1",
+            "COUNT()",
+            "SUM(Prices)*TaxRate",
+            "#NAME?+1",
+            "#N/A+1",
+            "VLOOKUP(A1,B1:C9,2,FALSE)",
+        ] {
+            let expr = Expr::parse(formula).unwrap();
+            assert_eq!(expr.to_string(), formula);
+            assert_eq!(Expr::parse(&expr.to_string()).unwrap(), expr);
+        }
+    }
+}
@@ -0,0 +1,473 @@
+use std::cmp::Ordering;
+use std::ops::RangeInclusive;
+
+use crate::decimal::Arithmetic;
+use crate::formula::Operation;
+use crate::value::{CellError, Value};
+use criteria::Criterion;
+
+mod criteria;
+mod lookup;
+
+/// Built-in function a formula can call, `SUM(A1:A3, 2)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Function {
+    Sum,
+    Count,
+    Min,
+    Max,
+    Average,
+    SumIf,
+    CountIf,
+    VLookup,
+    Match,
+    Index,
+}
+
+/// Evaluated function argument. References are passed as ranges, even when
+/// they are a single cell, because functions treat the cells they read
+/// differently from values typed into the call: `SUM(A1)` skips text in
+/// `A1` while `SUM("a")` is `#VALUE!`.
+#[derive(Clone)]
+pub(crate) enum Arg {
+    Value(Value),
+    /// Values of the range's cells in row-major order, up to the last row
+    /// on its sheet holding anything: the rest of its `rows` are empty.
+    Range {
+        values: Vec<Value>,
+        rows: usize,
+        columns: usize,
+    },
+}
+
+/// The value of the cells past the end of a range's `values`.
+const EMPTY: &Value = &Value::Empty;
+
+impl Arg {
+    /// The argument where a single value is expected: a single cell stands
+    /// for its value, larger ranges are `#VALUE!`.
+    fn value(&self) -> Result<&Value, CellError> {
+        match self {
+            Arg::Value(value) => Ok(value),
+            Arg::Range {
+                values,
+                rows: 1,
+                columns: 1,
+            } => Ok(values.first().unwrap_or(EMPTY)),
+            Arg::Range { .. } => Err(CellError::Value),
+        }
+    }
+
+    /// The argument where cells are expected, with the number of rows and
+    /// columns they are laid out in. A value is taken as a single cell.
+    fn table(&self) -> (&[Value], usize, usize) {
+        match self {
+            Arg::Value(value) => (std::slice::from_ref(value), 1, 1),
+            Arg::Range {
+                values,
+                rows,
+                columns,
+            } => (values, *rows, *columns),
+        }
+    }
+}
+
+/// The `i`th cell of a range in row-major order, given its `values`.
+fn cell(values: &[Value], i: usize) -> &Value {
+    values.get(i).unwrap_or(EMPTY)
+}
+
+impl Function {
+    /// Looks a function up by name, case-insensitive.
+    pub fn parse(name: &str) -> Option<Function> {
+        match name.to_uppercase().as_str() {
+            "SUM" => Some(Function::Sum),
+            "COUNT" => Some(Function::Count),
+            "MIN" => Some(Function::Min),
+            "MAX" => Some(Function::Max),
+            "AVERAGE" => Some(Function::Average),
+            "SUMIF" => Some(Function::SumIf),
+            "COUNTIF" => Some(Function::CountIf),
+            "VLOOKUP" => Some(Function::VLookup),
+            "MATCH" => Some(Function::Match),
+            "INDEX" => Some(Function::Index),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Function::Sum => "SUM",
+            Function::Count => "COUNT",
+            Function::Min => "MIN",
+            Function::Max => "MAX",
+            Function::Average => "AVERAGE",
+            Function::SumIf => "SUMIF",
+            Function::CountIf => "COUNTIF",
+            Function::VLookup => "VLOOKUP",
+            Function::Match => "MATCH",
+            Function::Index => "INDEX",
+        }
+    }
+
+    /// How many arguments the function takes.
+    pub fn arguments(&self) -> RangeInclusive<usize> {
+        match self {
+            Function::Sum | Function::Count | Function::Min | Function::Max | Function::Average => {
+                0..=usize::MAX
+            }
+            Function::SumIf => 2..=3,
+            Function::CountIf => 2..=2,
+            Function::VLookup => 3..=4,
+            Function::Match => 2..=3,
+            Function::Index => 2..=3,
+        }
+    }
+
+    /// Calls the function, calculating numbers with `arithmetic`.
+    pub(crate) fn call(&self, args: &[Arg], arithmetic: Arithmetic) -> Value {
+        if !self.arguments().contains(&args.len()) {
+            return CellError::Value.into();
+        }
+        let result = match self {
+            Function::Count => return Value::Number(count(args) as f64),
+            Function::SumIf | Function::CountIf => self.conditional(args, arithmetic),
+            Function::VLookup => lookup::vlookup(args),
+            Function::Match => lookup::position(args),
+            Function::Index => lookup::index(args),
+            _ => return self.aggregate(args, arithmetic),
+        };
+        result.unwrap_or_else(Value::Error)
+    }
+
+    /// `SUM`, `MIN`, `MAX` and `AVERAGE` of all the numbers in the arguments.
+    fn aggregate(&self, args: &[Arg], arithmetic: Arithmetic) -> Value {
+        let numbers = match numbers(args, arithmetic) {
+            Ok(numbers) => numbers,
+            Err(err) => return Value::Error(err),
+        };
+        let extreme = |keep: Ordering| {
+            numbers
+                .iter()
+                .cloned()
+                .reduce(|a, b| {
+                    if b.compare_numbers(&a) == Some(keep) {
+                        b
+                    } else {
+                        a
+                    }
+                })
+                .unwrap_or(Value::Number(0.0))
+        };
+        match self {
+            Function::Sum => sum(numbers.iter(), arithmetic),
+            Function::Min => extreme(Ordering::Less),
+            Function::Max => extreme(Ordering::Greater),
+            Function::Average if numbers.is_empty() => CellError::DivByZero.into(),
+            Function::Average => sum(numbers.iter(), arithmetic).calculate(
+                Operation::Div,
+                &Value::Number(numbers.len() as f64),
+                arithmetic,
+            ),
+            _ => unreachable!(),
+        }
+    }
+
+    /// `COUNTIF(cells, criterion)` counts the cells matching the criterion,
+    /// `SUMIF(cells, criterion, [sums])` adds up the numbers in the same
+    /// positions of `sums`, or of `cells` without it. Positions past the end
+    /// of `sums` count as empty.
+    fn conditional(&self, args: &[Arg], arithmetic: Arithmetic) -> Result<Value, CellError> {
+        let Arg::Range {
+            values,
+            rows,
+            columns,
+        } = &args[0]
+        else {
+            return Err(CellError::Value);
+        };
+        let criterion = Criterion::new(args[1].value()?);
+        let empty = criterion.matches(EMPTY);
+        if *self == Function::CountIf {
+            let stored = values.iter().filter(|value| criterion.matches(value));
+            let empties = if empty {
+                rows * columns - values.len()
+            } else {
+                0
+            };
+            return Ok(Value::Number((stored.count() + empties) as f64));
+        }
+        let (sums, sum_columns) = match args.get(2) {
+            Some(Arg::Range {
+                values, columns, ..
+            }) => (values, *columns),
+            Some(Arg::Value(_)) => return Err(CellError::Value),
+            None => (values, *columns),
+        };
+        // matching empty cells only add something where `sums` has values
+        let end = match empty {
+            true => (sums.len() / sum_columns * columns).min(rows * columns),
+            false => 0,
+        };
+        let matching = (0..values.len().max(end))
+            .filter(|i| criterion.matches(cell(values, *i)))
+            .map(|i| (i / columns, i % columns));
+        let mut cells = Vec::new();
+        for (row, col) in matching {
+            if col >= sum_columns {
+                continue;
+            }
+            match sums.get(row * sum_columns + col) {
+                Some(value) if value.is_number() => cells.push(value),
+                Some(Value::Error(err)) => return Err(*err),
+                _ => {}
+            }
+        }
+        Ok(sum(cells.into_iter(), arithmetic))
+    }
+}
+
+/// Adds up numbers in `arithmetic`, zero for none.
+fn sum<'a>(numbers: impl Iterator<Item = &'a Value>, arithmetic: Arithmetic) -> Value {
+    numbers.fold(Value::Number(0.0), |sum, n| {
+        sum.calculate(Operation::Add, n, arithmetic)
+    })
+}
+
+/// The numbers a numeric function works on: values typed into the call are
+/// coerced, cells only count when they hold a number. Errors anywhere win.
+/// The numbers are all of the kind `arithmetic` uses.
+fn numbers(args: &[Arg], arithmetic: Arithmetic) -> Result<Vec<Value>, CellError> {
+    let mut numbers = Vec::new();
+    for arg in args {
+        match arg {
+            Arg::Value(value) => numbers.push(match arithmetic {
+                Arithmetic::Float => Value::Number(value.as_number()?),
+                Arithmetic::Decimal { .. } => Value::Decimal(value.as_decimal()?),
+            }),
+            Arg::Range { values, .. } => {
+                for value in values {
+                    match value {
+                        n if n.is_number() => numbers.push(n.clone().for_arithmetic(arithmetic)),
+                        Value::Error(err) => return Err(*err),
+                        _ => {}
+                    }
+                }
+            }
+        }
+    }
+    Ok(numbers)
+}
+
+/// `COUNT` never fails: it counts what reads as a number and skips the rest,
+/// errors included.
+fn count(args: &[Arg]) -> usize {
+    args.iter()
+        .map(|arg| match arg {
+            Arg::Value(Value::Empty | Value::Error(_)) => 0,
+            Arg::Value(value) => value.as_number().is_ok() as usize,
+            Arg::Range { values, .. } => values.iter().filter(|value| value.is_number()).count(),
+        })
+        .sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn range(values: &[Value]) -> Arg {
+        Arg::Range {
+            values: values.to_vec(),
+            rows: values.len(),
+            columns: 1,
+        }
+    }
+
+    #[test]
+    fn cells_skip_what_isnt_a_number() {
+        let cells = range(&[
+            Value::Number(2.0),
+            Value::from("3"),
+            Value::Bool(true),
+            Value::Empty,
+            Value::Number(-1.0),
+        ]);
+
+        assert_eq!(
+            Function::Sum.call(&[cells, Arg::Value(Value::from("3"))], Arithmetic::Float),
+            Value::Number(4.0)
+        );
+        let cells = range(&[Value::Number(2.0), Value::from("x"), Value::Number(4.0)]);
+        assert_eq!(
+            Function::Average.call(&[cells], Arithmetic::Float),
+            Value::Number(3.0)
+        );
+    }
+
+    #[test]
+    fn typed_values_are_coerced() {
+        assert_eq!(
+            Function::Sum.call(
+                &[Arg::Value(Value::Bool(true)), Arg::Value(2.0.into())],
+                Arithmetic::Float
+            ),
+            Value::Number(3.0)
+        );
+        assert_eq!(
+            Function::Sum.call(&[Arg::Value(Value::from("a"))], Arithmetic::Float),
+            CellError::Value.into()
+        );
+    }
+
+    #[test]
+    fn errors_propagate_except_in_count() {
+        let cells = || range(&[Value::Number(1.0), CellError::DivByZero.into()]);
+
+        assert_eq!(
+            Function::Max.call(&[cells()], Arithmetic::Float),
+            CellError::DivByZero.into()
+        );
+        assert_eq!(
+            Function::Count.call(&[cells()], Arithmetic::Float),
+            Value::Number(1.0)
+        );
+    }
+
+    #[test]
+    fn empty_arguments() {
+        assert_eq!(
+            Function::Min.call(&[range(&[])], Arithmetic::Float),
+            Value::Number(0.0)
+        );
+        assert_eq!(
+            Function::Count.call(&[], Arithmetic::Float),
+            Value::Number(0.0)
+        );
+        assert_eq!(
+            Function::Average.call(&[range(&[Value::Empty])], Arithmetic::Float),
+            CellError::DivByZero.into()
+        );
+    }
+
+    #[test]
+    fn conditional_aggregation() {
+        let fruit = range(&[
+            "apple".into(),
+            "banana".into(),
+            "Apricot".into(),
+            Value::Empty,
+        ]);
+        let amounts = range(&[10.0.into(), 20.0.into(), 30.0.into(), 40.0.into()]);
+
+        assert_eq!(
+            Function::SumIf.call(
+                &[fruit.clone(), Arg::Value("a*".into()), amounts.clone()],
+                Arithmetic::Float
+            ),
+            Value::Number(40.0)
+        );
+        assert_eq!(
+            Function::CountIf.call(
+                &[fruit.clone(), Arg::Value("<>a*".into())],
+                Arithmetic::Float
+            ),
+            Value::Number(2.0)
+        );
+        assert_eq!(
+            Function::SumIf.call(
+                &[amounts.clone(), Arg::Value(">15".into())],
+                Arithmetic::Float
+            ),
+            Value::Number(90.0)
+        );
+        // a shorter sum range leaves the rest out
+        assert_eq!(
+            Function::SumIf.call(
+                &[fruit, Arg::Value("".into()), range(&[1.0.into()])],
+                Arithmetic::Float
+            ),
+            Value::Number(0.0)
+        );
+        assert_eq!(
+            Function::CountIf.call(
+                &[Arg::Value(1.0.into()), Arg::Value(1.0.into())],
+                Arithmetic::Float
+            ),
+            CellError::Value.into()
+        );
+    }
+
+    #[test]
+    fn empty_rows_past_the_values() {
+        // a range of 1000 rows with values in the first two
+        let cells = Arg::Range {
+            values: vec![Value::Number(1.0), Value::from("a")],
+            rows: 1000,
+            columns: 1,
+        };
+        let amounts = range(&[Value::Number(1.0), Value::Number(2.0), Value::Number(4.0)]);
+
+        assert_eq!(
+            Function::CountIf.call(
+                &[cells.clone(), Arg::Value("<>a".into())],
+                Arithmetic::Float
+            ),
+            Value::Number(999.0)
+        );
+        assert_eq!(
+            Function::SumIf.call(
+                &[cells.clone(), Arg::Value("<>a".into()), amounts],
+                Arithmetic::Float
+            ),
+            Value::Number(5.0)
+        );
+        assert_eq!(
+            Function::Index.call(
+                &[cells.clone(), Arg::Value(999.0.into())],
+                Arithmetic::Float
+            ),
+            Value::Empty
+        );
+        assert_eq!(
+            Function::Index.call(&[cells, Arg::Value(1001.0.into())], Arithmetic::Float),
+            CellError::Ref.into()
+        );
+    }
+
+    #[test]
+    fn errors_in_conditional_aggregation() {
+        let cells = range(&[1.0.into(), CellError::Num.into(), 3.0.into()]);
+
+        assert_eq!(
+            Function::CountIf.call(
+                &[cells.clone(), Arg::Value("#NUM!".into())],
+                Arithmetic::Float
+            ),
+            Value::Number(1.0)
+        );
+        assert_eq!(
+            Function::SumIf.call(&[cells.clone(), Arg::Value(">1".into())], Arithmetic::Float),
+            Value::Number(3.0)
+        );
+        assert_eq!(
+            Function::SumIf.call(&[cells, Arg::Value("<>1".into())], Arithmetic::Float),
+            CellError::Num.into()
+        );
+    }
+
+    #[test]
+    fn argument_counts() {
+        assert_eq!(Function::VLookup.arguments(), 3..=4);
+        assert_eq!(
+            Function::Index.call(&[range(&[1.0.into()])], Arithmetic::Float),
+            CellError::Value.into()
+        );
+    }
+
+    #[test]
+    fn names() {
+        assert_eq!(Function::parse("sum"), Some(Function::Sum));
+        assert_eq!(Function::parse("AVERAGE").unwrap().name(), "AVERAGE");
+        assert_eq!(Function::parse("SUMX"), None);
+    }
+}
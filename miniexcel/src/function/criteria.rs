@@ -0,0 +1,190 @@
+use std::cmp::Ordering;
+
+use crate::decimal::Decimal;
+use crate::value::{CellError, Value};
+
+/// Condition of `SUMIF` and `COUNTIF`. A value matches cells holding the
+/// same value, text may start with a comparison, `">=10"`, `"<>"`, and may
+/// use the wildcards `*`, `?` and `~` to escape them, `"app*"`.
+pub(super) struct Criterion {
+    op: Comparison,
+    operand: Value,
+}
+
+#[derive(Clone, Copy)]
+enum Comparison {
+    Equal,
+    NotEqual,
+    Less,
+    LessOrEqual,
+    Greater,
+    GreaterOrEqual,
+}
+
+impl Criterion {
+    pub(super) fn new(criterion: &Value) -> Criterion {
+        let Value::Text(text) = criterion else {
+            return Criterion {
+                op: Comparison::Equal,
+                operand: criterion.clone(),
+            };
+        };
+        let (op, operand) = [
+            ("<=", Comparison::LessOrEqual),
+            (">=", Comparison::GreaterOrEqual),
+            ("<>", Comparison::NotEqual),
+            ("<", Comparison::Less),
+            (">", Comparison::Greater),
+            ("=", Comparison::Equal),
+        ]
+        .into_iter()
+        .find_map(|(prefix, op)| Some((op, text.strip_prefix(prefix)?)))
+        .unwrap_or((Comparison::Equal, text));
+        let operand = match CellError::from_code(operand) {
+            Some(err) => Value::Error(err),
+            // `"=""a"""` is the text `"a"`, not `a`
+            None if operand.starts_with('"') => Value::Text(operand.to_string()),
+            None => Value::from_literal(operand),
+        };
+        Criterion { op, operand }
+    }
+
+    pub(super) fn matches(&self, value: &Value) -> bool {
+        match self.op {
+            Comparison::Equal => equals(value, &self.operand),
+            Comparison::NotEqual => !equals(value, &self.operand),
+            op => compare(value, &self.operand).is_some_and(|ordering| match op {
+                Comparison::Less => ordering.is_lt(),
+                Comparison::LessOrEqual => ordering.is_le(),
+                Comparison::Greater => ordering.is_gt(),
+                _ => ordering.is_ge(),
+            }),
+        }
+    }
+}
+
+/// Whether a cell holds the value a criterion asks for: text patterns only
+/// match text, numbers also match text that reads as the same number and
+/// nothing matches empty cells but empty text.
+fn equals(value: &Value, operand: &Value) -> bool {
+    match (value, operand) {
+        (Value::Text(text), Value::Text(pattern)) => wildcard_match(pattern, text),
+        (Value::Text(text), Value::Empty) => text.is_empty(),
+        (Value::Text(text), Value::Number(n)) => text.trim().parse::<f64>() == Ok(*n),
+        (Value::Text(text), Value::Decimal(d)) => text.parse::<Decimal>().is_ok_and(|n| n == *d),
+        _ if value.is_number() => value.compare_numbers(operand) == Some(Ordering::Equal),
+        _ => value == operand,
+    }
+}
+
+/// Orders values of the same kind the way lookups do: numbers by size, text
+/// alphabetically ignoring case, `FALSE` before `TRUE`. Values of different
+/// kinds don't compare.
+pub(super) fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Text(a), Value::Text(b)) => Some(a.to_lowercase().cmp(&b.to_lowercase())),
+        (Value::Bool(a), Value::Bool(b)) => Some(a.cmp(b)),
+        _ => a.compare_numbers(b),
+    }
+}
+
+/// Matches `text` against a pattern where `*` stands for any number of
+/// characters, `?` for any one character and `~` makes the next character
+/// literal. Case is ignored.
+pub(super) fn wildcard_match(pattern: &str, text: &str) -> bool {
+    enum Part {
+        Any,
+        One,
+        Char(char),
+    }
+    let mut parts = Vec::new();
+    let mut chars = pattern.chars().flat_map(char::to_lowercase);
+    while let Some(c) = chars.next() {
+        parts.push(match c {
+            '*' => Part::Any,
+            '?' => Part::One,
+            '~' => Part::Char(chars.next().unwrap_or('~')),
+            c => Part::Char(c),
+        });
+    }
+    let text: Vec<char> = text.chars().flat_map(char::to_lowercase).collect();
+
+    // greedy matching that goes back to the last `*` on a mismatch
+    let (mut p, mut t) = (0, 0);
+    let mut star = None;
+    while t < text.len() {
+        match parts.get(p) {
+            Some(Part::Any) => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(Part::One) => {
+                p += 1;
+                t += 1;
+            }
+            Some(Part::Char(c)) if *c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                Some((star_p, star_t)) => {
+                    p = star_p + 1;
+                    t = star_t + 1;
+                    star = Some((star_p, star_t + 1));
+                }
+                None => return false,
+            },
+        }
+    }
+    parts[p..].iter().all(|part| matches!(part, Part::Any))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn matches(criterion: impl Into<Value>, value: impl Into<Value>) -> bool {
+        Criterion::new(&criterion.into()).matches(&value.into())
+    }
+
+    #[test]
+    fn wildcards() {
+        assert!(wildcard_match("app*", "Apple"));
+        assert!(wildcard_match("*le", "apple"));
+        assert!(wildcard_match("a?p*e", "ample"));
+        assert!(wildcard_match("*", ""));
+        assert!(wildcard_match("a*b*c", "aXbYbZc"));
+        assert!(!wildcard_match("a?", "a"));
+        assert!(!wildcard_match("app", "apple"));
+        assert!(wildcard_match("what~?", "what?"));
+        assert!(!wildcard_match("what~?", "whats"));
+        assert!(wildcard_match("~*", "*"));
+    }
+
+    #[test]
+    fn comparisons() {
+        assert!(matches(">=10", 10.0));
+        assert!(!matches(">10", 10.0));
+        assert!(matches("<b", "Apple"));
+        assert!(!matches("<10", "5"));
+        assert!(!matches("<10", Value::Empty));
+        assert!(matches("<>5", "x"));
+        assert!(!matches("<>5", 5.0));
+    }
+
+    #[test]
+    fn equality() {
+        assert!(matches(5.0, 5.0));
+        assert!(matches("5", 5.0));
+        assert!(matches(5.0, "5"));
+        assert!(matches("=apple", "APPLE"));
+        assert!(matches("true", true));
+        assert!(!matches("true", "true"));
+        assert!(matches("#DIV/0!", CellError::DivByZero));
+        assert!(matches("", Value::Empty));
+        assert!(matches("=", Value::Empty));
+        assert!(!matches("", 0.0));
+        assert!(matches("<>", 0.0));
+        assert!(!matches("<>", Value::Empty));
+    }
+}
@@ -0,0 +1,266 @@
+use std::cmp::Ordering;
+
+use super::criteria::{compare, wildcard_match};
+use super::{cell, Arg};
+use crate::value::{CellError, Value};
+
+/// How `VLOOKUP` and `MATCH` look for a value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Search {
+    /// The first equal value. Text is compared ignoring case and may use
+    /// wildcards.
+    Exact,
+    /// The largest value that is at most the one looked for, in keys sorted
+    /// in ascending order.
+    Ascending,
+    /// The smallest value that is at least the one looked for, in keys
+    /// sorted in descending order.
+    Descending,
+}
+
+/// Index of the key `search` finds for `value`. Keys of another kind than
+/// `value` are skipped, and approximate searches stop at the first key past
+/// `value` since the keys are expected to be sorted.
+fn find<'a>(keys: impl Iterator<Item = &'a Value>, value: &Value, search: Search) -> Option<usize> {
+    let mut found = None;
+    for (i, key) in keys.enumerate() {
+        match search {
+            Search::Exact => {
+                let equal = match (key, value) {
+                    (Value::Text(key), Value::Text(pattern)) => wildcard_match(pattern, key),
+                    _ => compare(key, value) == Some(Ordering::Equal),
+                };
+                if equal {
+                    return Some(i);
+                }
+            }
+            Search::Ascending | Search::Descending => {
+                let past = if search == Search::Ascending {
+                    Ordering::Greater
+                } else {
+                    Ordering::Less
+                };
+                match compare(key, value) {
+                    Some(ordering) if ordering == past => break,
+                    Some(_) => found = Some(i),
+                    None => {}
+                }
+            }
+        }
+    }
+    found
+}
+
+/// A whole number argument such as a row or column number, truncated like
+/// Excel does.
+fn number(arg: &Arg) -> Result<i64, CellError> {
+    Ok(arg.value()?.as_number()?.trunc() as i64)
+}
+
+/// `VLOOKUP(value, table, column, [approximate])` finds `value` in the first
+/// column of `table` and returns the cell in `column` of that row.
+pub(super) fn vlookup(args: &[Arg]) -> Result<Value, CellError> {
+    let value = args[0].value()?;
+    if let Value::Error(err) = value {
+        return Err(*err);
+    }
+    let (cells, rows, columns) = args[1].table();
+    let column = number(&args[2])?;
+    if column < 1 {
+        return Err(CellError::Value);
+    }
+    let column = column as usize;
+    if column > columns {
+        return Err(CellError::Ref);
+    }
+    let search = match args.get(3) {
+        Some(arg) if !arg.value()?.as_bool()? => Search::Exact,
+        _ => Search::Ascending,
+    };
+    let keys = (0..rows).map(|row| cell(cells, row * columns));
+    let row = find(keys, value, search).ok_or(CellError::NotAvailable)?;
+    Ok(cell(cells, row * columns + column - 1).clone())
+}
+
+/// `MATCH(value, cells, [type])` is the position of `value` in a single row
+/// or column: exact for type 0, ascending for 1, descending for -1.
+pub(super) fn position(args: &[Arg]) -> Result<Value, CellError> {
+    let value = args[0].value()?;
+    if let Value::Error(err) = value {
+        return Err(*err);
+    }
+    let (cells, rows, columns) = args[1].table();
+    if columns != 1 && rows != 1 {
+        return Err(CellError::NotAvailable);
+    }
+    let search = match args.get(2).map(number).transpose()?.unwrap_or(1) {
+        0 => Search::Exact,
+        t if t > 0 => Search::Ascending,
+        _ => Search::Descending,
+    };
+    let keys = (0..rows * columns).map(|i| cell(cells, i));
+    let i = find(keys, value, search).ok_or(CellError::NotAvailable)?;
+    Ok(Value::Number((i + 1) as f64))
+}
+
+/// `INDEX(cells, row, [column])` is the cell at a position counted from 1.
+/// A single row or column can be indexed by one number, and 0 stands for
+/// the whole row or column, which only works out when that is one cell.
+pub(super) fn index(args: &[Arg]) -> Result<Value, CellError> {
+    let (cells, rows, columns) = args[0].table();
+    let row = number(&args[1])?;
+    let column = args.get(2).map(number).transpose()?;
+    let (row, column) = match column {
+        Some(column) => (row, column),
+        None if rows == 1 => (1, row),
+        None => (row, 0),
+    };
+    if row < 0 || column < 0 {
+        return Err(CellError::Value);
+    }
+    let (row, column) = (row as usize, column as usize);
+    if row > rows || column > columns {
+        return Err(CellError::Ref);
+    }
+    let pick = |n: usize, len: usize| match (n, len) {
+        (0, 1) => Ok(0),
+        (0, _) => Err(CellError::Value),
+        (n, _) => Ok(n - 1),
+    };
+    let (row, column) = (pick(row, rows)?, pick(column, columns)?);
+    Ok(cell(cells, row * columns + column).clone())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A table of `columns` columns, filled in row by row.
+    fn table(columns: usize, values: &[Value]) -> Arg {
+        Arg::Range {
+            values: values.to_vec(),
+            rows: values.len() / columns,
+            columns,
+        }
+    }
+
+    fn value(value: impl Into<Value>) -> Arg {
+        Arg::Value(value.into())
+    }
+
+    fn prices() -> Arg {
+        table(
+            2,
+            &[
+                "apple".into(),
+                1.5.into(),
+                "banana".into(),
+                0.25.into(),
+                "cherry".into(),
+                4.0.into(),
+            ],
+        )
+    }
+
+    fn brackets() -> Arg {
+        table(
+            2,
+            &[
+                0.0.into(),
+                "F".into(),
+                50.0.into(),
+                "C".into(),
+                70.0.into(),
+                "B".into(),
+                90.0.into(),
+                "A".into(),
+            ],
+        )
+    }
+
+    #[test]
+    fn exact_vlookup() {
+        let exact = |key: &str| vlookup(&[value(key), prices(), value(2.0), value(false)]);
+
+        assert_eq!(exact("Banana"), Ok(Value::Number(0.25)));
+        assert_eq!(exact("ch*"), Ok(Value::Number(4.0)));
+        assert_eq!(exact("b?nana"), Ok(Value::Number(0.25)));
+        assert_eq!(exact("kiwi"), Err(CellError::NotAvailable));
+    }
+
+    #[test]
+    fn approximate_vlookup() {
+        let grade = |score: f64| vlookup(&[value(score), brackets(), value(2.0)]);
+
+        assert_eq!(grade(72.0), Ok(Value::from("B")));
+        assert_eq!(grade(50.0), Ok(Value::from("C")));
+        assert_eq!(grade(100.0), Ok(Value::from("A")));
+        assert_eq!(grade(-1.0), Err(CellError::NotAvailable));
+    }
+
+    #[test]
+    fn vlookup_errors() {
+        let column = |n: f64| vlookup(&[value("apple"), prices(), value(n), value(false)]);
+
+        assert_eq!(column(0.0), Err(CellError::Value));
+        assert_eq!(column(3.0), Err(CellError::Ref));
+        assert_eq!(
+            vlookup(&[value(CellError::DivByZero), prices(), value(2.0)]),
+            Err(CellError::DivByZero)
+        );
+        assert_eq!(
+            vlookup(&[value("apple"), prices(), value("x")]),
+            Err(CellError::Value)
+        );
+    }
+
+    #[test]
+    fn match_positions() {
+        let ascending = table(1, &[1.0.into(), 3.0.into(), 5.0.into(), 7.0.into()]);
+        let descending = table(4, &[7.0.into(), 5.0.into(), 3.0.into(), 1.0.into()]);
+
+        assert_eq!(
+            position(&[value(4.0), ascending.clone()]),
+            Ok(Value::Number(2.0))
+        );
+        assert_eq!(
+            position(&[value(4.0), descending.clone(), value(-1.0)]),
+            Ok(Value::Number(2.0))
+        );
+        assert_eq!(
+            position(&[value(5.0), descending, value(0.0)]),
+            Ok(Value::Number(2.0))
+        );
+        assert_eq!(
+            position(&[value(4.0), ascending, value(0.0)]),
+            Err(CellError::NotAvailable)
+        );
+        assert_eq!(
+            position(&[value("x"), prices(), value(0.0)]),
+            Err(CellError::NotAvailable)
+        );
+    }
+
+    #[test]
+    fn index_positions() {
+        assert_eq!(
+            index(&[prices(), value(3.0), value(2.0)]),
+            Ok(Value::Number(4.0))
+        );
+        let row = table(3, &[1.0.into(), 2.0.into(), 3.0.into()]);
+        assert_eq!(index(&[row.clone(), value(2.0)]), Ok(Value::Number(2.0)));
+        assert_eq!(
+            index(&[row, value(0.0), value(3.0)]),
+            Ok(Value::Number(3.0))
+        );
+        assert_eq!(index(&[prices(), value(2.0)]), Err(CellError::Value));
+        assert_eq!(
+            index(&[prices(), value(4.0), value(1.0)]),
+            Err(CellError::Ref)
+        );
+        assert_eq!(
+            index(&[prices(), value(-1.0), value(1.0)]),
+            Err(CellError::Value)
+        );
+    }
+}
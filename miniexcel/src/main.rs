@@ -1,71 +1,65 @@
-use std::error::Error;
-use std::io;
+use std::env;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Write};
+use std::process;
+use std::thread;
 
-mod lib;
-use lib::*;
+use miniexcel::*;
 
-macro_rules! parse_input {
-    ($x:expr, $t:ident) => {
-        $x.trim().parse::<$t>().unwrap()
-    };
-}
-
-fn main() {
-    let mut input_line = String::new();
-    io::stdin().read_line(&mut input_line).unwrap();
-    let n = parse_input!(input_line, i32);
-
-    let mut sheet = Sheet::new();
-
-    for _ in 0..n as usize {
-        let mut input_line = String::new();
-        io::stdin().read_line(&mut input_line).unwrap();
-        let inputs = input_line.split(" ").collect::<Vec<_>>();
-        let operation = parse_operation(inputs[0].trim()).unwrap();
-        let arg1 = parse_arg(inputs[1].trim()).unwrap();
-        let arg2 = parse_arg(inputs[2].trim()).unwrap();
+mod tui;
 
-        sheet.push(Cell {
-            operation,
-            args: [arg1, arg2],
-        });
-    }
-    for i in 0..n as usize {
-        // Write an answer using println!("message...");
-        // To debug: eprintln!("Debug message...");
-        println!("{}", sheet.calculate_cell(i));
-    }
-    // println!("calculated {} times", sheet.get_total());
-}
+const USAGE: &str =
+    "usage: miniexcel [SCRIPT] | miniexcel --explain CELL [SCRIPT] | miniexcel tui [FILE]";
 
-fn parse_operation(operation: &str) -> Result<Operation, String> {
-    match operation {
-        "VALUE" => Ok(Operation::Val),
-        "ADD" => Ok(Operation::Add),
-        "SUB" => Ok(Operation::Sub),
-        "MULT" => Ok(Operation::Mult),
-        _ => Err(format!("Couldn't parse operation '{}'.", operation)),
+fn main() {
+    let args: Vec<String> = env::args().collect();
+    match args.get(1).map(String::as_str) {
+        Some("tui") => {
+            if let Err(err) = tui::run(args.get(2).map(String::as_str)) {
+                eprintln!("miniexcel: {}", err);
+                process::exit(1);
+            }
+        }
+        Some("--explain") => match args.get(2).and_then(|at| CellRef::parse(at)) {
+            Some(at) => run_script(args.get(3).map(String::as_str), Some(at)),
+            None => {
+                eprintln!("{}", USAGE);
+                process::exit(2);
+            }
+        },
+        Some(arg) if arg.starts_with('-') => {
+            eprintln!("{}", USAGE);
+            process::exit(2);
+        }
+        path => run_script(path, None),
     }
 }
 
-fn parse_arg(arg: &str) -> Result<Option<CellValue>, Box<dyn Error>> {
-    match arg {
-        "_" => Ok(None),
-        _ => {
-            match arg
-                .chars()
-                .nth(0)
-                .expect("Couldn't parse argument.")
-            {
-                '$' => {
-                    let reference = arg[1..].parse::<usize>()?;
-                    Ok(Some(CellValue::new(CellType::Ref(reference))))
-                }
-                _ => {
-                    let value = arg.parse::<i32>()?;
-                    Ok(Some(CellValue::new(CellType::Val(value))))
-                }
+/// Runs the commands of a script file, or of stdin without one, on a new
+/// sheet, see [`Command`]. With `explain`, that cell's calculation is
+/// printed once the script has run. Like the script, it only sees that one
+/// sheet, references to other sheets are `#REF!`.
+fn run_script(path: Option<&str>, explain: Option<CellRef>) {
+    let script: Box<dyn BufRead> = match path {
+        Some(path) => match File::open(path) {
+            Ok(file) => Box::new(BufReader::new(file)),
+            Err(err) => {
+                eprintln!("miniexcel: {}: {}", path, err);
+                process::exit(1);
             }
-        }
+        },
+        None => Box::new(io::stdin().lock()),
+    };
+
+    let mut sheet = Sheet::new();
+    let mut out = io::stdout().lock();
+    if let Err(err) = sheet.run_script(script, &mut out) {
+        let _ = out.flush();
+        eprintln!("miniexcel: {}", err);
+        process::exit(1);
+    }
+    if let Some(at) = explain {
+        sheet.calculate_all(thread::available_parallelism().map_or(1, usize::from));
+        print!("{}", sheet.explain(at));
     }
 }
@@ -0,0 +1,195 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::formula::Expr;
+use crate::reference::{same_name, CellRef, Range, Shift};
+use crate::value::{CellError, Value};
+
+/// What a defined name stands for: `TaxRate = C2`, `Prices = B2:B100` or
+/// `Vat = 0.2`. Formulas use the name wherever they could use its cells or
+/// its value.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Name {
+    /// Cells on `sheet`, or on the sheet the name is defined for when that is
+    /// `None`.
+    Range {
+        sheet: Option<String>,
+        range: Range,
+    },
+    Constant(Value),
+}
+
+impl Name {
+    /// Parses a definition the way it is written after the `=`: a reference,
+    /// `Data!B2:B100`, or a literal, `0.2`, `"EUR"`.
+    pub fn parse(text: &str) -> Option<Name> {
+        match Expr::parse(text).ok()? {
+            Expr::Ref(at) => Some(Name::from(Range::from(at))),
+            Expr::Range { sheet, range } => Some(Name::Range { sheet, range }),
+            Expr::Literal(value) => Some(Name::Constant(value)),
+            Expr::Neg(expr) => match *expr {
+                Expr::Literal(Value::Number(n)) => Some(Name::Constant(Value::Number(-n))),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// The formula the name stands for.
+    pub(crate) fn to_expr(&self) -> Expr {
+        match self {
+            Name::Range { sheet: None, range } if range.start == range.end => {
+                Expr::Ref(range.start)
+            }
+            Name::Range { sheet, range } => Expr::Range {
+                sheet: sheet.clone(),
+                range: *range,
+            },
+            Name::Constant(value) => Expr::Literal(value.clone()),
+        }
+    }
+
+    /// The definition after rows or columns of the sheet it is defined for
+    /// moved, `None` when all of its cells were deleted.
+    pub(crate) fn shifted(&self, shift: &Shift) -> Option<Name> {
+        match self {
+            Name::Range { sheet: None, range } => Some(Name::Range {
+                sheet: None,
+                range: shift.range(*range)?,
+            }),
+            _ => Some(self.clone()),
+        }
+    }
+
+    /// The definition after rows or columns of the sheet `sheet` moved, when
+    /// it names that sheet. `None` when all of its cells were deleted.
+    pub(crate) fn sheet_shifted(&self, sheet: &str, shift: &Shift) -> Option<Name> {
+        match self {
+            Name::Range {
+                sheet: Some(name),
+                range,
+            } if same_name(name, sheet) => Some(Name::Range {
+                sheet: Some(name.clone()),
+                range: shift.range(*range)?,
+            }),
+            _ => Some(self.clone()),
+        }
+    }
+
+    /// The definition after the sheet `from` was renamed to `to`, or deleted
+    /// when `to` is `None`, which leaves the name standing for `#REF!`.
+    pub(crate) fn sheet_renamed(&self, from: &str, to: Option<&str>) -> Name {
+        match self {
+            Name::Range {
+                sheet: Some(sheet),
+                range,
+            } if same_name(sheet, from) => match to {
+                Some(to) => Name::Range {
+                    sheet: Some(to.to_string()),
+                    range: *range,
+                },
+                None => Name::Constant(Value::Error(CellError::Ref)),
+            },
+            _ => self.clone(),
+        }
+    }
+}
+
+impl From<Range> for Name {
+    fn from(range: Range) -> Name {
+        Name::Range { sheet: None, range }
+    }
+}
+
+impl From<Value> for Name {
+    fn from(value: Value) -> Name {
+        Name::Constant(value)
+    }
+}
+
+/// Shows the definition so that [`Name::parse`] reads it back.
+impl fmt::Display for Name {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Name::Range { sheet: None, range } => write!(f, "{}", range),
+            _ => write!(f, "{}", self.to_expr()),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum NameError {
+    /// Not usable in a formula, e.g. because it reads as a cell reference.
+    InvalidName(String),
+    UnknownName(String),
+    DuplicateName(String),
+    /// A workbook name for cells that doesn't say which sheet they are on.
+    MissingSheet(String),
+}
+
+impl fmt::Display for NameError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NameError::InvalidName(name) => write!(f, "invalid name '{}'", name),
+            NameError::UnknownName(name) => write!(f, "no name '{}' is defined", name),
+            NameError::DuplicateName(name) => write!(f, "'{}' is already defined", name),
+            NameError::MissingSheet(name) => {
+                write!(f, "'{}' has to name the sheet of its cells", name)
+            }
+        }
+    }
+}
+
+impl Error for NameError {}
+
+/// Whether a formula would read `name` as a name: an identifier that isn't a
+/// cell reference or a boolean.
+pub(crate) fn check_name(name: &str) -> Result<(), NameError> {
+    let valid = name.starts_with(|c: char| c.is_alphabetic() || c == '_')
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '_' || c == '.')
+        && CellRef::parse(name).is_none()
+        && !["TRUE", "FALSE"].contains(&name.to_uppercase().as_str());
+    if valid {
+        Ok(())
+    } else {
+        Err(NameError::InvalidName(name.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn definitions_round_trip() {
+        for text in [
+            "C2",
+            "B2:B100",
+            "Data!A1",
+            "'Q1 Report'!A1:B2",
+            "0.2",
+            "-3",
+            "\"EUR\"",
+            "TRUE",
+        ] {
+            let name = Name::parse(text).unwrap();
+            assert_eq!(name.to_string(), text);
+        }
+        assert_eq!(Name::parse("0.2"), Some(Value::Number(0.2).into()));
+        assert_eq!(Name::parse("A1+1"), None);
+    }
+
+    #[test]
+    fn valid_names() {
+        assert!(check_name("TaxRate").is_ok());
+        assert!(check_name("_tax.rate2").is_ok());
+        for name in ["C2", "true", "2x", "tax rate", ""] {
+            assert_eq!(
+                check_name(name),
+                Err(NameError::InvalidName(name.to_string()))
+            );
+        }
+    }
+}
@@ -0,0 +1,314 @@
+//! The line based input format: a cell count followed by one
+//! `OPERATION arg1 arg2` line per cell, where an argument is a literal, a
+//! reference `$n` to the n-th cell or `_` for none.
+
+use std::error::Error;
+use std::fmt;
+
+use crate::formula::{Expr, Operation};
+use crate::reference::CellRef;
+use crate::sheet::{Cell, Sheet};
+use crate::value::Value;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InputErrorKind {
+    /// The first line isn't a cell count.
+    InvalidCount(String),
+    /// The input ended before all counted cells were read.
+    MissingCells {
+        expected: usize,
+        found: usize,
+    },
+    /// A non-blank line after the last counted cell.
+    UnexpectedLine,
+    UnknownOperation(String),
+    /// A cell line without all of its arguments.
+    MissingArgument,
+    TooManyArguments,
+    InvalidReference(String),
+    /// `$n` pointing past the last cell.
+    ReferenceOutOfRange {
+        reference: usize,
+        cells: usize,
+    },
+}
+
+/// A malformed part of the input. `line` and `column` are one based and
+/// point at the start of the offending token.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputError {
+    pub line: usize,
+    pub column: usize,
+    pub kind: InputErrorKind,
+}
+
+impl fmt::Display for InputError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}, column {}: ", self.line, self.column)?;
+        match &self.kind {
+            InputErrorKind::InvalidCount(count) => {
+                write!(f, "expected a cell count, found '{}'", count)
+            }
+            InputErrorKind::MissingCells { expected, found } => {
+                write!(f, "expected {} cells, found {}", expected, found)
+            }
+            InputErrorKind::UnexpectedLine => write!(f, "unexpected line after the last cell"),
+            InputErrorKind::UnknownOperation(operation) => {
+                write!(f, "unknown operation '{}'", operation)
+            }
+            InputErrorKind::MissingArgument => write!(f, "missing argument"),
+            InputErrorKind::TooManyArguments => write!(f, "too many arguments"),
+            InputErrorKind::InvalidReference(reference) => {
+                write!(f, "invalid reference '{}'", reference)
+            }
+            InputErrorKind::ReferenceOutOfRange { reference, cells } => write!(
+                f,
+                "reference to cell {} but there are only {} cells",
+                reference, cells
+            ),
+        }
+    }
+}
+
+impl Error for InputError {}
+
+/// Whitespace separated words of a line with their one based column.
+fn words(line: &str) -> Vec<(usize, &str)> {
+    let mut words = Vec::new();
+    let mut start = None;
+    for (column, (index, c)) in line.char_indices().enumerate() {
+        match (start, c.is_whitespace()) {
+            (None, false) => start = Some((column, index)),
+            (Some((word_column, word_index)), true) => {
+                words.push((word_column + 1, &line[word_index..index]));
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some((column, index)) = start {
+        words.push((column + 1, &line[index..]));
+    }
+    words
+}
+
+/// Reads the whole input into a sheet with cell `$n` in row n of column A,
+/// reporting every malformed cell instead of stopping at the first.
+pub fn parse_protocol(input: &str) -> Result<Sheet, Vec<InputError>> {
+    let mut lines = input
+        .lines()
+        .enumerate()
+        .map(|(index, line)| (index + 1, line));
+    let error = |line, column, kind| InputError { line, column, kind };
+
+    let (count_line, count) = match lines.next() {
+        None => {
+            return Err(vec![error(
+                1,
+                1,
+                InputErrorKind::InvalidCount(String::new()),
+            )])
+        }
+        Some((line, text)) => match text.trim().parse::<usize>() {
+            Ok(count) => (line, count),
+            Err(_) => {
+                let column = words(text).first().map_or(1, |(column, _)| *column);
+                return Err(vec![error(
+                    line,
+                    column,
+                    InputErrorKind::InvalidCount(text.trim().to_string()),
+                )]);
+            }
+        },
+    };
+
+    let mut sheet = Sheet::new();
+    let mut errors = Vec::new();
+    let mut last_line = count_line;
+    for row in 0..count {
+        let Some((line, text)) = lines.next() else {
+            errors.push(error(
+                last_line + 1,
+                1,
+                InputErrorKind::MissingCells {
+                    expected: count,
+                    found: row,
+                },
+            ));
+            break;
+        };
+        last_line = line;
+        match parse_cell(text, count) {
+            Ok(cell) => sheet.set(CellRef::new(row, 0), cell),
+            Err(mut cell_errors) => errors.extend(
+                cell_errors
+                    .drain(..)
+                    .map(|(column, kind)| error(line, column, kind)),
+            ),
+        }
+    }
+    for (line, text) in lines {
+        if let Some((column, _)) = words(text).first() {
+            errors.push(error(line, *column, InputErrorKind::UnexpectedLine));
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(sheet)
+    } else {
+        Err(errors)
+    }
+}
+
+/// Parses one `OPERATION arg1 arg2` line, errors are `(column, kind)`.
+fn parse_cell(line: &str, cells: usize) -> Result<Cell, Vec<(usize, InputErrorKind)>> {
+    let words = words(line);
+    let mut errors = Vec::new();
+
+    let operation = match words.first() {
+        None => return Err(vec![(1, InputErrorKind::MissingArgument)]),
+        Some((column, operation)) => match parse_operation(operation) {
+            Some(operation) => operation,
+            None => {
+                errors.push((
+                    *column,
+                    InputErrorKind::UnknownOperation(operation.to_string()),
+                ));
+                None
+            }
+        },
+    };
+    let mut args = Vec::new();
+    for index in 1..3 {
+        match words.get(index) {
+            Some((column, arg)) => match parse_arg(arg, cells) {
+                Ok(arg) => args.push(arg),
+                Err(kind) => errors.push((*column, kind)),
+            },
+            None => {
+                let column = line.chars().count() + 1;
+                errors.push((column, InputErrorKind::MissingArgument));
+                break;
+            }
+        }
+    }
+    if let Some((column, _)) = words.get(3) {
+        errors.push((*column, InputErrorKind::TooManyArguments));
+    }
+
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    let arg2 = args.pop().unwrap();
+    let arg1 = args.pop().unwrap();
+    Ok(Cell::Formula(match operation {
+        Some(operation) => Expr::binary(operation, arg1, arg2),
+        None => arg1,
+    }))
+}
+
+/// `Some(None)` for `VALUE`, which has no operation: the cell is just its
+/// first argument.
+fn parse_operation(operation: &str) -> Option<Option<Operation>> {
+    match operation {
+        "VALUE" => Some(None),
+        "ADD" => Some(Some(Operation::Add)),
+        "SUB" => Some(Some(Operation::Sub)),
+        "MULT" => Some(Some(Operation::Mult)),
+        "DIV" => Some(Some(Operation::Div)),
+        _ => None,
+    }
+}
+
+fn parse_arg(arg: &str, cells: usize) -> Result<Expr, InputErrorKind> {
+    if arg == "_" {
+        return Ok(Expr::Literal(Value::Number(0.0)));
+    }
+    match arg.strip_prefix('$') {
+        Some(reference) => match reference.parse::<usize>() {
+            Ok(reference) if reference < cells => Ok(Expr::Ref(CellRef::new(reference, 0))),
+            Ok(reference) => Err(InputErrorKind::ReferenceOutOfRange { reference, cells }),
+            Err(_) => Err(InputErrorKind::InvalidReference(arg.to_string())),
+        },
+        None => Ok(Expr::Literal(Value::from_literal(arg))),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_cells() {
+        let mut sheet = parse_protocol("3\nVALUE 3 _\nADD $0 4\n MULT\t$1  $0\n").unwrap();
+
+        assert_eq!(sheet.calculate(CellRef::new(1, 0)), Value::Number(7.0));
+        assert_eq!(sheet.calculate(CellRef::new(2, 0)), Value::Number(21.0));
+    }
+
+    #[test]
+    fn reports_every_malformed_cell() {
+        let errors = parse_protocol("4\nVALUE 3 _\nADX $0 4\nADD $x $9\nSUB 1\n")
+            .err()
+            .unwrap();
+
+        assert_eq!(
+            errors,
+            vec![
+                InputError {
+                    line: 3,
+                    column: 1,
+                    kind: InputErrorKind::UnknownOperation("ADX".to_string())
+                },
+                InputError {
+                    line: 4,
+                    column: 5,
+                    kind: InputErrorKind::InvalidReference("$x".to_string())
+                },
+                InputError {
+                    line: 4,
+                    column: 8,
+                    kind: InputErrorKind::ReferenceOutOfRange {
+                        reference: 9,
+                        cells: 4
+                    }
+                },
+                InputError {
+                    line: 5,
+                    column: 6,
+                    kind: InputErrorKind::MissingArgument
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn count_errors() {
+        let errors = parse_protocol("  three\n").err().unwrap();
+        assert_eq!(
+            errors[0].to_string(),
+            "line 1, column 3: expected a cell count, found 'three'"
+        );
+
+        let errors = parse_protocol("2\nVALUE 1 _\n").err().unwrap();
+        assert_eq!(
+            errors[0].to_string(),
+            "line 3, column 1: expected 2 cells, found 1"
+        );
+
+        let errors = parse_protocol("1\nVALUE 1 _ _\n\nVALUE 2 _").err().unwrap();
+        assert_eq!(
+            errors.iter().map(|e| e.to_string()).collect::<Vec<_>>(),
+            [
+                "line 2, column 11: too many arguments",
+                "line 4, column 1: unexpected line after the last cell"
+            ]
+        );
+    }
+
+    #[test]
+    fn empty_line_is_not_a_panic() {
+        let errors = parse_protocol("1\n\n").err().unwrap();
+        assert_eq!(errors[0].kind, InputErrorKind::MissingArgument);
+    }
+}
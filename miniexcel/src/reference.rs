@@ -0,0 +1,346 @@
+use std::fmt;
+
+/// Rows in a sheet, as in Excel: the last one is `1048576`.
+pub const MAX_ROWS: usize = 1_048_576;
+
+/// Columns in a sheet, as in Excel: the last one is `XFD`.
+pub const MAX_COLUMNS: usize = 16_384;
+
+/// Position of a cell in a sheet, zero based. Displayed in A1 notation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CellRef {
+    pub row: usize,
+    pub col: usize,
+}
+
+impl CellRef {
+    pub fn new(row: usize, col: usize) -> CellRef {
+        CellRef { row, col }
+    }
+
+    /// Parses A1 notation (`B3`, `aa10`), returning `None` for anything else,
+    /// including cells past `XFD1048576`.
+    pub fn parse(text: &str) -> Option<CellRef> {
+        let split = text.find(|c: char| !c.is_ascii_alphabetic())?;
+        let (letters, digits) = text.split_at(split);
+        let col = column_index(letters)?;
+        if !digits.chars().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        let row = digits.parse::<usize>().ok()?.checked_sub(1)?;
+        (row < MAX_ROWS).then_some(CellRef { row, col })
+    }
+}
+
+impl fmt::Display for CellRef {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}{}", column_name(self.col), self.row + 1)
+    }
+}
+
+/// Rectangular block of cells, `A1:B3`. `start` is always the top-left and
+/// `end` the bottom-right corner; a single cell has `start == end`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Range {
+    pub start: CellRef,
+    pub end: CellRef,
+}
+
+impl Range {
+    /// Range spanning both corners, given in any order.
+    pub fn new(a: CellRef, b: CellRef) -> Range {
+        Range {
+            start: CellRef::new(a.row.min(b.row), a.col.min(b.col)),
+            end: CellRef::new(a.row.max(b.row), a.col.max(b.col)),
+        }
+    }
+
+    /// Parses `A1:B3`, or a single cell reference.
+    pub fn parse(text: &str) -> Option<Range> {
+        match text.split_once(':') {
+            Some((start, end)) => Some(Range::new(CellRef::parse(start)?, CellRef::parse(end)?)),
+            None => CellRef::parse(text).map(|at| Range::new(at, at)),
+        }
+    }
+
+    pub fn contains(&self, at: CellRef) -> bool {
+        (self.start.row..=self.end.row).contains(&at.row)
+            && (self.start.col..=self.end.col).contains(&at.col)
+    }
+
+    /// Cells in the range in row-major order.
+    pub fn cells(&self) -> impl Iterator<Item = CellRef> {
+        let Range { start, end } = *self;
+        (start.row..=end.row)
+            .flat_map(move |row| (start.col..=end.col).map(move |col| CellRef::new(row, col)))
+    }
+}
+
+impl From<CellRef> for Range {
+    fn from(at: CellRef) -> Range {
+        Range::new(at, at)
+    }
+}
+
+impl fmt::Display for Range {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.start == self.end {
+            write!(f, "{}", self.start)
+        } else {
+            write!(f, "{}:{}", self.start, self.end)
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Axis {
+    Row,
+    Column,
+}
+
+impl Axis {
+    /// Number of rows or columns in a sheet.
+    pub fn size(&self) -> usize {
+        match self {
+            Axis::Row => MAX_ROWS,
+            Axis::Column => MAX_COLUMNS,
+        }
+    }
+}
+
+/// Rows or columns inserted into or deleted from a sheet, which every cell
+/// position and reference has to follow. Inserting pushes the last rows or
+/// columns off the end of the sheet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Shift {
+    pub axis: Axis,
+    pub at: usize,
+    pub count: usize,
+    pub insert: bool,
+}
+
+impl Shift {
+    /// New position of a cell, `None` when the cell was deleted or pushed
+    /// off the sheet.
+    pub fn cell(&self, at: CellRef) -> Option<CellRef> {
+        match self.axis {
+            Axis::Row => Some(CellRef::new(self.index(at.row)?, at.col)),
+            Axis::Column => Some(CellRef::new(at.row, self.index(at.col)?)),
+        }
+    }
+
+    /// New extent of a range: it grows when rows or columns are inserted
+    /// inside it and shrinks when some of them are deleted. `None` when all
+    /// of its cells were deleted.
+    pub fn range(&self, range: Range) -> Option<Range> {
+        let Range { start, end } = range;
+        match self.axis {
+            Axis::Row => {
+                let (first, last) = self.span(start.row, end.row)?;
+                Some(Range::new(
+                    CellRef::new(first, start.col),
+                    CellRef::new(last, end.col),
+                ))
+            }
+            Axis::Column => {
+                let (first, last) = self.span(start.col, end.col)?;
+                Some(Range::new(
+                    CellRef::new(start.row, first),
+                    CellRef::new(end.row, last),
+                ))
+            }
+        }
+    }
+
+    fn index(&self, index: usize) -> Option<usize> {
+        if index < self.at {
+            Some(index)
+        } else if self.insert {
+            index
+                .checked_add(self.count)
+                .filter(|index| *index < self.axis.size())
+        } else if index < self.at.saturating_add(self.count) {
+            None
+        } else {
+            Some(index - self.count)
+        }
+    }
+
+    fn span(&self, first: usize, last: usize) -> Option<(usize, usize)> {
+        if self.insert {
+            // the end of the span may be pushed off the sheet
+            let last = self.index(last).unwrap_or(self.axis.size() - 1);
+            return Some((self.index(first)?, last));
+        }
+        // deleted indices at the edges of the span are cut off
+        let first = self.index(first).unwrap_or(self.at);
+        let last = match self.index(last) {
+            Some(last) => last,
+            None => self.at.checked_sub(1)?,
+        };
+        (first <= last).then_some((first, last))
+    }
+}
+
+/// Sheet names and defined names are compared case-insensitively,
+/// `sheet2!A1` reads `Sheet2`.
+pub(crate) fn same_name(a: &str, b: &str) -> bool {
+    a.to_lowercase() == b.to_lowercase()
+}
+
+/// Spreadsheet column letters: 0 is `A`, 25 is `Z`, 26 is `AA`.
+pub fn column_name(mut col: usize) -> String {
+    let mut name = Vec::new();
+    loop {
+        name.push(b'A' + (col % 26) as u8);
+        if col < 26 {
+            break;
+        }
+        col = col / 26 - 1;
+    }
+    name.reverse();
+    String::from_utf8(name).unwrap()
+}
+
+/// Inverse of [`column_name`], case-insensitive. `None` past `XFD`.
+pub fn column_index(letters: &str) -> Option<usize> {
+    if letters.is_empty() || !letters.chars().all(|c| c.is_ascii_alphabetic()) {
+        return None;
+    }
+    letters
+        .to_ascii_uppercase()
+        .bytes()
+        .try_fold(0usize, |acc, b| {
+            acc.checked_mul(26)?.checked_add((b - b'A') as usize + 1)
+        })
+        .map(|n| n - 1)
+        .filter(|col| *col < MAX_COLUMNS)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn column_names() {
+        assert_eq!(column_name(0), "A");
+        assert_eq!(column_name(25), "Z");
+        assert_eq!(column_name(26), "AA");
+        assert_eq!(column_name(701), "ZZ");
+        assert_eq!(column_name(702), "AAA");
+        for col in [0, 1, 25, 26, 27, 700, 701, 702, 16383] {
+            assert_eq!(column_index(&column_name(col)), Some(col));
+        }
+        assert_eq!(column_index("XFE"), None);
+        assert_eq!(column_index("ZZZZZZZZZZZZZZZ"), None);
+    }
+
+    #[test]
+    fn a1_notation() {
+        assert_eq!(CellRef::parse("A1"), Some(CellRef::new(0, 0)));
+        assert_eq!(CellRef::parse("b3"), Some(CellRef::new(2, 1)));
+        assert_eq!(CellRef::parse("AA10"), Some(CellRef::new(9, 26)));
+        assert_eq!(CellRef::new(9, 26).to_string(), "AA10");
+    }
+
+    #[test]
+    fn invalid_a1_notation() {
+        assert_eq!(CellRef::parse("A0"), None);
+        assert_eq!(CellRef::parse("A"), None);
+        assert_eq!(CellRef::parse("12"), None);
+        assert_eq!(CellRef::parse("A1B"), None);
+        assert_eq!(CellRef::parse("TRUE"), None);
+        assert_eq!(
+            CellRef::parse("XFD1048576"),
+            Some(CellRef::new(MAX_ROWS - 1, MAX_COLUMNS - 1))
+        );
+        assert_eq!(CellRef::parse("XFE1"), None);
+        assert_eq!(CellRef::parse("A1048577"), None);
+    }
+
+    #[test]
+    fn shifts() {
+        let insert = Shift {
+            axis: Axis::Row,
+            at: 2,
+            count: 3,
+            insert: true,
+        };
+        assert_eq!(insert.cell(CellRef::new(1, 4)), Some(CellRef::new(1, 4)));
+        assert_eq!(insert.cell(CellRef::new(2, 4)), Some(CellRef::new(5, 4)));
+        assert_eq!(
+            insert.range(Range::parse("A1:B3").unwrap()),
+            Range::parse("A1:B6")
+        );
+
+        let delete = Shift {
+            axis: Axis::Column,
+            at: 1,
+            count: 2,
+            insert: false,
+        };
+        assert_eq!(delete.cell(CellRef::new(0, 0)), Some(CellRef::new(0, 0)));
+        assert_eq!(delete.cell(CellRef::new(0, 2)), None);
+        assert_eq!(delete.cell(CellRef::new(0, 3)), Some(CellRef::new(0, 1)));
+        assert_eq!(
+            delete.range(Range::parse("A1:D1").unwrap()),
+            Range::parse("A1:B1")
+        );
+        assert_eq!(
+            delete.range(Range::parse("C1:E2").unwrap()),
+            Range::parse("B1:C2")
+        );
+        assert_eq!(delete.range(Range::parse("B1:C9").unwrap()), None);
+    }
+
+    #[test]
+    fn shifts_past_the_end() {
+        let insert = Shift {
+            axis: Axis::Row,
+            at: 0,
+            count: usize::MAX,
+            insert: true,
+        };
+        assert_eq!(insert.cell(CellRef::new(0, 0)), None);
+        let insert = Shift {
+            count: 10,
+            ..insert
+        };
+        assert_eq!(
+            insert.cell(CellRef::new(MAX_ROWS - 11, 0)),
+            Some(CellRef::new(MAX_ROWS - 1, 0))
+        );
+        assert_eq!(insert.cell(CellRef::new(MAX_ROWS - 10, 0)), None);
+        assert_eq!(
+            insert.range(Range::parse("A1:A1048570").unwrap()),
+            Range::parse("A11:A1048576")
+        );
+
+        let delete = Shift {
+            axis: Axis::Column,
+            at: 1,
+            count: usize::MAX,
+            insert: false,
+        };
+        assert_eq!(delete.cell(CellRef::new(0, 0)), Some(CellRef::new(0, 0)));
+        assert_eq!(delete.cell(CellRef::new(0, 2)), None);
+        assert_eq!(
+            delete.range(Range::parse("A1:C3").unwrap()),
+            Range::parse("A1:A3")
+        );
+    }
+
+    #[test]
+    fn ranges() {
+        let range = Range::parse("B3:a1").unwrap();
+        assert_eq!(range.to_string(), "A1:B3");
+        assert_eq!(Range::parse("C2").unwrap().to_string(), "C2");
+        assert!(range.contains(CellRef::new(2, 1)));
+        assert!(!range.contains(CellRef::new(0, 2)));
+        assert_eq!(
+            range.cells().map(|at| at.to_string()).collect::<Vec<_>>(),
+            ["A1", "B1", "A2", "B2", "A3", "B3"]
+        );
+        assert_eq!(Range::parse("A1:"), None);
+    }
+}
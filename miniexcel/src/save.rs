@@ -0,0 +1,297 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value as Json;
+
+use crate::formula::ParseError;
+use crate::name::{Name, NameError};
+use crate::reference::{column_index, column_name, CellRef};
+use crate::sheet::{Cell, Sheet};
+use crate::value::{CellError, Value};
+
+/// Version written by [`Sheet::save`]. Bump it together with a new entry in
+/// [`MIGRATIONS`] whenever the document layout changes.
+pub const FORMAT_VERSION: u64 = 1;
+
+/// Upgrades a raw document by one version, including its `version` field.
+type Migration = fn(Json) -> Result<Json, SaveError>;
+
+/// Migration from version `n` to `n + 1` lives at index `n - 1`. Documents
+/// are migrated one version at a time before being deserialized, so every
+/// migration only has to know about the layout right before it.
+const MIGRATIONS: &[Migration] = &[];
+
+#[derive(Debug)]
+pub enum SaveError {
+    Json(serde_json::Error),
+    MissingVersion,
+    UnsupportedVersion(u64),
+    InvalidReference(String),
+    Formula { cell: CellRef, error: ParseError },
+    Name(NameError),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SaveError::Json(err) => write!(f, "invalid document: {}", err),
+            SaveError::MissingVersion => write!(f, "document has no version"),
+            SaveError::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported document version {} (latest is {})",
+                version, FORMAT_VERSION
+            ),
+            SaveError::InvalidReference(reference) => {
+                write!(f, "invalid reference '{}'", reference)
+            }
+            SaveError::Formula { cell, error } => write!(f, "cell {}: {}", cell, error),
+            SaveError::Name(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for SaveError {}
+
+impl From<serde_json::Error> for SaveError {
+    fn from(err: serde_json::Error) -> SaveError {
+        SaveError::Json(err)
+    }
+}
+
+#[derive(Serialize, Deserialize)]
+struct Document {
+    version: u64,
+    cells: Vec<SavedCell>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    column_widths: BTreeMap<String, f64>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    names: BTreeMap<String, String>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct SavedCell {
+    at: String,
+    input: String,
+    /// Calculated value, only stored for formulas.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    value: Option<SavedValue>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(untagged)]
+enum SavedValue {
+    Number(f64),
+    Bool(bool),
+    Text(String),
+    Error { error: String },
+}
+
+impl SavedValue {
+    fn from_value(value: &Value) -> Option<SavedValue> {
+        match value {
+            Value::Empty => None,
+            Value::Number(n) => Some(SavedValue::Number(*n)),
+            // a sheet on its own always calculates with floats, see `Workbook`
+            Value::Decimal(d) => Some(SavedValue::Number(d.to_f64())),
+            Value::Bool(b) => Some(SavedValue::Bool(*b)),
+            Value::Text(text) => Some(SavedValue::Text(text.clone())),
+            Value::Error(err) => Some(SavedValue::Error {
+                error: err.to_string(),
+            }),
+        }
+    }
+
+    /// Unknown error codes are dropped, the cell is recalculated instead.
+    fn into_value(self) -> Option<Value> {
+        match self {
+            SavedValue::Number(n) => Some(Value::Number(n)),
+            SavedValue::Bool(b) => Some(Value::Bool(b)),
+            SavedValue::Text(text) => Some(Value::Text(text)),
+            SavedValue::Error { error } => CellError::from_code(&error).map(Value::Error),
+        }
+    }
+}
+
+impl Sheet {
+    /// Serializes the sheet, including the calculated value of every formula
+    /// so that a loaded sheet doesn't need to be recalculated.
+    pub fn save(&mut self) -> String {
+        let ats: Vec<CellRef> = self.cells().map(|(at, _)| at).collect();
+        let cells = ats
+            .into_iter()
+            .map(|at| {
+                let value = match self.get(at) {
+                    Some(Cell::Formula(_)) => SavedValue::from_value(&self.calculate(at)),
+                    _ => None,
+                };
+                SavedCell {
+                    at: at.to_string(),
+                    input: self
+                        .get(at)
+                        .map(|cell| cell.to_string())
+                        .unwrap_or_default(),
+                    value,
+                }
+            })
+            .collect();
+        let document = Document {
+            version: FORMAT_VERSION,
+            cells,
+            column_widths: self
+                .column_widths()
+                .map(|(col, width)| (column_name(col), width))
+                .collect(),
+            names: self
+                .names()
+                .map(|(name, definition)| (name.to_string(), definition.to_string()))
+                .collect(),
+        };
+        serde_json::to_string_pretty(&document).unwrap()
+    }
+
+    /// Loads a sheet written by [`Sheet::save`] by this or any earlier version.
+    pub fn load(input: &str) -> Result<Sheet, SaveError> {
+        let document: Document = serde_json::from_value(migrate(serde_json::from_str(input)?)?)?;
+
+        let mut sheet = Sheet::new();
+        let mut cached = Vec::new();
+        for saved in document.cells {
+            let at = parse_ref(&saved.at)?;
+            let cell = Cell::parse(&saved.input)
+                .map_err(|error| SaveError::Formula { cell: at, error })?;
+            sheet.set(at, cell);
+            if let Some(value) = saved.value.and_then(SavedValue::into_value) {
+                cached.push((at, value));
+            }
+        }
+        for (column, width) in document.column_widths {
+            let col = column_index(&column).ok_or(SaveError::InvalidReference(column))?;
+            sheet.set_column_width(col, Some(width));
+        }
+        for (name, definition) in document.names {
+            let definition =
+                Name::parse(&definition).ok_or(SaveError::InvalidReference(definition))?;
+            sheet
+                .define_name(&name, definition)
+                .map_err(SaveError::Name)?;
+        }
+        // after all edits, which would otherwise throw the values away again
+        for (at, value) in cached {
+            sheet.restore_cached(at, value);
+        }
+        Ok(sheet)
+    }
+}
+
+fn parse_ref(text: &str) -> Result<CellRef, SaveError> {
+    CellRef::parse(text).ok_or_else(|| SaveError::InvalidReference(text.to_string()))
+}
+
+/// Brings a document of any supported version up to [`FORMAT_VERSION`].
+fn migrate(mut document: Json) -> Result<Json, SaveError> {
+    let version = document
+        .get("version")
+        .and_then(Json::as_u64)
+        .ok_or(SaveError::MissingVersion)?;
+    if version == 0 || version > FORMAT_VERSION {
+        return Err(SaveError::UnsupportedVersion(version));
+    }
+    for migration in &MIGRATIONS[(version - 1) as usize..] {
+        document = migration(document)?;
+    }
+    Ok(document)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reference::Range;
+
+    fn sheet() -> Sheet {
+        let mut sheet = Sheet::from_csv("2,\"\"\"12\"\"\",=A1*3\ntext,=1/0\n").unwrap();
+        sheet.set_column_width(0, Some(12.5));
+        sheet.set_column_width(27, Some(4.0));
+        sheet
+            .define_name("Total", Range::parse("C1").unwrap())
+            .unwrap();
+        sheet
+            .define_name("Inputs", Range::parse("A1:B2").unwrap())
+            .unwrap();
+        sheet.define_name("Rate", Value::Number(0.25)).unwrap();
+        sheet
+    }
+
+    #[test]
+    fn round_trip() {
+        let mut original = sheet();
+        let saved = original.save();
+        let mut loaded = Sheet::load(&saved).unwrap();
+
+        assert_eq!(
+            original.cells().collect::<Vec<_>>(),
+            loaded.cells().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            original.column_widths().collect::<Vec<_>>(),
+            loaded.column_widths().collect::<Vec<_>>()
+        );
+        assert_eq!(
+            original.names().collect::<Vec<_>>(),
+            loaded.names().collect::<Vec<_>>()
+        );
+        assert_eq!(loaded.save(), saved);
+    }
+
+    #[test]
+    fn cached_values_are_restored() {
+        let loaded = Sheet::load(&sheet().save()).unwrap();
+
+        assert_eq!(loaded.cached(CellRef::new(0, 2)), Some(&Value::Number(6.0)));
+        assert_eq!(
+            loaded.cached(CellRef::new(1, 1)),
+            Some(&Value::Error(CellError::DivByZero))
+        );
+    }
+
+    #[test]
+    fn document_layout() {
+        let mut sheet = Sheet::from_csv("1,=A1+1\n").unwrap();
+        sheet.set_column_width(1, Some(20.0));
+        let document: Json = serde_json::from_str(&sheet.save()).unwrap();
+
+        assert_eq!(
+            document,
+            serde_json::json!({
+                "version": 1,
+                "cells": [
+                    { "at": "A1", "input": "1" },
+                    { "at": "B1", "input": "=A1+1", "value": 2.0 },
+                ],
+                "column_widths": { "B": 20.0 },
+            })
+        );
+    }
+
+    #[test]
+    fn load_errors() {
+        assert!(matches!(Sheet::load("{"), Err(SaveError::Json(_))));
+        assert!(matches!(
+            Sheet::load(r#"{ "cells": [] }"#),
+            Err(SaveError::MissingVersion)
+        ));
+        assert!(matches!(
+            Sheet::load(r#"{ "version": 99, "cells": [] }"#),
+            Err(SaveError::UnsupportedVersion(99))
+        ));
+        assert!(matches!(
+            Sheet::load(r#"{ "version": 1, "cells": [{ "at": "1A", "input": "1" }] }"#),
+            Err(SaveError::InvalidReference(_))
+        ));
+        assert!(matches!(
+            Sheet::load(r#"{ "version": 1, "cells": [{ "at": "A1", "input": "=1+" }] }"#),
+            Err(SaveError::Formula { .. })
+        ));
+    }
+}
@@ -0,0 +1,513 @@
+//! Line oriented commands to drive a single sheet from scripts, one per line:
+//!
+//! ```text
+//! # comments and blank lines are skipped
+//! set A1 2
+//! set A2 =A1*2            # the rest of the line is the cell input
+//! set A3                  # clears the cell
+//! get A2                  # prints the value, ranges print a row per line
+//! explain A2
+//! insert row 5            # also `rows`, `column C`, `columns C 2`
+//! delete column B
+//! name TaxRate C2         # defines a name for cells or a constant
+//! undo
+//! redo
+//! import csv in.csv       # also `import protocol in.minix`, `import xlsx in.xlsx`
+//! export csv out.csv      # values, or `export csv out.csv formulas`
+//! export xlsx out.xlsx
+//! save book.json
+//! open book.json
+//! ```
+//!
+//! Scripts only ever see that one [`Sheet`]: references to other sheets,
+//! `Data!A1`, read as `#REF!` and `name` defines names of the sheet. Several
+//! sheets and workbook-wide names need a [`Workbook`](crate::Workbook).
+
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io::{self, BufRead, Write};
+use std::thread;
+
+use crate::csv::CsvExport;
+use crate::formula::ParseError;
+use crate::name::{Name, NameError};
+use crate::protocol::parse_protocol;
+use crate::reference::{column_index, Axis, CellRef, Range, MAX_ROWS};
+use crate::sheet::{Cell, Edit, Sheet};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Set {
+        at: CellRef,
+        cell: Cell,
+    },
+    Get(Range),
+    Explain(CellRef),
+    Insert {
+        axis: Axis,
+        at: usize,
+        count: usize,
+    },
+    Delete {
+        axis: Axis,
+        at: usize,
+        count: usize,
+    },
+    Name {
+        name: String,
+        definition: Name,
+    },
+    Undo,
+    Redo,
+    ImportCsv(String),
+    /// Reads the first sheet, printing the formulas that aren't supported.
+    ImportXlsx(String),
+    ImportProtocol(String),
+    ExportCsv {
+        path: String,
+        export: CsvExport,
+    },
+    ExportXlsx(String),
+    Save(String),
+    Open(String),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptErrorKind {
+    UnknownCommand(String),
+    /// The arguments don't fit the command, which is shown as it is used.
+    Usage(&'static str),
+    InvalidReference(String),
+    Formula(ParseError),
+    Name(NameError),
+    /// Reading or writing a file failed, or the file isn't valid.
+    File {
+        path: String,
+        message: String,
+    },
+}
+
+/// A command that couldn't be parsed or run. `line` is one based.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ScriptError {
+    pub line: usize,
+    pub kind: ScriptErrorKind,
+}
+
+impl fmt::Display for ScriptError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: ", self.line)?;
+        match &self.kind {
+            ScriptErrorKind::UnknownCommand(command) => {
+                write!(f, "unknown command '{}'", command)
+            }
+            ScriptErrorKind::Usage(usage) => write!(f, "usage: {}", usage),
+            ScriptErrorKind::InvalidReference(reference) => {
+                write!(f, "invalid reference '{}'", reference)
+            }
+            ScriptErrorKind::Formula(err) => write!(f, "{}", err),
+            ScriptErrorKind::Name(err) => write!(f, "{}", err),
+            ScriptErrorKind::File { path, message } => write!(f, "{}: {}", path, message),
+        }
+    }
+}
+
+impl Error for ScriptError {}
+
+const SET: &str = "set CELL [INPUT]";
+const GET: &str = "get CELL|RANGE";
+const EXPLAIN: &str = "explain CELL";
+const INSERT: &str = "insert row|rows|column|columns AT [COUNT]";
+const DELETE: &str = "delete row|rows|column|columns AT [COUNT]";
+const NAME: &str = "name NAME CELL|RANGE|VALUE";
+const IMPORT: &str = "import csv|protocol|xlsx FILE";
+const EXPORT: &str = "export csv FILE [values|formulas] | export xlsx FILE";
+const SAVE: &str = "save FILE";
+const OPEN: &str = "open FILE";
+
+impl Command {
+    /// Parses one line of a script, `None` for blank lines and comments.
+    pub fn parse(line: &str) -> Result<Option<Command>, ScriptErrorKind> {
+        let line = line.trim_start();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(None);
+        }
+        let (command, rest) = split_word(line);
+        if command == "set" {
+            // the input is taken as is, it may contain spaces and `#`
+            let (at, input) = split_word(rest);
+            let at = cell(at, SET)?;
+            let cell = Cell::parse(input.trim_end()).map_err(ScriptErrorKind::Formula)?;
+            return Ok(Some(Command::Set { at, cell }));
+        }
+
+        let args: Vec<&str> = strip_comment(rest).split_whitespace().collect();
+        let command = match (command, args.as_slice()) {
+            ("get", [range]) => Command::Get(
+                Range::parse(range)
+                    .ok_or_else(|| ScriptErrorKind::InvalidReference(range.to_string()))?,
+            ),
+            ("get", _) => return Err(ScriptErrorKind::Usage(GET)),
+            ("explain", [at]) => Command::Explain(cell(at, EXPLAIN)?),
+            ("explain", _) => return Err(ScriptErrorKind::Usage(EXPLAIN)),
+            ("insert", args) => {
+                let (axis, at, count) = structural(args, INSERT)?;
+                Command::Insert { axis, at, count }
+            }
+            ("delete", args) => {
+                let (axis, at, count) = structural(args, DELETE)?;
+                Command::Delete { axis, at, count }
+            }
+            ("name", [name, definition]) => Command::Name {
+                name: name.to_string(),
+                definition: Name::parse(definition)
+                    .ok_or_else(|| ScriptErrorKind::InvalidReference(definition.to_string()))?,
+            },
+            ("name", _) => return Err(ScriptErrorKind::Usage(NAME)),
+            ("undo", []) => Command::Undo,
+            ("redo", []) => Command::Redo,
+            ("import", ["csv", path]) => Command::ImportCsv(path.to_string()),
+            ("import", ["xlsx", path]) => Command::ImportXlsx(path.to_string()),
+            ("import", ["protocol", path]) => Command::ImportProtocol(path.to_string()),
+            ("import", _) => return Err(ScriptErrorKind::Usage(IMPORT)),
+            ("export", ["csv", path, rest @ ..]) => {
+                let export = match rest {
+                    [] | ["values"] => CsvExport::Values,
+                    ["formulas"] => CsvExport::Formulas,
+                    _ => return Err(ScriptErrorKind::Usage(EXPORT)),
+                };
+                Command::ExportCsv {
+                    path: path.to_string(),
+                    export,
+                }
+            }
+            ("export", ["xlsx", path]) => Command::ExportXlsx(path.to_string()),
+            ("export", _) => return Err(ScriptErrorKind::Usage(EXPORT)),
+            ("save", [path]) => Command::Save(path.to_string()),
+            ("save", _) => return Err(ScriptErrorKind::Usage(SAVE)),
+            ("open", [path]) => Command::Open(path.to_string()),
+            ("open", _) => return Err(ScriptErrorKind::Usage(OPEN)),
+            (command, _) => return Err(ScriptErrorKind::UnknownCommand(command.to_string())),
+        };
+        Ok(Some(command))
+    }
+}
+
+fn split_word(line: &str) -> (&str, &str) {
+    let line = line.trim_start();
+    match line.find(char::is_whitespace) {
+        Some(end) => (&line[..end], line[end..].trim_start()),
+        None => (line, ""),
+    }
+}
+
+fn strip_comment(line: &str) -> &str {
+    line.split_once('#').map_or(line, |(line, _)| line)
+}
+
+fn cell(text: &str, usage: &'static str) -> Result<CellRef, ScriptErrorKind> {
+    if text.is_empty() {
+        return Err(ScriptErrorKind::Usage(usage));
+    }
+    CellRef::parse(text).ok_or_else(|| ScriptErrorKind::InvalidReference(text.to_string()))
+}
+
+/// Arguments of `insert` and `delete`: rows are numbered from 1 and columns
+/// are given by their letters, like they are shown. The rows or columns
+/// have to be on the sheet.
+fn structural(args: &[&str], usage: &'static str) -> Result<(Axis, usize, usize), ScriptErrorKind> {
+    let (axis, at, count) = match args {
+        [axis, at] => (axis, at, None),
+        [axis, at, count] => (axis, at, Some(count)),
+        _ => return Err(ScriptErrorKind::Usage(usage)),
+    };
+    let invalid = || ScriptErrorKind::InvalidReference(at.to_string());
+    let (axis, at) = match *axis {
+        "row" | "rows" => {
+            let row = at.parse::<usize>().map_err(|_| invalid())?;
+            let row = row.checked_sub(1).filter(|row| *row < MAX_ROWS);
+            (Axis::Row, row.ok_or_else(invalid)?)
+        }
+        "column" | "columns" => (Axis::Column, column_index(at).ok_or_else(invalid)?),
+        _ => return Err(ScriptErrorKind::Usage(usage)),
+    };
+    let count = match count {
+        None => 1,
+        Some(count) => match count.parse::<usize>() {
+            Ok(count) if count > 0 && count <= axis.size() - at => count,
+            _ => return Err(ScriptErrorKind::Usage(usage)),
+        },
+    };
+    Ok((axis, at, count))
+}
+
+impl Sheet {
+    /// Runs a command, writing what it prints to `out`.
+    pub fn run(&mut self, command: Command, out: &mut dyn Write) -> Result<(), ScriptErrorKind> {
+        let io_error = |err: io::Error| ScriptErrorKind::File {
+            path: "<output>".to_string(),
+            message: err.to_string(),
+        };
+        match command {
+            Command::Set { at, cell } => self.apply(Edit::Set { at, cell }),
+            Command::Get(range) => {
+                self.calculate_for(range);
+                for row in range.start.row..=range.end.row {
+                    let values: Vec<String> = (range.start.col..=range.end.col)
+                        .map(|col| self.calculate(CellRef::new(row, col)).to_string())
+                        .collect();
+                    writeln!(out, "{}", values.join("\t")).map_err(io_error)?;
+                }
+            }
+            Command::Explain(at) => {
+                self.calculate_for(at.into());
+                write!(out, "{}", self.explain(at)).map_err(io_error)?
+            }
+            Command::Insert { axis, at, count } => self.apply(match axis {
+                Axis::Row => Edit::InsertRows { at, count },
+                Axis::Column => Edit::InsertColumns { at, count },
+            }),
+            Command::Delete { axis, at, count } => self.apply(match axis {
+                Axis::Row => Edit::DeleteRows { at, count },
+                Axis::Column => Edit::DeleteColumns { at, count },
+            }),
+            Command::Name { name, definition } => self
+                .define_name(&name, definition)
+                .map_err(ScriptErrorKind::Name)?,
+            Command::Undo => {
+                self.undo();
+            }
+            Command::Redo => {
+                self.redo();
+            }
+            Command::ImportCsv(path) => {
+                let input = read(&path)?;
+                *self = Sheet::from_csv(&input).map_err(|err| file_error(&path, err))?;
+            }
+            Command::ImportProtocol(path) => {
+                let input = read(&path)?;
+                *self = parse_protocol(&input).map_err(|errors| {
+                    let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+                    file_error(&path, messages.join("; "))
+                })?;
+            }
+            Command::ImportXlsx(path) => {
+                let input = fs::read(&path).map_err(|err| file_error(&path, err))?;
+                let (sheet, unsupported) =
+                    Sheet::from_xlsx(&input).map_err(|err| file_error(&path, err))?;
+                *self = sheet;
+                for item in unsupported {
+                    writeln!(out, "unsupported: {}", item).map_err(io_error)?;
+                }
+            }
+            Command::ExportCsv { path, export } => write(&path, &self.to_csv(export))?,
+            Command::ExportXlsx(path) => {
+                let output = self.to_xlsx().map_err(|err| file_error(&path, err))?;
+                fs::write(&path, output).map_err(|err| file_error(&path, err))?;
+            }
+            Command::Save(path) => write(&path, &self.save())?,
+            Command::Open(path) => {
+                let input = read(&path)?;
+                *self = Sheet::load(&input).map_err(|err| file_error(&path, err))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Calculates the whole sheet on all cores unless the cells of `range`
+    /// are up to date. Calculating them one by one would recurse down long
+    /// chains of formulas.
+    fn calculate_for(&mut self, range: Range) {
+        if self.cells_in(range).any(|at| self.cached(at).is_none()) {
+            self.calculate_all(thread::available_parallelism().map_or(1, usize::from));
+        }
+    }
+
+    /// Runs a script line by line until it ends or a line fails.
+    pub fn run_script(
+        &mut self,
+        script: impl BufRead,
+        out: &mut dyn Write,
+    ) -> Result<(), ScriptError> {
+        for (index, line) in script.lines().enumerate() {
+            let error = |kind| ScriptError {
+                line: index + 1,
+                kind,
+            };
+            let line = line.map_err(|err| error(file_error("<input>", err)))?;
+            if let Some(command) = Command::parse(&line).map_err(error)? {
+                self.run(command, out).map_err(error)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+fn file_error(path: &str, err: impl fmt::Display) -> ScriptErrorKind {
+    ScriptErrorKind::File {
+        path: path.to_string(),
+        message: err.to_string(),
+    }
+}
+
+fn read(path: &str) -> Result<String, ScriptErrorKind> {
+    fs::read_to_string(path).map_err(|err| file_error(path, err))
+}
+
+fn write(path: &str, contents: &str) -> Result<(), ScriptErrorKind> {
+    fs::write(path, contents).map_err(|err| file_error(path, err))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::value::Value;
+
+    fn run(sheet: &mut Sheet, script: &str) -> Result<String, ScriptError> {
+        let mut out = Vec::new();
+        sheet.run_script(script.as_bytes(), &mut out)?;
+        Ok(String::from_utf8(out).unwrap())
+    }
+
+    #[test]
+    fn set_and_get() {
+        let mut sheet = Sheet::new();
+        let out = run(
+            &mut sheet,
+            "# a comment\n\
+             set A1 2\n\
+             set B2 =A1 * 2  \n\
+             \n\
+             set C1 a # not a comment\n\
+             get B2\n\
+             get A1:C2 # values of a block\n",
+        )
+        .unwrap();
+
+        assert_eq!(out, "4\n2\t\ta # not a comment\n\t4\t\n");
+    }
+
+    #[test]
+    fn structural_edits_and_undo() {
+        let mut sheet = Sheet::new();
+        run(
+            &mut sheet,
+            "set A1 1\nset A2 =A1+1\ninsert row 1\ninsert columns A 2\nundo\nset A5 =A2",
+        )
+        .unwrap();
+
+        assert_eq!(sheet.get(CellRef::new(2, 0)).unwrap().to_string(), "=A2+1");
+        assert_eq!(sheet.calculate(CellRef::new(4, 0)), Value::Number(1.0));
+        run(&mut sheet, "delete rows 1 2\nget A1").unwrap();
+        assert_eq!(
+            sheet.get(CellRef::new(0, 0)).unwrap().to_string(),
+            "=#REF!+1"
+        );
+    }
+
+    #[test]
+    fn names_and_explain() {
+        let mut sheet = Sheet::new();
+        let out = run(
+            &mut sheet,
+            "set C2 0.5\nname TaxRate C2\nname Fee 2\nset A1 =TaxRate*10+Fee\nget A1\nexplain A1",
+        )
+        .unwrap();
+
+        assert_eq!(
+            out,
+            "7\nA1: =TaxRate*10+Fee = 7\n├─ TaxRate*10 = 5\n│  └─ TaxRate: C2 = 0.5\n│     └─ C2 = 0.5\n└─ Fee: 2 = 2\n"
+        );
+    }
+
+    #[test]
+    fn files() {
+        let dir = std::env::temp_dir().join(format!("miniexcel-script-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        fs::write(path("in.minix"), "2\nVALUE 3 _\nMULT $0 $0\n").unwrap();
+
+        let mut sheet = Sheet::new();
+        let script = format!(
+            "import protocol {}\nexport csv {}\nexport csv {} formulas\nsave {}\nexport xlsx {}\n\
+             set A1 1\nopen {}\nget A2\nset A1 1\nimport xlsx {}\nget A2",
+            path("in.minix"),
+            path("values.csv"),
+            path("formulas.csv"),
+            path("book.json"),
+            path("book.xlsx"),
+            path("book.json"),
+            path("book.xlsx"),
+        );
+        let out = run(&mut sheet, &script).unwrap();
+
+        assert_eq!(out, "9\n9\n");
+        assert_eq!(fs::read_to_string(path("values.csv")).unwrap(), "3\n9\n");
+        assert_eq!(
+            fs::read_to_string(path("formulas.csv")).unwrap(),
+            "=3\n=A1*A1\n"
+        );
+        let mut imported = Sheet::new();
+        run(
+            &mut imported,
+            &format!("import csv {}", path("formulas.csv")),
+        )
+        .unwrap();
+        assert_eq!(imported.calculate(CellRef::new(1, 0)), Value::Number(9.0));
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn errors_stop_the_script() {
+        let mut sheet = Sheet::new();
+        let err = run(&mut sheet, "set A1 1\nset B1 =1+\nset A1 2").unwrap_err();
+
+        assert_eq!(err.line, 2);
+        assert!(matches!(err.kind, ScriptErrorKind::Formula(_)));
+        assert_eq!(sheet.calculate(CellRef::new(0, 0)), Value::Number(1.0));
+    }
+
+    #[test]
+    fn parse_errors() {
+        let message = |line: &str| {
+            ScriptError {
+                line: 1,
+                kind: Command::parse(line).unwrap_err(),
+            }
+            .to_string()
+        };
+
+        assert_eq!(
+            message("frobnicate A1"),
+            "line 1: unknown command 'frobnicate'"
+        );
+        assert_eq!(message("set"), "line 1: usage: set CELL [INPUT]");
+        assert_eq!(message("get 1A"), "line 1: invalid reference '1A'");
+        assert_eq!(message("insert row 0"), "line 1: invalid reference '0'");
+        assert_eq!(
+            message("insert row 1048577"),
+            "line 1: invalid reference '1048577'"
+        );
+        assert_eq!(
+            message("delete rows 2 18446744073709551615"),
+            "line 1: usage: delete row|rows|column|columns AT [COUNT]"
+        );
+        assert_eq!(
+            message("insert columns XFD 2"),
+            "line 1: usage: insert row|rows|column|columns AT [COUNT]"
+        );
+        assert_eq!(
+            message("insert cell 5"),
+            "line 1: usage: insert row|rows|column|columns AT [COUNT]"
+        );
+        assert_eq!(
+            message("export csv out.csv everything"),
+            "line 1: usage: export csv FILE [values|formulas] | export xlsx FILE"
+        );
+        assert_eq!(
+            Command::parse("open /nonexistent/book.json").unwrap(),
+            Some(Command::Open("/nonexistent/book.json".to_string()))
+        );
+    }
+}
@@ -0,0 +1,541 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::mem;
+
+mod edit;
+mod explain;
+mod parallel;
+
+pub use edit::Edit;
+pub use explain::Trace;
+
+use crate::decimal::Arithmetic;
+use crate::formula::{Expr, Operation, ParseError};
+use crate::function::Arg;
+use crate::name::{check_name, Name, NameError};
+use crate::reference::{same_name, CellRef, Range, Shift, MAX_COLUMNS};
+use crate::value::{CellError, Value};
+use edit::History;
+
+/// Content of a cell: either a plain value or a formula to calculate.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Cell {
+    Literal(Value),
+    Formula(Expr),
+}
+
+impl Cell {
+    /// Parses cell input the way it is typed: `=` starts a formula, anything
+    /// else is a literal.
+    pub fn parse(input: &str) -> Result<Cell, ParseError> {
+        match input.strip_prefix('=') {
+            Some(formula) => Ok(Cell::Formula(Expr::parse(formula)?)),
+            None => Ok(Cell::Literal(Value::from_literal(input))),
+        }
+    }
+}
+
+/// Shows the cell as it would be typed, so that [`Cell::parse`] reads it back.
+impl fmt::Display for Cell {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Cell::Formula(expr) => write!(f, "={}", expr),
+            Cell::Literal(Value::Text(text)) => {
+                if Value::from_literal(text) == Value::Text(text.clone()) && !text.starts_with('=')
+                {
+                    write!(f, "{}", text)
+                } else {
+                    write!(f, "\"{}\"", text)
+                }
+            }
+            Cell::Literal(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct Sheet {
+    cells: BTreeMap<CellRef, Cell>,
+    cache: HashMap<CellRef, Value>,
+    column_widths: BTreeMap<usize, f64>,
+    names: BTreeMap<String, Name>,
+    history: History,
+}
+
+impl Sheet {
+    pub fn new() -> Sheet {
+        Sheet {
+            cells: BTreeMap::new(),
+            cache: HashMap::new(),
+            column_widths: BTreeMap::new(),
+            names: BTreeMap::new(),
+            history: History::default(),
+        }
+    }
+
+    /// Replaces the content of a cell without recording it for undo, see
+    /// [`Sheet::apply`] for undoable edits.
+    pub fn set(&mut self, at: CellRef, cell: Cell) {
+        if cell == Cell::Literal(Value::Empty) {
+            self.cells.remove(&at);
+        } else {
+            self.cells.insert(at, cell);
+        }
+        self.cache.clear();
+    }
+
+    pub fn get(&self, at: CellRef) -> Option<&Cell> {
+        self.cells.get(&at)
+    }
+
+    /// Non-empty cells in row-major order.
+    pub fn cells(&self) -> impl Iterator<Item = (CellRef, &Cell)> {
+        self.cells.iter().map(|(at, cell)| (*at, cell))
+    }
+
+    /// Non-empty cells inside `range` in row-major order, without walking
+    /// the empty ones.
+    pub(crate) fn cells_in(&self, range: Range) -> impl Iterator<Item = CellRef> + '_ {
+        self.cells
+            .range(range.start..=range.end)
+            .map(|(at, _)| *at)
+            .filter(move |at| range.contains(*at))
+    }
+
+    /// Number of rows up to the last one holding a cell.
+    fn used_rows(&self) -> usize {
+        self.cells.last_key_value().map_or(0, |(at, _)| at.row + 1)
+    }
+
+    /// Number of rows and columns spanned by the non-empty cells, counted
+    /// from `A1`.
+    pub fn dimensions(&self) -> (usize, usize) {
+        self.cells.keys().fold((0, 0), |(rows, cols), at| {
+            (rows.max(at.row + 1), cols.max(at.col + 1))
+        })
+    }
+
+    /// Width of a column in characters, `None` when it has the default width.
+    pub fn column_width(&self, col: usize) -> Option<f64> {
+        self.column_widths.get(&col).copied()
+    }
+
+    /// Sets the width of a column, ignored past the last column, `XFD`.
+    pub fn set_column_width(&mut self, col: usize, width: Option<f64>) {
+        if col >= MAX_COLUMNS {
+            return;
+        }
+        match width {
+            Some(width) => self.column_widths.insert(col, width),
+            None => self.column_widths.remove(&col),
+        };
+    }
+
+    pub fn column_widths(&self) -> impl Iterator<Item = (usize, f64)> + '_ {
+        self.column_widths.iter().map(|(col, width)| (*col, *width))
+    }
+
+    /// Defines a name for formulas on this sheet, replacing any definition
+    /// it had. Cells without a sheet are on this sheet.
+    pub fn define_name(
+        &mut self,
+        name: &str,
+        definition: impl Into<Name>,
+    ) -> Result<(), NameError> {
+        check_name(name)?;
+        self.names.retain(|defined, _| !same_name(defined, name));
+        self.names.insert(name.to_string(), definition.into());
+        self.cache.clear();
+        Ok(())
+    }
+
+    /// Removes a name, formulas still using it show `#NAME?`.
+    pub fn remove_name(&mut self, name: &str) -> Result<Name, NameError> {
+        let defined = self.defined_name(name)?;
+        self.cache.clear();
+        Ok(self.names.remove(&defined).unwrap())
+    }
+
+    /// Renames a name along with every formula on this sheet using it.
+    pub fn rename_name(&mut self, from: &str, to: &str) -> Result<(), NameError> {
+        let defined = self.defined_name(from)?;
+        check_name(to)?;
+        if self
+            .names
+            .keys()
+            .any(|other| *other != defined && same_name(other, to))
+        {
+            return Err(NameError::DuplicateName(to.to_string()));
+        }
+        let definition = self.names.remove(&defined).unwrap();
+        self.names.insert(to.to_string(), definition);
+        self.rewrite_formulas(|expr| expr.name_renamed(from, to));
+        Ok(())
+    }
+
+    /// What a name stands for, names are case-insensitive.
+    pub fn name(&self, name: &str) -> Option<&Name> {
+        self.names
+            .iter()
+            .find(|(defined, _)| same_name(defined, name))
+            .map(|(_, definition)| definition)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = (&str, &Name)> {
+        self.names
+            .iter()
+            .map(|(name, definition)| (name.as_str(), definition))
+    }
+
+    /// The name as it was defined, which may differ in case from `name`.
+    fn defined_name(&self, name: &str) -> Result<String, NameError> {
+        self.names
+            .keys()
+            .find(|defined| same_name(defined, name))
+            .cloned()
+            .ok_or_else(|| NameError::UnknownName(name.to_string()))
+    }
+
+    /// Replaces every formula with `rewrite(formula)`, outside of the undo
+    /// history, e.g. to follow a renamed name or sheet.
+    pub(crate) fn rewrite_formulas(&mut self, rewrite: impl Fn(&Expr) -> Expr) {
+        for cell in self.cells.values_mut() {
+            if let Cell::Formula(expr) = cell {
+                *expr = rewrite(expr);
+            }
+        }
+        self.cache.clear();
+    }
+
+    /// Points the names defined for this sheet that refer to the sheet `from`
+    /// at `to`, or at `#REF!`.
+    pub(crate) fn rename_sheet_in_names(&mut self, from: &str, to: Option<&str>) {
+        for definition in self.names.values_mut() {
+            *definition = definition.sheet_renamed(from, to);
+        }
+    }
+
+    /// Moves the cells of names defined for this sheet that refer to the
+    /// sheet `sheet` along with its rows or columns, dropping the names whose
+    /// cells were all deleted.
+    pub(crate) fn shift_sheet_in_names(&mut self, sheet: &str, shift: &Shift) {
+        self.names = mem::take(&mut self.names)
+            .into_iter()
+            .filter_map(|(name, definition)| Some((name, definition.sheet_shifted(sheet, shift)?)))
+            .collect();
+    }
+
+    /// Value of the cell from the last calculation, if it is still valid.
+    pub fn cached(&self, at: CellRef) -> Option<&Value> {
+        self.cache.get(&at)
+    }
+
+    /// Seeds the cache with a value calculated elsewhere, e.g. read from a
+    /// saved file, so the cell isn't recalculated until the sheet changes.
+    pub(crate) fn restore_cached(&mut self, at: CellRef, value: Value) {
+        self.cache.insert(at, value);
+    }
+
+    pub fn calculate(&mut self, at: CellRef) -> Value {
+        if let Some(value) = self.cache.get(&at) {
+            return value.clone();
+        }
+        let value = match self.cells.get(&at).cloned() {
+            None => Value::Empty,
+            Some(Cell::Literal(value)) => value,
+            Some(Cell::Formula(expr)) => {
+                // a reference back to this cell while it is being calculated
+                // is circular and reads as #REF!
+                self.cache.insert(at, Value::Error(CellError::Ref));
+                evaluate(&expr, self)
+            }
+        };
+        self.cache.insert(at, value.clone());
+        value
+    }
+}
+
+impl Resolve for Sheet {
+    fn cell(&mut self, sheet: Option<&str>, at: CellRef) -> Value {
+        match sheet {
+            None => self.calculate(at),
+            // a sheet on its own can't see other sheets, see `Workbook`
+            Some(_) => Value::Error(CellError::Ref),
+        }
+    }
+
+    fn name(&self, name: &str) -> Option<Name> {
+        Sheet::name(self, name).cloned()
+    }
+
+    fn rows(&self, sheet: Option<&str>) -> Option<usize> {
+        sheet.is_none().then(|| self.used_rows())
+    }
+}
+
+/// Where a formula being calculated gets the cells and names it reads.
+pub(crate) trait Resolve {
+    /// Value of a cell on `sheet`, or on the formula's own sheet for `None`.
+    fn cell(&mut self, sheet: Option<&str>, at: CellRef) -> Value;
+
+    /// What a name visible to the formula stands for.
+    fn name(&self, name: &str) -> Option<Name>;
+
+    /// Number of rows of `sheet`, or of the formula's own sheet for `None`,
+    /// up to the last one holding anything. `None` for unknown sheets.
+    fn rows(&self, sheet: Option<&str>) -> Option<usize>;
+
+    /// How the formula calculates numbers.
+    fn arithmetic(&self) -> Arithmetic {
+        Arithmetic::Float
+    }
+}
+
+/// Calculates a formula, looking up the cells and names it uses with
+/// `resolve`, and turns the result into the kind of number its arithmetic
+/// uses.
+pub(crate) fn evaluate(expr: &Expr, resolve: &mut dyn Resolve) -> Value {
+    let arithmetic = resolve.arithmetic();
+    let value = match expr {
+        Expr::Literal(value) => value.clone(),
+        Expr::Ref(at) => resolve.cell(None, *at),
+        Expr::Range { sheet, range } if range.start == range.end => {
+            resolve.cell(sheet.as_deref(), range.start)
+        }
+        // a block of cells has no single value
+        Expr::Range { .. } => Value::Error(CellError::Value),
+        Expr::Name(name) => match resolve.name(name) {
+            Some(definition) => evaluate(&definition.to_expr(), resolve),
+            None => Value::Error(CellError::Name),
+        },
+        Expr::Neg(expr) => {
+            Value::Number(0.0).calculate(Operation::Sub, &evaluate(expr, resolve), arithmetic)
+        }
+        Expr::Binary(op, lhs, rhs) => {
+            let lhs = evaluate(lhs, resolve);
+            let rhs = evaluate(rhs, resolve);
+            lhs.calculate(*op, &rhs, arithmetic)
+        }
+        Expr::Call(function, args) => {
+            let args: Vec<Arg> = args.iter().map(|arg| argument(arg, resolve)).collect();
+            function.call(&args, arithmetic)
+        }
+    };
+    value.for_arithmetic(arithmetic)
+}
+
+/// Evaluates a function argument, keeping references as ranges.
+fn argument(expr: &Expr, resolve: &mut dyn Resolve) -> Arg {
+    match expr {
+        Expr::Ref(at) => Arg::Range {
+            values: vec![resolve.cell(None, *at)],
+            rows: 1,
+            columns: 1,
+        },
+        Expr::Range { sheet, range } => {
+            let Some(used) = resolve.rows(sheet.as_deref()) else {
+                return Arg::Value(Value::Error(CellError::Ref));
+            };
+            // the rows below the last one holding anything are all empty,
+            // which keeps ranges like `A1:XFD1048576` cheap
+            let Range { start, end } = *range;
+            let values = match (end.row + 1).min(used) {
+                last if last > start.row => Range::new(start, CellRef::new(last - 1, end.col))
+                    .cells()
+                    .map(|at| resolve.cell(sheet.as_deref(), at))
+                    .collect(),
+                _ => Vec::new(),
+            };
+            Arg::Range {
+                values,
+                rows: end.row - start.row + 1,
+                columns: end.col - start.col + 1,
+            }
+        }
+        Expr::Name(name) => match resolve.name(name) {
+            Some(definition) => argument(&definition.to_expr(), resolve),
+            None => Arg::Value(Value::Error(CellError::Name)),
+        },
+        _ => Arg::Value(evaluate(expr, resolve)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reference::Range;
+
+    fn set(sheet: &mut Sheet, at: &str, input: &str) {
+        sheet.set(CellRef::parse(at).unwrap(), Cell::parse(input).unwrap());
+    }
+
+    fn calculate(sheet: &mut Sheet, at: &str) -> Value {
+        sheet.calculate(CellRef::parse(at).unwrap())
+    }
+
+    #[test]
+    fn formulas() {
+        let mut sheet = Sheet::new();
+        set(&mut sheet, "A1", "1.5");
+        set(&mut sheet, "A2", "=A1+TRUE");
+        set(&mut sheet, "B1", "=A2*(A1-0.5)");
+        set(&mut sheet, "B2", "text");
+
+        assert_eq!(calculate(&mut sheet, "B1"), Value::Number(2.5));
+        assert_eq!(calculate(&mut sheet, "B2"), Value::from("text"));
+        assert_eq!(calculate(&mut sheet, "C9"), Value::Empty);
+    }
+
+    #[test]
+    fn errors_propagate() {
+        let mut sheet = Sheet::new();
+        set(&mut sheet, "A1", "=1/0");
+        set(&mut sheet, "A2", "=A1*2");
+        set(&mut sheet, "A3", "=B3+1");
+        set(&mut sheet, "A4", "=Other!A1*2");
+
+        assert_eq!(calculate(&mut sheet, "A2"), CellError::DivByZero.into());
+        assert_eq!(calculate(&mut sheet, "A3"), Value::Number(1.0));
+        assert_eq!(calculate(&mut sheet, "A4"), CellError::Ref.into());
+    }
+
+    #[test]
+    fn multiplication_does_not_overflow() {
+        let mut sheet = Sheet::new();
+        set(&mut sheet, "A1", &i32::MAX.to_string());
+        set(&mut sheet, "A2", "=A1*4");
+
+        assert_eq!(
+            calculate(&mut sheet, "A2"),
+            Value::Number(i32::MAX as f64 * 4.0)
+        );
+    }
+
+    #[test]
+    fn circular_references() {
+        let mut sheet = Sheet::new();
+        set(&mut sheet, "A1", "=B1+1");
+        set(&mut sheet, "B1", "=A1");
+
+        assert_eq!(calculate(&mut sheet, "A1"), CellError::Ref.into());
+        assert_eq!(calculate(&mut sheet, "B1"), CellError::Ref.into());
+    }
+
+    #[test]
+    fn edits_recalculate() {
+        let mut sheet = Sheet::new();
+        set(&mut sheet, "A1", "2");
+        set(&mut sheet, "A2", "=A1*A1");
+        assert_eq!(calculate(&mut sheet, "A2"), Value::Number(4.0));
+
+        set(&mut sheet, "A1", "3");
+        assert_eq!(calculate(&mut sheet, "A2"), Value::Number(9.0));
+    }
+
+    #[test]
+    fn functions() {
+        let mut sheet = Sheet::new();
+        set(&mut sheet, "A1", "1");
+        set(&mut sheet, "A2", "=A1*2");
+        set(&mut sheet, "A3", "text");
+        set(&mut sheet, "B1", "=SUM(A1:A3)+COUNT(A1:A3)");
+        set(&mut sheet, "B2", "=A1:A2");
+        set(&mut sheet, "B3", "=Other!A1");
+
+        assert_eq!(calculate(&mut sheet, "B1"), Value::Number(5.0));
+        assert_eq!(calculate(&mut sheet, "B2"), CellError::Value.into());
+        assert_eq!(calculate(&mut sheet, "B3"), CellError::Ref.into());
+    }
+
+    #[test]
+    fn names() {
+        let mut sheet = Sheet::new();
+        set(&mut sheet, "B2", "10");
+        set(&mut sheet, "B3", "20");
+        set(&mut sheet, "C2", "0.5");
+        sheet
+            .define_name("TaxRate", Range::parse("C2").unwrap())
+            .unwrap();
+        sheet
+            .define_name("Prices", Range::parse("B2:B100").unwrap())
+            .unwrap();
+        sheet.define_name("Fee", Value::Number(2.0)).unwrap();
+        set(&mut sheet, "A1", "=SUM(prices)*(1+TaxRate)+Fee");
+        set(&mut sheet, "A2", "=Prices");
+        set(&mut sheet, "A3", "=Discount*2");
+
+        assert_eq!(calculate(&mut sheet, "A1"), Value::Number(47.0));
+        assert_eq!(calculate(&mut sheet, "A2"), CellError::Value.into());
+        assert_eq!(calculate(&mut sheet, "A3"), CellError::Name.into());
+
+        sheet.define_name("discount", Value::Number(0.1)).unwrap();
+        assert_eq!(calculate(&mut sheet, "A3"), Value::Number(0.2));
+        sheet.define_name("TAXRATE", Value::Number(0.0)).unwrap();
+        assert_eq!(calculate(&mut sheet, "A1"), Value::Number(32.0));
+        assert_eq!(sheet.names().count(), 4);
+    }
+
+    #[test]
+    fn renamed_names_follow_into_formulas() {
+        let mut sheet = Sheet::new();
+        set(&mut sheet, "C2", "0.5");
+        sheet
+            .define_name("TaxRate", Range::parse("C2").unwrap())
+            .unwrap();
+        sheet.define_name("Fee", Value::Number(2.0)).unwrap();
+        set(&mut sheet, "A1", "=taxrate*10+Fee");
+
+        assert_eq!(
+            sheet.rename_name("Taxrate", "Fee"),
+            Err(NameError::DuplicateName("Fee".to_string()))
+        );
+        sheet.rename_name("Taxrate", "Vat").unwrap();
+        assert_eq!(
+            sheet.get(CellRef::new(0, 0)).unwrap().to_string(),
+            "=Vat*10+Fee"
+        );
+        assert_eq!(calculate(&mut sheet, "A1"), Value::Number(7.0));
+
+        sheet.remove_name("fee").unwrap();
+        assert_eq!(calculate(&mut sheet, "A1"), CellError::Name.into());
+        assert_eq!(
+            sheet.remove_name("Fee"),
+            Err(NameError::UnknownName("Fee".to_string()))
+        );
+        assert_eq!(
+            sheet.define_name("A1", Value::Number(1.0)),
+            Err(NameError::InvalidName("A1".to_string()))
+        );
+    }
+
+    #[test]
+    fn lookups() {
+        let mut sheet = Sheet::new();
+        for (at, input) in [
+            ("A1", "apple"),
+            ("B1", "1.5"),
+            ("A2", "banana"),
+            ("B2", "0.25"),
+            ("A3", "cherry"),
+            ("B3", "4"),
+            ("D1", "=VLOOKUP(\"Cherry\",A1:B3,2,FALSE)"),
+            ("D2", "=INDEX(B1:B3,MATCH(\"ban*\",A1:A3,0))"),
+            ("D3", "=SUMIF(A1:A3,\"<c\",B1:B3)+COUNTIF(B1:B3,\">1\")"),
+            ("D4", "=VLOOKUP(\"kiwi\",A1:B3,2,FALSE)"),
+        ] {
+            set(&mut sheet, at, input);
+        }
+
+        assert_eq!(calculate(&mut sheet, "D1"), Value::Number(4.0));
+        assert_eq!(calculate(&mut sheet, "D2"), Value::Number(0.25));
+        assert_eq!(calculate(&mut sheet, "D3"), Value::Number(3.75));
+        assert_eq!(calculate(&mut sheet, "D4"), CellError::NotAvailable.into());
+    }
+
+    #[test]
+    fn cell_input_round_trips() {
+        for input in ["12", "TRUE", "text", "\"12\"", "\"=A1\"", "=A1*2"] {
+            assert_eq!(Cell::parse(input).unwrap().to_string(), input);
+        }
+    }
+}
@@ -0,0 +1,479 @@
+use std::collections::BTreeMap;
+use std::mem;
+
+use super::{Cell, Sheet};
+use crate::name::Name;
+use crate::reference::{Axis, CellRef, Shift};
+use crate::value::Value;
+
+/// An undoable change to a sheet, applied with [`Sheet::apply`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum Edit {
+    /// Replaces the content of a cell, `Cell::Literal(Value::Empty)` clears it.
+    Set { at: CellRef, cell: Cell },
+    /// Inserts `count` empty rows before row `at`, moving the rows below down.
+    InsertRows { at: usize, count: usize },
+    /// Removes rows `at..at + count`, moving the rows below up.
+    DeleteRows { at: usize, count: usize },
+    /// Inserts `count` empty columns before column `at`, moving the columns
+    /// to the right along.
+    InsertColumns { at: usize, count: usize },
+    /// Removes columns `at..at + count`, moving the columns to the right back.
+    DeleteColumns { at: usize, count: usize },
+    /// Writes a block of cells, given row by row, with its top-left at `at`.
+    Paste { at: CellRef, cells: Vec<Vec<Cell>> },
+}
+
+/// What an edit overwrote: applying it reverts the edit and yields the
+/// change that redoes it.
+#[derive(Debug, Default)]
+struct Change {
+    cells: Vec<(CellRef, Option<Cell>)>,
+    column_widths: Option<BTreeMap<usize, f64>>,
+    names: Option<BTreeMap<String, Name>>,
+}
+
+/// Undo and redo stacks. Each entry is a group of changes that is undone in
+/// reverse order as a whole.
+#[derive(Default)]
+pub(super) struct History {
+    undo: Vec<Vec<Change>>,
+    redo: Vec<Vec<Change>>,
+    /// Changes of the open transaction and, for each nested transaction,
+    /// how many of them were made before it began.
+    transaction: Option<(Vec<Change>, Vec<usize>)>,
+}
+
+struct Snapshot {
+    cells: BTreeMap<CellRef, Cell>,
+    column_widths: BTreeMap<usize, f64>,
+    names: BTreeMap<String, Name>,
+}
+
+impl Sheet {
+    /// Applies an edit and records it so it can be undone.
+    pub fn apply(&mut self, edit: Edit) {
+        let change = self.perform(edit);
+        self.cache.clear();
+        self.history.redo.clear();
+        match &mut self.history.transaction {
+            Some((changes, _)) => changes.push(change),
+            None => self.history.undo.push(vec![change]),
+        }
+    }
+
+    /// Reverts the last edit or transaction. Returns `false` when there is
+    /// nothing to undo or a transaction is still open.
+    pub fn undo(&mut self) -> bool {
+        if self.history.transaction.is_some() {
+            return false;
+        }
+        match self.history.undo.pop() {
+            Some(changes) => {
+                let redo = self.revert(changes);
+                self.history.redo.push(redo);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Applies the last undone edit or transaction again. Returns `false`
+    /// when there is nothing to redo or a transaction is still open.
+    pub fn redo(&mut self) -> bool {
+        if self.history.transaction.is_some() {
+            return false;
+        }
+        match self.history.redo.pop() {
+            Some(changes) => {
+                let undo = self.revert(changes);
+                self.history.undo.push(undo);
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.history.undo.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.history.redo.is_empty()
+    }
+
+    /// Starts grouping edits so they are undone in one step. Transactions
+    /// nest: a committed inner transaction becomes part of the outer one,
+    /// and only the outermost commit ends the group.
+    pub fn begin_transaction(&mut self) {
+        match &mut self.history.transaction {
+            Some((changes, savepoints)) => savepoints.push(changes.len()),
+            None => self.history.transaction = Some((Vec::new(), vec![0])),
+        }
+    }
+
+    pub fn commit_transaction(&mut self) {
+        let Some((changes, mut savepoints)) = self.history.transaction.take() else {
+            return;
+        };
+        savepoints.pop();
+        if !savepoints.is_empty() {
+            self.history.transaction = Some((changes, savepoints));
+        } else if !changes.is_empty() {
+            self.history.undo.push(changes);
+        }
+    }
+
+    /// Reverts every edit made since the innermost `begin_transaction`,
+    /// without leaving anything to redo. The outer transactions stay open
+    /// with their own edits.
+    pub fn rollback_transaction(&mut self) {
+        let Some((mut changes, mut savepoints)) = self.history.transaction.take() else {
+            return;
+        };
+        let savepoint = savepoints.pop().unwrap_or(0);
+        self.revert(changes.split_off(savepoint));
+        if !savepoints.is_empty() {
+            self.history.transaction = Some((changes, savepoints));
+        }
+    }
+
+    /// Runs `edits` as one transaction, rolled back when it returns an error.
+    pub fn transaction<T, E>(
+        &mut self,
+        edits: impl FnOnce(&mut Sheet) -> Result<T, E>,
+    ) -> Result<T, E> {
+        self.begin_transaction();
+        let result = edits(self);
+        match result {
+            Ok(_) => self.commit_transaction(),
+            Err(_) => self.rollback_transaction(),
+        }
+        result
+    }
+
+    fn perform(&mut self, edit: Edit) -> Change {
+        match edit {
+            Edit::Set { at, cell } => self.replace_cells(vec![(at, Some(cell))]),
+            Edit::Paste { at, cells } => {
+                let cells = cells
+                    .into_iter()
+                    .enumerate()
+                    .flat_map(|(row, cells)| {
+                        cells.into_iter().enumerate().map(move |(col, cell)| {
+                            (CellRef::new(at.row + row, at.col + col), Some(cell))
+                        })
+                    })
+                    .collect();
+                self.replace_cells(cells)
+            }
+            Edit::InsertRows { at, count } => self.shift(Axis::Row, at, count, true),
+            Edit::DeleteRows { at, count } => self.shift(Axis::Row, at, count, false),
+            Edit::InsertColumns { at, count } => self.shift(Axis::Column, at, count, true),
+            Edit::DeleteColumns { at, count } => self.shift(Axis::Column, at, count, false),
+        }
+    }
+
+    /// Moves cells, column widths and names along with inserted or deleted
+    /// rows or columns and rewrites every formula to match.
+    fn shift(&mut self, axis: Axis, at: usize, count: usize, insert: bool) -> Change {
+        let shift = Shift {
+            axis,
+            at,
+            count,
+            insert,
+        };
+        let before = self.snapshot();
+
+        self.cells = mem::take(&mut self.cells)
+            .into_iter()
+            .filter_map(|(at, cell)| {
+                let cell = match cell {
+                    Cell::Formula(expr) => Cell::Formula(expr.shifted(&shift)),
+                    literal => literal,
+                };
+                Some((shift.cell(at)?, cell))
+            })
+            .collect();
+        if axis == Axis::Column {
+            self.column_widths = mem::take(&mut self.column_widths)
+                .into_iter()
+                .filter_map(|(col, width)| Some((shift.cell(CellRef::new(0, col))?.col, width)))
+                .collect();
+        }
+        self.names = mem::take(&mut self.names)
+            .into_iter()
+            .filter_map(|(name, definition)| Some((name, definition.shifted(&shift)?)))
+            .collect();
+
+        self.changes_since(before)
+    }
+
+    /// Applies a group of changes last to first, returning the group that
+    /// undoes it again. That group lists the inverse of the last change
+    /// first, so reverting it replays the changes in their original order.
+    fn revert(&mut self, changes: Vec<Change>) -> Vec<Change> {
+        let inverse: Vec<Change> = changes
+            .into_iter()
+            .rev()
+            .map(|change| {
+                let mut undone = self.replace_cells(change.cells);
+                if let Some(widths) = change.column_widths {
+                    undone.column_widths = Some(mem::replace(&mut self.column_widths, widths));
+                }
+                if let Some(names) = change.names {
+                    undone.names = Some(mem::replace(&mut self.names, names));
+                }
+                undone
+            })
+            .collect();
+        self.cache.clear();
+        inverse
+    }
+
+    fn replace_cells(&mut self, cells: Vec<(CellRef, Option<Cell>)>) -> Change {
+        let cells = cells
+            .into_iter()
+            .map(|(at, cell)| {
+                let previous = match cell {
+                    Some(cell) if cell != Cell::Literal(Value::Empty) => {
+                        self.cells.insert(at, cell)
+                    }
+                    _ => self.cells.remove(&at),
+                };
+                (at, previous)
+            })
+            .collect();
+        Change {
+            cells,
+            ..Change::default()
+        }
+    }
+
+    fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            cells: self.cells.clone(),
+            column_widths: self.column_widths.clone(),
+            names: self.names.clone(),
+        }
+    }
+
+    /// The change that brings the sheet back to `before`, covering only the
+    /// cells that differ.
+    fn changes_since(&self, before: Snapshot) -> Change {
+        let mut cells: Vec<(CellRef, Option<Cell>)> = self
+            .cells
+            .keys()
+            .filter(|at| !before.cells.contains_key(at))
+            .map(|at| (*at, None))
+            .collect();
+        cells.extend(
+            before
+                .cells
+                .into_iter()
+                .filter(|(at, cell)| self.cells.get(at) != Some(cell))
+                .map(|(at, cell)| (at, Some(cell))),
+        );
+        Change {
+            cells,
+            column_widths: (before.column_widths != self.column_widths)
+                .then_some(before.column_widths),
+            names: (before.names != self.names).then_some(before.names),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reference::Range;
+    use crate::value::CellError;
+
+    fn set(at: &str, input: &str) -> Edit {
+        Edit::Set {
+            at: CellRef::parse(at).unwrap(),
+            cell: Cell::parse(input).unwrap(),
+        }
+    }
+
+    fn inputs(sheet: &Sheet) -> Vec<String> {
+        sheet
+            .cells()
+            .map(|(at, cell)| format!("{}:{}", at, cell))
+            .collect()
+    }
+
+    #[test]
+    fn undo_and_redo_set() {
+        let mut sheet = Sheet::new();
+        sheet.apply(set("A1", "1"));
+        sheet.apply(set("A2", "=A1*2"));
+        sheet.apply(set("A1", "5"));
+        assert_eq!(sheet.calculate(CellRef::new(1, 0)), Value::Number(10.0));
+
+        assert!(sheet.undo());
+        assert_eq!(sheet.calculate(CellRef::new(1, 0)), Value::Number(2.0));
+        assert!(sheet.undo());
+        assert_eq!(inputs(&sheet), ["A1:1"]);
+
+        assert!(sheet.redo());
+        assert!(sheet.redo());
+        assert!(!sheet.redo());
+        assert_eq!(inputs(&sheet), ["A1:5", "A2:=A1*2"]);
+    }
+
+    #[test]
+    fn new_edit_clears_redo() {
+        let mut sheet = Sheet::new();
+        sheet.apply(set("A1", "1"));
+        sheet.undo();
+        assert!(sheet.can_redo());
+
+        sheet.apply(set("B1", "2"));
+        assert!(!sheet.can_redo());
+        assert!(!sheet.redo());
+    }
+
+    #[test]
+    fn insert_and_delete_rows() {
+        let mut sheet = Sheet::new();
+        sheet.apply(set("A1", "1"));
+        sheet.apply(set("A2", "2"));
+        sheet.apply(set("B3", "3"));
+
+        sheet.apply(Edit::InsertRows { at: 1, count: 2 });
+        assert_eq!(inputs(&sheet), ["A1:1", "A4:2", "B5:3"]);
+
+        sheet.apply(Edit::DeleteRows { at: 0, count: 4 });
+        assert_eq!(inputs(&sheet), ["B1:3"]);
+
+        sheet.undo();
+        assert_eq!(inputs(&sheet), ["A1:1", "A4:2", "B5:3"]);
+        sheet.undo();
+        assert_eq!(inputs(&sheet), ["A1:1", "A2:2", "B3:3"]);
+        sheet.redo();
+        assert_eq!(inputs(&sheet), ["A1:1", "A4:2", "B5:3"]);
+    }
+
+    #[test]
+    fn structural_edits_rewrite_references() {
+        let mut sheet = Sheet::new();
+        sheet.apply(set("A1", "1"));
+        sheet.apply(set("B2", "2"));
+        sheet.apply(set("C3", "=A1+B2*2"));
+        sheet.apply(set("D1", "=C3-B2"));
+        sheet.set_column_width(2, Some(20.0));
+        sheet
+            .define_name("Result", Range::parse("C3").unwrap())
+            .unwrap();
+
+        sheet.apply(Edit::InsertRows { at: 1, count: 1 });
+        sheet.apply(Edit::InsertColumns { at: 0, count: 2 });
+        assert_eq!(inputs(&sheet), ["C1:1", "F1:=E4-D3", "D3:2", "E4:=C1+D3*2"]);
+        assert_eq!(sheet.column_width(4), Some(20.0));
+        assert_eq!(
+            sheet.name("Result"),
+            Some(&Name::from(Range::parse("E4").unwrap()))
+        );
+        assert_eq!(sheet.calculate(CellRef::new(0, 5)), Value::Number(3.0));
+
+        sheet.apply(Edit::DeleteColumns { at: 3, count: 1 });
+        assert_eq!(inputs(&sheet), ["C1:1", "E1:=D4-#REF!", "D4:=C1+#REF!*2"]);
+        assert_eq!(sheet.calculate(CellRef::new(0, 4)), CellError::Ref.into());
+
+        sheet.apply(Edit::DeleteRows { at: 3, count: 1 });
+        assert_eq!(inputs(&sheet), ["C1:1", "E1:=#REF!-#REF!"]);
+        assert_eq!(sheet.name("Result"), None);
+
+        for _ in 0..4 {
+            sheet.undo();
+        }
+        assert_eq!(inputs(&sheet), ["A1:1", "D1:=C3-B2", "B2:2", "C3:=A1+B2*2"]);
+        assert_eq!(sheet.column_width(2), Some(20.0));
+        assert_eq!(
+            sheet.name("Result"),
+            Some(&Name::from(Range::parse("C3").unwrap()))
+        );
+    }
+
+    #[test]
+    fn paste_block() {
+        let mut sheet = Sheet::new();
+        sheet.apply(set("C3", "old"));
+        let cells = vec![
+            vec![Cell::parse("1").unwrap(), Cell::parse("2").unwrap()],
+            vec![Cell::Literal(Value::Empty), Cell::parse("=B2+C2").unwrap()],
+        ];
+        sheet.apply(Edit::Paste {
+            at: CellRef::new(1, 1),
+            cells,
+        });
+        assert_eq!(inputs(&sheet), ["B2:1", "C2:2", "C3:=B2+C2"]);
+
+        sheet.undo();
+        assert_eq!(inputs(&sheet), ["C3:old"]);
+    }
+
+    #[test]
+    fn transactions_undo_as_one() {
+        let mut sheet = Sheet::new();
+        sheet.apply(set("A1", "1"));
+        sheet.begin_transaction();
+        sheet.apply(set("A1", "2"));
+        sheet.begin_transaction();
+        sheet.apply(set("B1", "3"));
+        sheet.commit_transaction();
+        assert!(!sheet.undo());
+        sheet.apply(Edit::InsertRows { at: 0, count: 1 });
+        sheet.commit_transaction();
+        assert_eq!(inputs(&sheet), ["A2:2", "B2:3"]);
+
+        sheet.undo();
+        assert_eq!(inputs(&sheet), ["A1:1"]);
+        sheet.redo();
+        assert_eq!(inputs(&sheet), ["A2:2", "B2:3"]);
+    }
+
+    #[test]
+    fn failed_transaction_rolls_back() {
+        let mut sheet = Sheet::new();
+        sheet.apply(set("A1", "1"));
+
+        let result: Result<(), &str> = sheet.transaction(|sheet| {
+            sheet.apply(set("A1", "2"));
+            sheet.apply(Edit::DeleteRows { at: 0, count: 1 });
+            Err("abort")
+        });
+
+        assert_eq!(result, Err("abort"));
+        assert_eq!(inputs(&sheet), ["A1:1"]);
+        assert!(!sheet.can_redo());
+        sheet.undo();
+        assert_eq!(inputs(&sheet), Vec::<String>::new());
+    }
+
+    #[test]
+    fn failed_inner_transaction_keeps_the_outer_one() {
+        let mut sheet = Sheet::new();
+        sheet.apply(set("A1", "1"));
+
+        let result: Result<(), &str> = sheet.transaction(|sheet| {
+            sheet.apply(set("A1", "2"));
+            let inner: Result<(), &str> = sheet.transaction(|sheet| {
+                sheet.apply(set("B1", "3"));
+                Err("abort")
+            });
+            assert_eq!(inner, Err("abort"));
+            assert_eq!(inputs(sheet), ["A1:2"]);
+            // still grouped with the outer transaction's edits
+            assert!(!sheet.undo());
+            sheet.apply(set("C1", "4"));
+            Ok(())
+        });
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(inputs(&sheet), ["A1:2", "C1:4"]);
+        sheet.undo();
+        assert_eq!(inputs(&sheet), ["A1:1"]);
+    }
+}
@@ -0,0 +1,274 @@
+use std::collections::HashSet;
+use std::fmt;
+
+use super::{evaluate, Cell, Resolve, Sheet};
+use crate::formula::Expr;
+use crate::name::Name;
+use crate::reference::CellRef;
+use crate::value::Value;
+
+/// How a value came about, see [`Sheet::explain`]: a cell, a part of a
+/// formula or a name, along with the parts it was calculated from.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trace {
+    /// The cell, name or part of a formula, as it is written in the formula.
+    pub expr: String,
+    /// `None` for blocks of cells, which don't have a single value.
+    pub value: Option<Value>,
+    /// Formula of a cell, or definition of a name.
+    pub input: Option<String>,
+    /// The cell was already explained further up, so its parts are left out.
+    /// This is also how circular references end.
+    pub repeated: bool,
+    /// References, names and operations the value was calculated from.
+    /// Literals typed into the formula are left out.
+    pub children: Vec<Trace>,
+}
+
+impl Trace {
+    fn leaf(expr: &impl fmt::Display, value: Option<Value>) -> Trace {
+        Trace {
+            expr: expr.to_string(),
+            value,
+            input: None,
+            repeated: false,
+            children: Vec::new(),
+        }
+    }
+
+    fn write(&self, f: &mut fmt::Formatter, first: &str, rest: &str) -> fmt::Result {
+        write!(f, "{}{}", first, self.expr)?;
+        if let Some(input) = &self.input {
+            write!(f, ": {}", input)?;
+        }
+        match &self.value {
+            Some(Value::Empty) => write!(f, " = (empty)")?,
+            Some(value) => write!(f, " = {}", value)?,
+            None => {}
+        }
+        if self.repeated {
+            write!(f, " (see above)")?;
+        }
+        writeln!(f)?;
+        for (i, child) in self.children.iter().enumerate() {
+            let (branch, indent) = if i + 1 == self.children.len() {
+                ("└─ ", "   ")
+            } else {
+                ("├─ ", "│  ")
+            };
+            child.write(
+                f,
+                &format!("{}{}", rest, branch),
+                &format!("{}{}", rest, indent),
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// Draws the trace as a tree, one line per part:
+///
+/// ```text
+/// A3: =A1+A2*2 = 7
+/// ├─ A1 = 1
+/// └─ A2*2 = 6
+///    └─ A2: =A1+2 = 3
+///       └─ A1 = 1 (see above)
+/// ```
+impl fmt::Display for Trace {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.write(f, "", "")
+    }
+}
+
+impl Sheet {
+    /// Explains the value of a cell: every cell and name it reads, their
+    /// formulas and values, and the intermediate result of every operation
+    /// and function call in between.
+    pub fn explain(&mut self, at: CellRef) -> Trace {
+        self.trace_cell(at, &mut HashSet::new())
+    }
+
+    fn trace_cell(&mut self, at: CellRef, seen: &mut HashSet<CellRef>) -> Trace {
+        let mut trace = Trace::leaf(&at, Some(self.calculate(at)));
+        if !seen.insert(at) {
+            trace.repeated = true;
+            return trace;
+        }
+        if let Some(Cell::Formula(expr)) = self.cells.get(&at).cloned() {
+            trace.input = Some(format!("={}", expr));
+            trace.children = self.trace_formula(&expr, seen);
+        }
+        trace
+    }
+
+    /// Traces of what a formula is calculated from. The formula itself is
+    /// already shown as the input of its cell or name.
+    fn trace_formula(&mut self, expr: &Expr, seen: &mut HashSet<CellRef>) -> Vec<Trace> {
+        match expr {
+            Expr::Literal(_) => Vec::new(),
+            Expr::Neg(_) | Expr::Binary(..) | Expr::Call(..) => self.trace(expr, seen).children,
+            _ => vec![self.trace(expr, seen)],
+        }
+    }
+
+    fn trace(&mut self, expr: &Expr, seen: &mut HashSet<CellRef>) -> Trace {
+        match expr {
+            Expr::Ref(at) => self.trace_cell(*at, seen),
+            Expr::Range { sheet: None, range } => {
+                let mut trace = Trace::leaf(expr, None);
+                let cells: Vec<CellRef> = self.cells_in(*range).collect();
+                trace.children = cells
+                    .into_iter()
+                    .map(|at| self.trace_cell(at, seen))
+                    .collect();
+                trace
+            }
+            Expr::Range {
+                sheet: Some(sheet),
+                range,
+            } => Trace::leaf(expr, Some(self.cell(Some(sheet), range.start))),
+            Expr::Name(name) => {
+                let Some(definition) = Sheet::name(self, name).cloned() else {
+                    return Trace::leaf(expr, Some(evaluate(expr, self)));
+                };
+                let value = match &definition {
+                    Name::Range { range, .. } if range.start != range.end => None,
+                    _ => Some(evaluate(expr, self)),
+                };
+                let mut trace = Trace::leaf(expr, value);
+                trace.input = Some(definition.to_string());
+                trace.children = self.trace_formula(&definition.to_expr(), seen);
+                trace
+            }
+            Expr::Literal(value) => Trace::leaf(expr, Some(value.clone())),
+            Expr::Neg(operand) => self.trace_operation(expr, [&**operand], seen),
+            Expr::Binary(_, lhs, rhs) => self.trace_operation(expr, [&**lhs, &**rhs], seen),
+            Expr::Call(_, args) => self.trace_operation(expr, args, seen),
+        }
+    }
+
+    fn trace_operation<'a>(
+        &mut self,
+        expr: &Expr,
+        operands: impl IntoIterator<Item = &'a Expr>,
+        seen: &mut HashSet<CellRef>,
+    ) -> Trace {
+        let mut trace = Trace::leaf(expr, Some(evaluate(expr, self)));
+        trace.children = operands
+            .into_iter()
+            .filter(|operand| !matches!(operand, Expr::Literal(_)))
+            .map(|operand| self.trace(operand, seen))
+            .collect();
+        trace
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reference::Range;
+    use crate::value::CellError;
+
+    fn sheet(cells: &[(&str, &str)]) -> Sheet {
+        let mut sheet = Sheet::new();
+        for (at, input) in cells {
+            sheet.set(CellRef::parse(at).unwrap(), Cell::parse(input).unwrap());
+        }
+        sheet
+    }
+
+    fn explain(sheet: &mut Sheet, at: &str) -> String {
+        sheet.explain(CellRef::parse(at).unwrap()).to_string()
+    }
+
+    #[test]
+    fn intermediate_results() {
+        let mut sheet = sheet(&[("A1", "1"), ("A2", "=A1+2"), ("A3", "=A1+A2*2")]);
+
+        assert_eq!(
+            explain(&mut sheet, "A3"),
+            [
+                "A3: =A1+A2*2 = 7",
+                "├─ A1 = 1",
+                "└─ A2*2 = 6",
+                "   └─ A2: =A1+2 = 3",
+                "      └─ A1 = 1 (see above)",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn trace_structure() {
+        let mut sheet = sheet(&[("A1", "2"), ("B1", "=-A1"), ("C1", "=B1")]);
+        let trace = sheet.explain(CellRef::parse("C1").unwrap());
+
+        assert_eq!(trace.input.as_deref(), Some("=B1"));
+        assert_eq!(trace.value, Some(Value::Number(-2.0)));
+        let b1 = &trace.children[0];
+        assert_eq!(b1.expr, "B1");
+        assert_eq!(b1.input.as_deref(), Some("=-A1"));
+        // `-A1` is B1's formula, shown as its input rather than as a part
+        assert_eq!(b1.children.len(), 1);
+        assert_eq!(b1.children[0].expr, "A1");
+        assert_eq!(b1.children[0].input, None);
+    }
+
+    #[test]
+    fn ranges_and_names() {
+        let mut sheet = sheet(&[
+            ("B1", "10"),
+            ("B3", "20"),
+            ("C1", "0.5"),
+            ("A1", "=SUM(Prices)*Rate+Missing"),
+        ]);
+        sheet
+            .define_name("Prices", Range::parse("B1:B3").unwrap())
+            .unwrap();
+        sheet
+            .define_name("Rate", Range::parse("C1").unwrap())
+            .unwrap();
+
+        assert_eq!(
+            explain(&mut sheet, "A1"),
+            [
+                "A1: =SUM(Prices)*Rate+Missing = #NAME?",
+                "├─ SUM(Prices)*Rate = 15",
+                "│  ├─ SUM(Prices) = 30",
+                "│  │  └─ Prices: B1:B3",
+                "│  │     └─ B1:B3",
+                "│  │        ├─ B1 = 10",
+                "│  │        └─ B3 = 20",
+                "│  └─ Rate: C1 = 0.5",
+                "│     └─ C1 = 0.5",
+                "└─ Missing = #NAME?",
+                "",
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn circular_references_end() {
+        let mut sheet = sheet(&[("A1", "=B1+1"), ("B1", "=A1")]);
+        let trace = sheet.explain(CellRef::parse("A1").unwrap());
+
+        assert_eq!(trace.value, Some(CellError::Ref.into()));
+        let a1 = &trace.children[0].children[0];
+        assert_eq!(a1.expr, "A1");
+        assert!(a1.repeated);
+        assert!(a1.children.is_empty());
+    }
+
+    #[test]
+    fn empty_cells() {
+        let mut sheet = sheet(&[("A1", "=B1")]);
+
+        assert_eq!(
+            explain(&mut sheet, "A1"),
+            "A1: =B1 = (empty)\n└─ B1 = (empty)\n"
+        );
+    }
+}
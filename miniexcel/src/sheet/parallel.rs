@@ -0,0 +1,299 @@
+use std::collections::HashMap;
+use std::sync::{Condvar, Mutex, OnceLock};
+use std::thread;
+
+use super::{evaluate, Cell, Resolve, Sheet};
+use crate::name::Name;
+use crate::reference::{CellRef, Range};
+use crate::value::{CellError, Value};
+
+/// Ready formulas a worker takes at once, to keep locking off the hot path.
+const BATCH: usize = 64;
+
+/// Formula cells and who depends on whom, by index into `cells`.
+struct Graph<'a> {
+    cells: Vec<(CellRef, &'a crate::formula::Expr)>,
+    index: HashMap<CellRef, usize>,
+    /// Formulas that reference each formula.
+    dependents: Vec<Vec<usize>>,
+    /// Number of formulas each formula still waits for.
+    waiting: Vec<usize>,
+}
+
+struct Queue {
+    ready: Vec<usize>,
+    /// Workers busy with a batch, which may make more formulas ready.
+    busy: usize,
+}
+
+/// Looks the cells a formula reads up in the results calculated so far.
+struct Calculated<'a> {
+    sheet: &'a Sheet,
+    graph: &'a Graph<'a>,
+    results: &'a [OnceLock<Value>],
+}
+
+impl Resolve for Calculated<'_> {
+    fn cell(&mut self, sheet: Option<&str>, at: CellRef) -> Value {
+        if sheet.is_some() {
+            return Value::Error(CellError::Ref);
+        }
+        match self.graph.index.get(&at) {
+            Some(i) => self.results[*i]
+                .get()
+                .cloned()
+                .expect("dependency calculated"),
+            None => match self.sheet.cells.get(&at) {
+                Some(Cell::Literal(value)) => value.clone(),
+                _ => Value::Empty,
+            },
+        }
+    }
+
+    fn name(&self, name: &str) -> Option<Name> {
+        self.sheet.name(name).cloned()
+    }
+
+    fn rows(&self, sheet: Option<&str>) -> Option<usize> {
+        self.sheet.rows(sheet)
+    }
+}
+
+impl Sheet {
+    /// Calculates every cell, spreading independent formulas over `threads`
+    /// threads. The values are the same as calculating each cell with
+    /// [`Sheet::calculate`] in row-major order.
+    pub fn calculate_all(&mut self, threads: usize) {
+        let values = self.calculate_acyclic(threads.max(1));
+        self.cache.extend(values);
+
+        // what's left is part of, or depends on, a circular reference; the
+        // serial calculation is what decides which of those cells sees #REF!
+        let rest: Vec<CellRef> = self
+            .cells
+            .keys()
+            .filter(|at| !self.cache.contains_key(at))
+            .copied()
+            .collect();
+        for at in rest {
+            self.calculate(at);
+        }
+    }
+
+    /// Values of all formulas that don't depend on a circular reference.
+    fn calculate_acyclic(&self, threads: usize) -> Vec<(CellRef, Value)> {
+        let graph = self.graph();
+        let results: Vec<OnceLock<Value>> = graph.cells.iter().map(|_| OnceLock::new()).collect();
+        let waiting: Vec<Mutex<usize>> = graph.waiting.iter().map(|n| Mutex::new(*n)).collect();
+        let queue = Mutex::new(Queue {
+            ready: (0..graph.cells.len())
+                .filter(|i| graph.waiting[*i] == 0)
+                .collect(),
+            busy: 0,
+        });
+        let wake = Condvar::new();
+
+        let work = || loop {
+            let batch = {
+                let mut queue = queue.lock().unwrap();
+                loop {
+                    if !queue.ready.is_empty() {
+                        let split = queue.ready.len().saturating_sub(BATCH);
+                        queue.busy += 1;
+                        break queue.ready.split_off(split);
+                    }
+                    if queue.busy == 0 {
+                        wake.notify_all();
+                        return;
+                    }
+                    queue = wake.wait(queue).unwrap();
+                }
+            };
+
+            let mut ready = Vec::new();
+            for i in batch {
+                let mut calculated = Calculated {
+                    sheet: self,
+                    graph: &graph,
+                    results: &results,
+                };
+                let value = evaluate(graph.cells[i].1, &mut calculated);
+                results[i].set(value).expect("calculated once");
+                for &dependent in &graph.dependents[i] {
+                    let mut waiting = waiting[dependent].lock().unwrap();
+                    *waiting -= 1;
+                    if *waiting == 0 {
+                        ready.push(dependent);
+                    }
+                }
+            }
+
+            let mut queue = queue.lock().unwrap();
+            queue.busy -= 1;
+            queue.ready.extend(ready);
+            wake.notify_all();
+        };
+
+        thread::scope(|scope| {
+            for _ in 1..threads {
+                scope.spawn(work);
+            }
+            work();
+        });
+
+        graph
+            .cells
+            .iter()
+            .zip(results)
+            .filter_map(|((at, _), value)| Some((*at, value.into_inner()?)))
+            .collect()
+    }
+
+    fn graph(&self) -> Graph<'_> {
+        let cells: Vec<(CellRef, &crate::formula::Expr)> = self
+            .cells
+            .iter()
+            .filter_map(|(at, cell)| match cell {
+                Cell::Formula(expr) => Some((*at, expr)),
+                Cell::Literal(_) => None,
+            })
+            .collect();
+        let index: HashMap<CellRef, usize> = cells
+            .iter()
+            .enumerate()
+            .map(|(i, (at, _))| (*at, i))
+            .collect();
+
+        let mut dependents = vec![Vec::new(); cells.len()];
+        let mut waiting = vec![0; cells.len()];
+        for (i, (_, expr)) in cells.iter().enumerate() {
+            let mut ranges: Vec<Range> = expr
+                .references()
+                .into_iter()
+                .filter(|(sheet, _)| sheet.is_none())
+                .map(|(_, range)| range)
+                .collect();
+            for name in expr.names() {
+                if let Some(Name::Range { sheet: None, range }) = self.name(name) {
+                    ranges.push(*range);
+                }
+            }
+            let mut refs: Vec<CellRef> = ranges
+                .into_iter()
+                .flat_map(|range| self.cells_in(range))
+                .collect();
+            refs.sort();
+            refs.dedup();
+            for at in refs {
+                if let Some(&dependency) = index.get(&at) {
+                    dependents[dependency].push(i);
+                    waiting[i] += 1;
+                }
+            }
+        }
+        Graph {
+            cells,
+            index,
+            dependents,
+            waiting,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Small deterministic generator so the test sheets are reproducible.
+    struct Lcg(u64);
+
+    impl Lcg {
+        fn next(&mut self, n: usize) -> usize {
+            self.0 = self
+                .0
+                .wrapping_mul(6364136223846793005)
+                .wrapping_add(1442695040888963407);
+            ((self.0 >> 33) % n as u64) as usize
+        }
+    }
+
+    fn random_sheet(seed: u64, rows: usize, cols: usize) -> Sheet {
+        let mut rng = Lcg(seed);
+        let mut sheet = Sheet::new();
+        for row in 0..rows {
+            for col in 0..cols {
+                let at = CellRef::new(row, col);
+                let input = match rng.next(6) {
+                    0 => format!("{}", rng.next(100) as f64 / 4.0),
+                    1 => "text".to_string(),
+                    2 => String::new(),
+                    _ => {
+                        let ops = ["+", "-", "*", "/"];
+                        let lhs = CellRef::new(rng.next(rows), rng.next(cols));
+                        let rhs = CellRef::new(rng.next(rows), rng.next(cols));
+                        format!("={}{}{}+{}", lhs, ops[rng.next(4)], rhs, rng.next(10))
+                    }
+                };
+                sheet.set(at, Cell::parse(&input).unwrap());
+            }
+        }
+        sheet
+    }
+
+    fn serial_values(sheet: &mut Sheet, rows: usize, cols: usize) -> Vec<Value> {
+        let mut values = Vec::new();
+        for row in 0..rows {
+            for col in 0..cols {
+                values.push(sheet.calculate(CellRef::new(row, col)));
+            }
+        }
+        values
+    }
+
+    #[test]
+    fn matches_serial_calculation() {
+        for seed in 0..20 {
+            let serial = serial_values(&mut random_sheet(seed, 30, 8), 30, 8);
+            for threads in [1, 2, 4, 8] {
+                let mut sheet = random_sheet(seed, 30, 8);
+                sheet.calculate_all(threads);
+                let values: Vec<Value> = (0..30)
+                    .flat_map(|row| (0..8).map(move |col| CellRef::new(row, col)))
+                    .map(|at| sheet.cached(at).cloned().unwrap_or_default())
+                    .collect();
+                assert_eq!(values, serial, "seed {} on {} threads", seed, threads);
+            }
+        }
+    }
+
+    #[test]
+    fn long_chains_do_not_recurse() {
+        let mut sheet = Sheet::new();
+        sheet.set(CellRef::new(0, 0), Cell::parse("1").unwrap());
+        for row in 1..200_000 {
+            let input = format!("={}+1", CellRef::new(row - 1, 0));
+            sheet.set(CellRef::new(row, 0), Cell::parse(&input).unwrap());
+        }
+        sheet.calculate_all(4);
+
+        assert_eq!(
+            sheet.calculate(CellRef::new(199_999, 0)),
+            Value::Number(200_000.0)
+        );
+    }
+
+    #[test]
+    fn large_ranges_only_read_stored_cells() {
+        let mut sheet = Sheet::new();
+        let count = CellRef::new(9, 0);
+        sheet.set(CellRef::new(0, 0), Cell::parse("=B5+1").unwrap());
+        sheet.set(CellRef::new(4, 1), Cell::parse("=C1*2").unwrap());
+        sheet.set(CellRef::new(0, 2), Cell::parse("3").unwrap());
+        sheet.set(count, Cell::parse("=COUNT(B1:XFD1048576)").unwrap());
+
+        let graph = sheet.graph();
+        assert_eq!(graph.waiting[graph.index[&count]], 1);
+        sheet.calculate_all(2);
+        assert_eq!(sheet.cached(count), Some(&Value::Number(2.0)));
+    }
+}
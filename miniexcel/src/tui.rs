@@ -0,0 +1,471 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use crossterm::style::{Attribute, Print, SetAttribute};
+use crossterm::terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen};
+use crossterm::{execute, queue};
+
+use miniexcel::*;
+
+const DEFAULT_WIDTH: usize = 10;
+/// Width of the row numbers on the left of the grid.
+const GUTTER: usize = 5;
+/// Lines used by the edit bar, the column header and the status line.
+const CHROME: usize = 3;
+const HELP: &str =
+    "arrows move | enter edit | del clear | ctrl-z undo | ctrl-y redo | ctrl-s save | ctrl-q quit";
+
+/// Runs the interactive editor until the user quits. `path` is loaded when
+/// it exists and is where ctrl-s saves to, as CSV when it ends in `.csv` and
+/// in the native format otherwise.
+pub fn run(path: Option<&str>) -> Result<(), Box<dyn std::error::Error>> {
+    let path = path.map(PathBuf::from);
+    let sheet = match &path {
+        Some(path) if path.exists() => load(path)?,
+        _ => Sheet::new(),
+    };
+    let mut app = App::new(sheet, path);
+
+    let _terminal = RawTerminal::enter()?;
+    let mut out = io::stdout();
+    while !app.quit {
+        let (width, height) = terminal::size()?;
+        app.draw(&mut out, width as usize, height as usize)?;
+        if let Event::Key(key) = event::read()? {
+            if key.kind == KeyEventKind::Press {
+                app.handle(key);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn is_csv(path: &Path) -> bool {
+    path.extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("csv"))
+}
+
+fn load(path: &Path) -> Result<Sheet, Box<dyn std::error::Error>> {
+    let input = fs::read_to_string(path)?;
+    if is_csv(path) {
+        Ok(Sheet::from_csv(&input)?)
+    } else {
+        Ok(Sheet::load(&input)?)
+    }
+}
+
+/// Puts the terminal in raw mode on the alternate screen and restores it
+/// when dropped, also when the editor bails out with an error.
+struct RawTerminal;
+
+impl RawTerminal {
+    fn enter() -> io::Result<RawTerminal> {
+        terminal::enable_raw_mode()?;
+        execute!(io::stdout(), EnterAlternateScreen)?;
+        Ok(RawTerminal)
+    }
+}
+
+impl Drop for RawTerminal {
+    fn drop(&mut self) {
+        let _ = execute!(io::stdout(), Show, LeaveAlternateScreen);
+        let _ = terminal::disable_raw_mode();
+    }
+}
+
+/// Input being typed into the selected cell, `caret` counts characters.
+struct Editor {
+    text: String,
+    caret: usize,
+}
+
+impl Editor {
+    fn new(text: String) -> Editor {
+        let caret = text.chars().count();
+        Editor { text, caret }
+    }
+
+    fn byte_index(&self, caret: usize) -> usize {
+        self.text
+            .char_indices()
+            .nth(caret)
+            .map_or(self.text.len(), |(index, _)| index)
+    }
+}
+
+pub struct App {
+    sheet: Sheet,
+    path: Option<PathBuf>,
+    cursor: CellRef,
+    /// Top-left cell on screen.
+    scroll: CellRef,
+    edit: Option<Editor>,
+    status: String,
+    /// Rows of cells that fit on the screen at the last draw.
+    page: usize,
+    quit: bool,
+}
+
+impl App {
+    pub fn new(sheet: Sheet, path: Option<PathBuf>) -> App {
+        App {
+            sheet,
+            path,
+            cursor: CellRef::new(0, 0),
+            scroll: CellRef::new(0, 0),
+            edit: None,
+            status: HELP.to_string(),
+            page: 1,
+            quit: false,
+        }
+    }
+
+    pub fn handle(&mut self, key: KeyEvent) {
+        let ctrl = key.modifiers.contains(KeyModifiers::CONTROL);
+        match key.code {
+            KeyCode::Char('q') | KeyCode::Char('c') if ctrl => self.quit = true,
+            KeyCode::Char('s') if ctrl => self.save(),
+            KeyCode::Char('z') if ctrl && self.edit.is_none() => self.undo(false),
+            KeyCode::Char('y') if ctrl && self.edit.is_none() => self.undo(true),
+            _ if self.edit.is_some() => self.handle_edit(key),
+            KeyCode::Up => self.move_cursor(-1, 0),
+            KeyCode::Down => self.move_cursor(1, 0),
+            KeyCode::Left | KeyCode::BackTab => self.move_cursor(0, -1),
+            KeyCode::Right | KeyCode::Tab => self.move_cursor(0, 1),
+            KeyCode::PageUp => self.move_cursor(-(self.page as isize), 0),
+            KeyCode::PageDown => self.move_cursor(self.page as isize, 0),
+            KeyCode::Home => self.cursor.col = 0,
+            KeyCode::Enter | KeyCode::F(2) => {
+                let input = self
+                    .sheet
+                    .get(self.cursor)
+                    .map(|cell| cell.to_string())
+                    .unwrap_or_default();
+                self.edit = Some(Editor::new(input));
+            }
+            KeyCode::Delete | KeyCode::Backspace => self.sheet.apply(Edit::Set {
+                at: self.cursor,
+                cell: Cell::Literal(Value::Empty),
+            }),
+            KeyCode::Char(c) if !ctrl => self.edit = Some(Editor::new(c.to_string())),
+            _ => {}
+        }
+    }
+
+    fn handle_edit(&mut self, key: KeyEvent) {
+        let edit = self.edit.as_mut().unwrap();
+        match key.code {
+            KeyCode::Enter => self.commit(1, 0),
+            KeyCode::Tab => self.commit(0, 1),
+            KeyCode::Esc => {
+                self.edit = None;
+                self.status = HELP.to_string();
+            }
+            KeyCode::Left => edit.caret = edit.caret.saturating_sub(1),
+            KeyCode::Right => edit.caret = (edit.caret + 1).min(edit.text.chars().count()),
+            KeyCode::Home => edit.caret = 0,
+            KeyCode::End => edit.caret = edit.text.chars().count(),
+            KeyCode::Backspace if edit.caret > 0 => {
+                edit.caret -= 1;
+                let index = edit.byte_index(edit.caret);
+                edit.text.remove(index);
+            }
+            KeyCode::Delete if edit.caret < edit.text.chars().count() => {
+                let index = edit.byte_index(edit.caret);
+                edit.text.remove(index);
+            }
+            KeyCode::Char(c) => {
+                let index = edit.byte_index(edit.caret);
+                edit.text.insert(index, c);
+                edit.caret += 1;
+            }
+            _ => {}
+        }
+    }
+
+    /// Stores the edited input in the selected cell and moves on. Invalid
+    /// formulas keep the editor open with the error in the status line.
+    fn commit(&mut self, rows: isize, cols: isize) {
+        let text = &self.edit.as_ref().unwrap().text;
+        match Cell::parse(text) {
+            Ok(cell) => {
+                self.sheet.apply(Edit::Set {
+                    at: self.cursor,
+                    cell,
+                });
+                self.edit = None;
+                self.status = HELP.to_string();
+                self.move_cursor(rows, cols);
+            }
+            Err(err) => self.status = format!("{}: {}", self.cursor, err),
+        }
+    }
+
+    fn undo(&mut self, redo: bool) {
+        let done = if redo {
+            self.sheet.redo()
+        } else {
+            self.sheet.undo()
+        };
+        self.status = match (done, redo) {
+            (true, _) => HELP.to_string(),
+            (false, true) => "nothing to redo".to_string(),
+            (false, false) => "nothing to undo".to_string(),
+        };
+    }
+
+    fn move_cursor(&mut self, rows: isize, cols: isize) {
+        self.cursor = CellRef::new(
+            self.cursor.row.saturating_add_signed(rows),
+            self.cursor.col.saturating_add_signed(cols),
+        );
+    }
+
+    fn save(&mut self) {
+        let Some(path) = &self.path else {
+            self.status = "no file to save to, start with `miniexcel tui <file>`".to_string();
+            return;
+        };
+        let output = if is_csv(path) {
+            self.sheet.to_csv(CsvExport::Formulas)
+        } else {
+            self.sheet.save()
+        };
+        self.status = match fs::write(path, output) {
+            Ok(()) => format!("saved {}", path.display()),
+            Err(err) => format!("couldn't save {}: {}", path.display(), err),
+        };
+    }
+
+    fn column_width(&self, col: usize) -> usize {
+        self.sheet
+            .column_width(col)
+            .map_or(DEFAULT_WIDTH, |width| width.round().max(1.0) as usize)
+    }
+
+    /// Columns that fit in `width` starting at the scroll position, with the
+    /// screen column each one starts at. Always contains at least one column.
+    fn visible_columns(&self, width: usize) -> Vec<(usize, usize)> {
+        let mut columns = Vec::new();
+        let mut x = GUTTER;
+        let mut col = self.scroll.col;
+        while columns.is_empty() || x + self.column_width(col) <= width {
+            columns.push((col, x));
+            x += self.column_width(col) + 1;
+            col += 1;
+        }
+        columns
+    }
+
+    /// Moves the scroll position just enough for the cursor to be on screen.
+    fn scroll_to_cursor(&mut self, width: usize, height: usize) {
+        self.page = height.saturating_sub(CHROME).max(1);
+        if self.cursor.row < self.scroll.row {
+            self.scroll.row = self.cursor.row;
+        } else if self.cursor.row >= self.scroll.row + self.page {
+            self.scroll.row = self.cursor.row + 1 - self.page;
+        }
+        if self.cursor.col < self.scroll.col {
+            self.scroll.col = self.cursor.col;
+        }
+        while !self
+            .visible_columns(width)
+            .iter()
+            .any(|(col, _)| *col == self.cursor.col)
+        {
+            self.scroll.col += 1;
+        }
+    }
+
+    pub fn draw(&mut self, out: &mut impl Write, width: usize, height: usize) -> io::Result<()> {
+        self.scroll_to_cursor(width, height);
+        queue!(out, Hide, MoveTo(0, 0), Clear(ClearType::All))?;
+
+        let input = match &self.edit {
+            Some(edit) => edit.text.clone(),
+            None => self
+                .sheet
+                .get(self.cursor)
+                .map(|cell| cell.to_string())
+                .unwrap_or_default(),
+        };
+        let label = format!("{:<width$}", self.cursor.to_string(), width = GUTTER);
+        queue!(
+            out,
+            Print(fit(&format!("{}{}", label, input), width, false))
+        )?;
+
+        let columns = self.visible_columns(width);
+        for (col, x) in &columns {
+            let name = column_name(*col);
+            let cell_width = self.column_width(*col);
+            let pad = cell_width.saturating_sub(name.len()) / 2;
+            queue!(
+                out,
+                MoveTo(*x as u16, 1),
+                Print(fit(
+                    &format!("{}{}", " ".repeat(pad), name),
+                    cell_width,
+                    false
+                ))
+            )?;
+        }
+
+        for line in 0..self.page {
+            let row = self.scroll.row + line;
+            let y = (line + 2) as u16;
+            queue!(out, MoveTo(0, y), Print(format!("{:>4} ", row + 1)))?;
+            for (col, x) in &columns {
+                let at = CellRef::new(row, *col);
+                let value = self.sheet.calculate(at);
+                let text = fit(
+                    &value.to_string(),
+                    self.column_width(*col),
+                    value.is_number(),
+                );
+                queue!(out, MoveTo(*x as u16, y))?;
+                if at == self.cursor {
+                    queue!(
+                        out,
+                        SetAttribute(Attribute::Reverse),
+                        Print(text),
+                        SetAttribute(Attribute::Reset)
+                    )?;
+                } else {
+                    queue!(out, Print(text))?;
+                }
+            }
+        }
+
+        queue!(
+            out,
+            MoveTo(0, height.saturating_sub(1) as u16),
+            Print(fit(&self.status, width, false))
+        )?;
+        if let Some(edit) = &self.edit {
+            queue!(out, MoveTo((GUTTER + edit.caret) as u16, 0), Show)?;
+        }
+        out.flush()
+    }
+}
+
+/// Pads or truncates `text` to exactly `width` characters.
+fn fit(text: &str, width: usize, right_align: bool) -> String {
+    let text: String = text.chars().take(width).collect();
+    if right_align {
+        format!("{:>width$}", text, width = width)
+    } else {
+        format!("{:<width$}", text, width = width)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn type_keys(app: &mut App, keys: &str) {
+        for c in keys.chars() {
+            let code = match c {
+                '\n' => KeyCode::Enter,
+                '\t' => KeyCode::Tab,
+                c => KeyCode::Char(c),
+            };
+            app.handle(KeyEvent::from(code));
+        }
+    }
+
+    #[test]
+    fn editing_recalculates() {
+        let mut app = App::new(Sheet::new(), None);
+        type_keys(&mut app, "2\n=A1*3\n");
+
+        assert_eq!(app.cursor, CellRef::new(2, 0));
+        assert_eq!(app.sheet.calculate(CellRef::new(1, 0)), Value::Number(6.0));
+
+        app.handle(KeyEvent::from(KeyCode::Up));
+        app.handle(KeyEvent::from(KeyCode::Up));
+        type_keys(&mut app, "5\n");
+        assert_eq!(app.sheet.calculate(CellRef::new(1, 0)), Value::Number(15.0));
+    }
+
+    #[test]
+    fn invalid_formula_keeps_editing() {
+        let mut app = App::new(Sheet::new(), None);
+        type_keys(&mut app, "=1+\n");
+
+        assert!(app.edit.is_some());
+        assert_eq!(app.cursor, CellRef::new(0, 0));
+        assert!(app.status.starts_with("A1: column 3"));
+
+        app.handle(KeyEvent::from(KeyCode::Esc));
+        assert!(app.edit.is_none());
+        assert_eq!(app.sheet.get(CellRef::new(0, 0)), None);
+    }
+
+    #[test]
+    fn edit_existing_input() {
+        let mut sheet = Sheet::new();
+        sheet.set(CellRef::new(0, 0), Cell::parse("=1+2").unwrap());
+        let mut app = App::new(sheet, None);
+        app.handle(KeyEvent::from(KeyCode::Enter));
+        app.handle(KeyEvent::from(KeyCode::Left));
+        app.handle(KeyEvent::from(KeyCode::Backspace));
+        type_keys(&mut app, "*\t");
+
+        assert_eq!(app.sheet.calculate(CellRef::new(0, 0)), Value::Number(2.0));
+        assert_eq!(app.cursor, CellRef::new(0, 1));
+    }
+
+    #[test]
+    fn undo_redo_keys() {
+        let mut app = App::new(Sheet::new(), None);
+        type_keys(&mut app, "1\n");
+        app.handle(KeyEvent::from(KeyCode::Up));
+        app.handle(KeyEvent::from(KeyCode::Delete));
+        assert_eq!(app.sheet.get(CellRef::new(0, 0)), None);
+
+        let ctrl = |c| KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL);
+        app.handle(ctrl('z'));
+        assert_eq!(app.sheet.calculate(CellRef::new(0, 0)), Value::Number(1.0));
+        app.handle(ctrl('z'));
+        app.handle(ctrl('z'));
+        assert_eq!(app.status, "nothing to undo");
+        app.handle(ctrl('y'));
+        assert_eq!(app.sheet.calculate(CellRef::new(0, 0)), Value::Number(1.0));
+    }
+
+    #[test]
+    fn scrolls_to_cursor() {
+        let mut app = App::new(Sheet::new(), None);
+        app.cursor = CellRef::new(30, 12);
+        app.scroll_to_cursor(40, 13);
+
+        assert_eq!(app.page, 10);
+        assert_eq!(app.scroll.row, 21);
+        let columns = app.visible_columns(40);
+        assert_eq!(columns.last().unwrap().0, 12);
+    }
+
+    #[test]
+    fn draws_values_and_input() {
+        let mut sheet = Sheet::new();
+        sheet.set(CellRef::new(0, 0), Cell::parse("=2*21").unwrap());
+        let mut app = App::new(sheet, None);
+        let mut out = Vec::new();
+        app.draw(&mut out, 40, 10).unwrap();
+        let screen = String::from_utf8(out).unwrap();
+
+        assert!(screen.contains("A1   =2*21"));
+        assert!(screen.contains("        42"));
+    }
+
+    #[test]
+    fn fits_text() {
+        assert_eq!(fit("abc", 5, false), "abc  ");
+        assert_eq!(fit("12", 5, true), "   12");
+        assert_eq!(fit("abcdef", 3, false), "abc");
+    }
+}
@@ -0,0 +1,387 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::ops::{Add, Div, Mul, Sub};
+
+use crate::decimal::{Arithmetic, Decimal};
+use crate::formula::Operation;
+
+/// Errors a cell can evaluate to, displayed the way a spreadsheet shows them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CellError {
+    DivByZero,
+    Ref,
+    Value,
+    Num,
+    /// A name that isn't defined.
+    Name,
+    /// A lookup that found nothing.
+    NotAvailable,
+}
+
+impl CellError {
+    /// Reads back the code shown by `Display`, e.g. `#REF!`.
+    pub fn from_code(code: &str) -> Option<CellError> {
+        match code {
+            "#DIV/0!" => Some(CellError::DivByZero),
+            "#REF!" => Some(CellError::Ref),
+            "#VALUE!" => Some(CellError::Value),
+            "#NUM!" => Some(CellError::Num),
+            "#NAME?" => Some(CellError::Name),
+            "#N/A" => Some(CellError::NotAvailable),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for CellError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let text = match self {
+            CellError::DivByZero => "#DIV/0!",
+            CellError::Ref => "#REF!",
+            CellError::Value => "#VALUE!",
+            CellError::Num => "#NUM!",
+            CellError::Name => "#NAME?",
+            CellError::NotAvailable => "#N/A",
+        };
+        write!(f, "{}", text)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Value {
+    #[default]
+    Empty,
+    Number(f64),
+    /// A number calculated with [`Arithmetic::Decimal`], or typed with more
+    /// digits than a float holds.
+    Decimal(Decimal),
+    Text(String),
+    Bool(bool),
+    Error(CellError),
+}
+
+impl Value {
+    /// Reads a literal the way a user would type it into a cell: numbers,
+    /// `TRUE`/`FALSE` (any case), and everything else as text. A literal
+    /// wrapped in double quotes is always text.
+    pub fn from_literal(literal: &str) -> Value {
+        if literal.is_empty() {
+            return Value::Empty;
+        }
+        if literal.len() >= 2 && literal.starts_with('"') && literal.ends_with('"') {
+            return Value::Text(literal[1..literal.len() - 1].to_string());
+        }
+        if let Some(number) = Value::parse_number(literal) {
+            return number;
+        }
+        match literal.to_uppercase().as_str() {
+            "TRUE" => Value::Bool(true),
+            "FALSE" => Value::Bool(false),
+            _ => Value::Text(literal.to_string()),
+        }
+    }
+
+    /// Reads a number the way it was typed, `None` for anything else. Digits
+    /// that a float can't hold are kept in a decimal, so that decimal
+    /// arithmetic calculates with what was typed.
+    pub(crate) fn parse_number(text: &str) -> Option<Value> {
+        let number = text.parse::<f64>().ok().filter(|n| n.is_finite())?;
+        match text.parse::<Decimal>() {
+            Ok(exact) if Decimal::from_f64(number).as_ref() != Some(&exact) => {
+                Some(Value::Decimal(exact))
+            }
+            _ => Some(Value::Number(number)),
+        }
+    }
+
+    /// Coerces the value to a number: empty cells count as 0, booleans as
+    /// 1/0 and text only when it reads as a number.
+    pub fn as_number(&self) -> Result<f64, CellError> {
+        match self {
+            Value::Empty => Ok(0.0),
+            Value::Number(n) => Ok(*n),
+            Value::Decimal(d) => match d.to_f64() {
+                n if n.is_finite() => Ok(n),
+                _ => Err(CellError::Num),
+            },
+            Value::Bool(b) => Ok(if *b { 1.0 } else { 0.0 }),
+            Value::Text(text) => match text.trim().parse::<f64>() {
+                Ok(n) if n.is_finite() => Ok(n),
+                _ => Err(CellError::Value),
+            },
+            Value::Error(err) => Err(*err),
+        }
+    }
+
+    /// Coerces the value to a boolean: numbers are true when non-zero and
+    /// text only when it reads `TRUE` or `FALSE`.
+    pub fn as_bool(&self) -> Result<bool, CellError> {
+        match self {
+            Value::Empty => Ok(false),
+            Value::Number(n) => Ok(*n != 0.0),
+            Value::Decimal(d) => Ok(!d.is_zero()),
+            Value::Bool(b) => Ok(*b),
+            Value::Text(text) => match text.trim().to_uppercase().as_str() {
+                "TRUE" => Ok(true),
+                "FALSE" => Ok(false),
+                _ => Err(CellError::Value),
+            },
+            Value::Error(err) => Err(*err),
+        }
+    }
+
+    /// Coerces the value to text, as it would be shown in the cell.
+    pub fn as_text(&self) -> Result<String, CellError> {
+        match self {
+            Value::Error(err) => Err(*err),
+            _ => Ok(self.to_string()),
+        }
+    }
+
+    /// Coerces the value to a decimal, like [`Value::as_number`] but with
+    /// text read exactly.
+    pub fn as_decimal(&self) -> Result<Decimal, CellError> {
+        match self {
+            Value::Empty => Ok(Decimal::from(0)),
+            Value::Number(n) => Decimal::from_f64(*n).ok_or(CellError::Num),
+            Value::Decimal(d) => Ok(d.clone()),
+            Value::Bool(b) => Ok(Decimal::from(*b as i64)),
+            Value::Text(text) => text.parse().map_err(|_| CellError::Value),
+            Value::Error(err) => Err(*err),
+        }
+    }
+
+    pub fn is_error(&self) -> bool {
+        matches!(self, Value::Error(_))
+    }
+
+    pub fn is_number(&self) -> bool {
+        matches!(self, Value::Number(_) | Value::Decimal(_))
+    }
+
+    /// The value with its number, if it is one, turned into the kind
+    /// `arithmetic` calculates with.
+    pub(crate) fn for_arithmetic(self, arithmetic: Arithmetic) -> Value {
+        match (self, arithmetic) {
+            (Value::Number(n), Arithmetic::Decimal { .. }) => {
+                Decimal::from_f64(n).map_or(CellError::Num.into(), Value::Decimal)
+            }
+            (value @ Value::Decimal(_), Arithmetic::Float) => match value.as_number() {
+                Ok(n) => Value::Number(n),
+                Err(err) => Value::Error(err),
+            },
+            (value, _) => value,
+        }
+    }
+
+    /// Orders numbers of either kind by size, `None` unless both are numbers.
+    pub(crate) fn compare_numbers(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (Value::Number(a), Value::Number(b)) => a.partial_cmp(b),
+            (Value::Decimal(a), Value::Decimal(b)) => Some(a.cmp(b)),
+            (Value::Decimal(a), Value::Number(b)) => Some(a.cmp(&Decimal::from_f64(*b)?)),
+            (Value::Number(a), Value::Decimal(b)) => Some(Decimal::from_f64(*a)?.cmp(b)),
+            _ => None,
+        }
+    }
+
+    /// Calculates `self op rhs` with both sides coerced to numbers of the
+    /// kind `arithmetic` uses.
+    pub(crate) fn calculate(&self, op: Operation, rhs: &Value, arithmetic: Arithmetic) -> Value {
+        let result = match arithmetic {
+            Arithmetic::Float => self
+                .as_number()
+                .and_then(|lhs| float(op, lhs, rhs.as_number()?))
+                .map(Value::Number),
+            Arithmetic::Decimal { scale, rounding } => self
+                .as_decimal()
+                .and_then(|lhs| {
+                    let rhs = rhs.as_decimal()?;
+                    match op {
+                        Operation::Add => lhs.checked_add(&rhs).ok_or(CellError::Num),
+                        Operation::Sub => lhs.checked_sub(&rhs).ok_or(CellError::Num),
+                        Operation::Mult => lhs.checked_mul(&rhs).ok_or(CellError::Num),
+                        Operation::Div if rhs.is_zero() => Err(CellError::DivByZero),
+                        Operation::Div => lhs.div(&rhs, scale, rounding).ok_or(CellError::Num),
+                    }
+                })
+                .map(Value::Decimal),
+        };
+        result.unwrap_or_else(Value::Error)
+    }
+}
+
+fn float(op: Operation, lhs: f64, rhs: f64) -> Result<f64, CellError> {
+    let result = match op {
+        Operation::Add => lhs + rhs,
+        Operation::Sub => lhs - rhs,
+        Operation::Mult => lhs * rhs,
+        Operation::Div if rhs == 0.0 => return Err(CellError::DivByZero),
+        Operation::Div => lhs / rhs,
+    };
+    if result.is_finite() {
+        Ok(result)
+    } else {
+        Err(CellError::Num)
+    }
+}
+
+impl From<f64> for Value {
+    fn from(n: f64) -> Value {
+        Value::Number(n)
+    }
+}
+
+impl From<bool> for Value {
+    fn from(b: bool) -> Value {
+        Value::Bool(b)
+    }
+}
+
+impl From<&str> for Value {
+    fn from(text: &str) -> Value {
+        Value::Text(text.to_string())
+    }
+}
+
+impl From<CellError> for Value {
+    fn from(err: CellError) -> Value {
+        Value::Error(err)
+    }
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Value::Empty => Ok(()),
+            // `-0` is an artifact of floating point, not something to show
+            Value::Number(n) if *n == 0.0 => write!(f, "0"),
+            Value::Number(n) => write!(f, "{}", n),
+            Value::Decimal(d) => write!(f, "{}", d),
+            Value::Text(text) => write!(f, "{}", text),
+            Value::Bool(true) => write!(f, "TRUE"),
+            Value::Bool(false) => write!(f, "FALSE"),
+            Value::Error(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Add for &Value {
+    type Output = Value;
+
+    fn add(self, rhs: &Value) -> Value {
+        self.calculate(Operation::Add, rhs, Arithmetic::Float)
+    }
+}
+
+impl Sub for &Value {
+    type Output = Value;
+
+    fn sub(self, rhs: &Value) -> Value {
+        self.calculate(Operation::Sub, rhs, Arithmetic::Float)
+    }
+}
+
+impl Mul for &Value {
+    type Output = Value;
+
+    fn mul(self, rhs: &Value) -> Value {
+        self.calculate(Operation::Mult, rhs, Arithmetic::Float)
+    }
+}
+
+impl Div for &Value {
+    type Output = Value;
+
+    fn div(self, rhs: &Value) -> Value {
+        self.calculate(Operation::Div, rhs, Arithmetic::Float)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn literals() {
+        assert_eq!(Value::from_literal("12"), Value::Number(12.0));
+        assert_eq!(Value::from_literal("-1.5"), Value::Number(-1.5));
+        assert_eq!(Value::from_literal("true"), Value::Bool(true));
+        assert_eq!(Value::from_literal("FALSE"), Value::Bool(false));
+        assert_eq!(Value::from_literal("abc"), Value::from("abc"));
+        assert_eq!(Value::from_literal("\"12\""), Value::from("12"));
+        assert_eq!(Value::from_literal("inf"), Value::from("inf"));
+        assert_eq!(
+            Value::from_literal("1e999999999"),
+            Value::from("1e999999999")
+        );
+        assert_eq!(
+            Value::from_literal("0.1000000000000000055"),
+            Value::Decimal("0.1000000000000000055".parse().unwrap())
+        );
+        assert_eq!(Value::from_literal(""), Value::Empty);
+    }
+
+    #[test]
+    fn coercion_to_decimal() {
+        assert_eq!(
+            Value::from("1.25").as_decimal(),
+            Ok("1.25".parse().unwrap())
+        );
+        assert_eq!(
+            Value::from("1e999999999").as_decimal(),
+            Err(CellError::Value)
+        );
+    }
+
+    #[test]
+    fn coercion_to_number() {
+        assert_eq!(Value::Empty.as_number(), Ok(0.0));
+        assert_eq!(Value::Bool(true).as_number(), Ok(1.0));
+        assert_eq!(Value::from(" 4 ").as_number(), Ok(4.0));
+        assert_eq!(Value::from("four").as_number(), Err(CellError::Value));
+        assert_eq!(
+            Value::Error(CellError::Ref).as_number(),
+            Err(CellError::Ref)
+        );
+    }
+
+    #[test]
+    fn arithmetic() {
+        assert_eq!(&Value::from(2.0) + &Value::from("3"), Value::Number(5.0));
+        assert_eq!(&Value::Bool(true) - &Value::Empty, Value::Number(1.0));
+        assert_eq!(&Value::from(2.5) * &Value::from(4.0), Value::Number(10.0));
+        assert_eq!(&Value::from(1.0) / &Value::from(4.0), Value::Number(0.25));
+    }
+
+    #[test]
+    fn arithmetic_errors() {
+        assert_eq!(
+            &Value::from(1.0) / &Value::Empty,
+            CellError::DivByZero.into()
+        );
+        assert_eq!(
+            &Value::from("a") + &Value::from(1.0),
+            CellError::Value.into()
+        );
+        assert_eq!(
+            &Value::from(f64::MAX) * &Value::from(2.0),
+            CellError::Num.into()
+        );
+        // the left-most error wins
+        assert_eq!(
+            &Value::Error(CellError::Ref) + &Value::Error(CellError::DivByZero),
+            CellError::Ref.into()
+        );
+    }
+
+    #[test]
+    fn display() {
+        assert_eq!(Value::Number(3.0).to_string(), "3");
+        assert_eq!(Value::Number(-0.0).to_string(), "0");
+        assert_eq!(Value::Number(0.5).to_string(), "0.5");
+        assert_eq!(Value::Bool(true).to_string(), "TRUE");
+        assert_eq!(Value::Empty.to_string(), "");
+        assert_eq!(Value::Error(CellError::DivByZero).to_string(), "#DIV/0!");
+    }
+}
@@ -0,0 +1,846 @@
+//! Several named sheets whose formulas read each other's cells with
+//! references like `Inputs!B3` or `SUM(Data!A1:A10)`.
+
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::error::Error;
+use std::fmt;
+use std::mem;
+
+use crate::decimal::Arithmetic;
+use crate::formula::Expr;
+use crate::name::{self, Name, NameError};
+use crate::reference::{same_name, Axis, CellRef, Range, Shift};
+use crate::sheet::{evaluate, Cell, Edit, Resolve, Sheet};
+use crate::value::{CellError, Value};
+
+/// A cell of the workbook: index of its sheet and its position there.
+type Key = (usize, CellRef);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum WorkbookError {
+    UnknownSheet(String),
+    DuplicateSheet(String),
+    /// Empty, or containing one of the characters spreadsheets reserve.
+    InvalidSheetName(String),
+    Name(NameError),
+}
+
+impl fmt::Display for WorkbookError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            WorkbookError::UnknownSheet(name) => write!(f, "no sheet named '{}'", name),
+            WorkbookError::DuplicateSheet(name) => {
+                write!(f, "there is already a sheet named '{}'", name)
+            }
+            WorkbookError::InvalidSheetName(name) => write!(f, "invalid sheet name '{}'", name),
+            WorkbookError::Name(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for WorkbookError {}
+
+impl From<NameError> for WorkbookError {
+    fn from(err: NameError) -> WorkbookError {
+        WorkbookError::Name(err)
+    }
+}
+
+/// Which formulas read which cells, across all sheets, so that an edit only
+/// throws away the values that depend on it.
+#[derive(Default)]
+struct Dependencies {
+    /// Formulas reading each single cell.
+    cells: HashMap<Key, HashSet<Key>>,
+    /// Formulas reading a block of cells, `(sheet, range, formula)`.
+    ranges: Vec<(usize, Range, Key)>,
+    /// What every formula reads, to forget it again when the formula changes.
+    reads: HashMap<Key, Vec<(usize, Range)>>,
+}
+
+impl Dependencies {
+    fn add(&mut self, formula: Key, reads: Vec<(usize, Range)>) {
+        for (sheet, range) in &reads {
+            if range.start == range.end {
+                self.cells
+                    .entry((*sheet, range.start))
+                    .or_default()
+                    .insert(formula);
+            } else {
+                self.ranges.push((*sheet, *range, formula));
+            }
+        }
+        self.reads.insert(formula, reads);
+    }
+
+    fn remove(&mut self, formula: Key) {
+        let Some(reads) = self.reads.remove(&formula) else {
+            return;
+        };
+        for (sheet, range) in reads {
+            if range.start == range.end {
+                if let Some(formulas) = self.cells.get_mut(&(sheet, range.start)) {
+                    formulas.remove(&formula);
+                }
+            } else {
+                self.ranges.retain(|(_, _, key)| *key != formula);
+            }
+        }
+    }
+
+    /// Formulas reading the cell directly.
+    fn dependents(&self, (sheet, at): Key) -> impl Iterator<Item = Key> + '_ {
+        let cells = self.cells.get(&(sheet, at)).into_iter().flatten().copied();
+        let ranges = self
+            .ranges
+            .iter()
+            .filter(move |(s, range, _)| *s == sheet && range.contains(at))
+            .map(|(_, _, formula)| *formula);
+        cells.chain(ranges)
+    }
+}
+
+/// Named sheets calculated together. Sheet names are case-insensitive and
+/// sheets keep the order they were added in.
+///
+/// Names can be defined for the whole workbook or, with
+/// [`Sheet::define_name`], for a single sheet. A sheet's own names hide
+/// workbook names of the same name in its formulas.
+///
+/// Formulas calculate with floating point numbers unless the workbook is set
+/// to [`Arithmetic::Decimal`], which all operators and functions then use.
+#[derive(Default)]
+pub struct Workbook {
+    sheets: Vec<(String, Sheet)>,
+    names: BTreeMap<String, Name>,
+    arithmetic: Arithmetic,
+    cache: HashMap<Key, Value>,
+    /// `None` after changes the workbook can't follow cell by cell, it is
+    /// rebuilt on the next edit.
+    dependencies: Option<Dependencies>,
+}
+
+impl Workbook {
+    pub fn new() -> Workbook {
+        Workbook::default()
+    }
+
+    pub fn add_sheet(&mut self, name: &str, sheet: Sheet) -> Result<(), WorkbookError> {
+        check_sheet_name(name)?;
+        if self.index(name).is_some() {
+            return Err(WorkbookError::DuplicateSheet(name.to_string()));
+        }
+        self.sheets.push((name.to_string(), sheet));
+        // formulas that read the new sheet while it was missing were #REF!
+        self.invalidate();
+        Ok(())
+    }
+
+    /// Removes a sheet, references to it in the other sheets become `#REF!`.
+    pub fn remove_sheet(&mut self, name: &str) -> Result<Sheet, WorkbookError> {
+        let index = self.find(name)?;
+        let (name, sheet) = self.sheets.remove(index);
+        self.rewrite_references(&name, None);
+        Ok(sheet)
+    }
+
+    /// Renames a sheet along with every reference to it.
+    pub fn rename_sheet(&mut self, from: &str, to: &str) -> Result<(), WorkbookError> {
+        let index = self.find(from)?;
+        check_sheet_name(to)?;
+        if self.index(to).is_some_and(|other| other != index) {
+            return Err(WorkbookError::DuplicateSheet(to.to_string()));
+        }
+        let from = std::mem::replace(&mut self.sheets[index].0, to.to_string());
+        self.rewrite_references(&from, Some(to));
+        Ok(())
+    }
+
+    pub fn sheet_names(&self) -> impl Iterator<Item = &str> {
+        self.sheets.iter().map(|(name, _)| name.as_str())
+    }
+
+    pub fn sheet(&self, name: &str) -> Option<&Sheet> {
+        self.index(name).map(|index| &self.sheets[index].1)
+    }
+
+    /// Direct access to a sheet, e.g. for undoable edits. Since the workbook
+    /// can't tell what changed, all values are recalculated afterwards;
+    /// prefer [`Workbook::set`] for single cells. Rows and columns inserted
+    /// or deleted there only move the sheet's own references, use
+    /// [`Workbook::insert`] and [`Workbook::delete`] instead.
+    pub fn sheet_mut(&mut self, name: &str) -> Option<&mut Sheet> {
+        let index = self.index(name)?;
+        self.invalidate();
+        Some(&mut self.sheets[index].1)
+    }
+
+    /// Replaces the content of a cell, recalculating only the formulas that
+    /// depend on it, on any sheet.
+    pub fn set(&mut self, sheet: &str, at: CellRef, cell: Cell) -> Result<(), WorkbookError> {
+        let index = self.find(sheet)?;
+        let key = (index, at);
+        let reads = match &cell {
+            Cell::Formula(expr) => self.reads(index, expr),
+            Cell::Literal(_) => Vec::new(),
+        };
+        if self.dependencies.is_none() {
+            self.dependencies = Some(self.dependencies());
+        }
+        let dependencies = self.dependencies.as_mut().unwrap();
+
+        let mut stale = vec![key];
+        let mut seen = HashSet::from([key]);
+        while let Some(key) = stale.pop() {
+            self.cache.remove(&key);
+            for dependent in dependencies.dependents(key) {
+                if seen.insert(dependent) {
+                    stale.push(dependent);
+                }
+            }
+        }
+
+        dependencies.remove(key);
+        dependencies.add(key, reads);
+        self.sheets[index].1.set(at, cell);
+        Ok(())
+    }
+
+    /// Inserts `count` empty rows or columns on `sheet` before row or column
+    /// `at`, moving the ones after them along with every reference to them:
+    /// in formulas on all sheets and in names. Only the changes to the sheet
+    /// itself are recorded in its undo history.
+    pub fn insert(
+        &mut self,
+        sheet: &str,
+        axis: Axis,
+        at: usize,
+        count: usize,
+    ) -> Result<(), WorkbookError> {
+        self.shift(
+            sheet,
+            Shift {
+                axis,
+                at,
+                count,
+                insert: true,
+            },
+        )
+    }
+
+    /// Deletes rows or columns `at..at + count` of `sheet` like
+    /// [`Workbook::insert`] inserts them. References to the deleted cells
+    /// become `#REF!`.
+    pub fn delete(
+        &mut self,
+        sheet: &str,
+        axis: Axis,
+        at: usize,
+        count: usize,
+    ) -> Result<(), WorkbookError> {
+        self.shift(
+            sheet,
+            Shift {
+                axis,
+                at,
+                count,
+                insert: false,
+            },
+        )
+    }
+
+    fn shift(&mut self, sheet: &str, shift: Shift) -> Result<(), WorkbookError> {
+        let index = self.find(sheet)?;
+        let Shift {
+            axis, at, count, ..
+        } = shift;
+        let edit = match (axis, shift.insert) {
+            (Axis::Row, true) => Edit::InsertRows { at, count },
+            (Axis::Row, false) => Edit::DeleteRows { at, count },
+            (Axis::Column, true) => Edit::InsertColumns { at, count },
+            (Axis::Column, false) => Edit::DeleteColumns { at, count },
+        };
+        // the sheet's own cells, references and names
+        self.sheets[index].1.apply(edit);
+
+        let name = self.sheets[index].0.clone();
+        for (_, sheet) in &mut self.sheets {
+            sheet.rewrite_formulas(|expr| expr.sheet_shifted(&name, &shift));
+            sheet.shift_sheet_in_names(&name, &shift);
+        }
+        self.names = mem::take(&mut self.names)
+            .into_iter()
+            .filter_map(|(defined, definition)| {
+                Some((defined, definition.sheet_shifted(&name, &shift)?))
+            })
+            .collect();
+        self.invalidate();
+        Ok(())
+    }
+
+    pub fn calculate(&mut self, sheet: &str, at: CellRef) -> Result<Value, WorkbookError> {
+        let index = self.find(sheet)?;
+        Ok(self.value((index, at)))
+    }
+
+    fn value(&mut self, key: Key) -> Value {
+        if let Some(value) = self.cache.get(&key) {
+            return value.clone();
+        }
+        let (index, at) = key;
+        let value = match self.sheets[index].1.get(at).cloned() {
+            None => Value::Empty,
+            Some(Cell::Literal(value)) => value,
+            Some(Cell::Formula(expr)) => {
+                // circular references read as #REF!, as within a sheet
+                self.cache.insert(key, Value::Error(CellError::Ref));
+                let mut lookup = Lookup {
+                    workbook: self,
+                    sheet: index,
+                };
+                evaluate(&expr, &mut lookup)
+            }
+        };
+        self.cache.insert(key, value.clone());
+        value
+    }
+
+    /// Defines a name for formulas on all sheets, replacing any definition
+    /// it had. A name for cells has to say which sheet they are on.
+    pub fn define_name(
+        &mut self,
+        name: &str,
+        definition: impl Into<Name>,
+    ) -> Result<(), WorkbookError> {
+        name::check_name(name)?;
+        let definition = definition.into();
+        match &definition {
+            Name::Range { sheet: None, .. } => {
+                return Err(NameError::MissingSheet(name.to_string()).into())
+            }
+            Name::Range {
+                sheet: Some(sheet), ..
+            } => {
+                self.find(sheet)?;
+            }
+            Name::Constant(_) => {}
+        }
+        self.names.retain(|defined, _| !same_name(defined, name));
+        self.names.insert(name.to_string(), definition);
+        self.invalidate();
+        Ok(())
+    }
+
+    /// Removes a workbook name, formulas still using it show `#NAME?`.
+    pub fn remove_name(&mut self, name: &str) -> Result<Name, WorkbookError> {
+        let defined = self.defined_name(name)?;
+        self.invalidate();
+        Ok(self.names.remove(&defined).unwrap())
+    }
+
+    /// Renames a workbook name along with every formula using it, except on
+    /// sheets where a name of their own hides it.
+    pub fn rename_name(&mut self, from: &str, to: &str) -> Result<(), WorkbookError> {
+        let defined = self.defined_name(from)?;
+        name::check_name(to)?;
+        if self
+            .names
+            .keys()
+            .any(|other| *other != defined && same_name(other, to))
+        {
+            return Err(NameError::DuplicateName(to.to_string()).into());
+        }
+        let definition = self.names.remove(&defined).unwrap();
+        self.names.insert(to.to_string(), definition);
+        for (_, sheet) in &mut self.sheets {
+            if sheet.name(from).is_none() {
+                sheet.rewrite_formulas(|expr| expr.name_renamed(from, to));
+            }
+        }
+        self.invalidate();
+        Ok(())
+    }
+
+    /// What a workbook name stands for.
+    pub fn name(&self, name: &str) -> Option<&Name> {
+        self.names
+            .iter()
+            .find(|(defined, _)| same_name(defined, name))
+            .map(|(_, definition)| definition)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = (&str, &Name)> {
+        self.names
+            .iter()
+            .map(|(name, definition)| (name.as_str(), definition))
+    }
+
+    pub fn arithmetic(&self) -> Arithmetic {
+        self.arithmetic
+    }
+
+    /// Switches how numbers are calculated, recalculating every formula.
+    pub fn set_arithmetic(&mut self, arithmetic: Arithmetic) {
+        self.arithmetic = arithmetic;
+        self.invalidate();
+    }
+
+    fn defined_name(&self, name: &str) -> Result<String, NameError> {
+        self.names
+            .keys()
+            .find(|defined| same_name(defined, name))
+            .cloned()
+            .ok_or_else(|| NameError::UnknownName(name.to_string()))
+    }
+
+    /// What a name used by a formula on sheet `index` stands for.
+    fn definition(&self, index: usize, name: &str) -> Option<&Name> {
+        self.sheets[index].1.name(name).or_else(|| self.name(name))
+    }
+
+    fn index(&self, name: &str) -> Option<usize> {
+        self.sheets
+            .iter()
+            .position(|(sheet, _)| same_name(sheet, name))
+    }
+
+    fn find(&self, name: &str) -> Result<usize, WorkbookError> {
+        self.index(name)
+            .ok_or_else(|| WorkbookError::UnknownSheet(name.to_string()))
+    }
+
+    fn invalidate(&mut self) {
+        self.cache.clear();
+        self.dependencies = None;
+    }
+
+    /// Points references and names for the sheet `from` at `to`, or at
+    /// `#REF!`.
+    fn rewrite_references(&mut self, from: &str, to: Option<&str>) {
+        for (_, sheet) in &mut self.sheets {
+            sheet.rewrite_formulas(|expr| expr.sheet_renamed(from, to));
+            sheet.rename_sheet_in_names(from, to);
+        }
+        for definition in self.names.values_mut() {
+            *definition = definition.sheet_renamed(from, to);
+        }
+        self.invalidate();
+    }
+
+    fn dependencies(&self) -> Dependencies {
+        let mut dependencies = Dependencies::default();
+        for (index, (_, sheet)) in self.sheets.iter().enumerate() {
+            for (at, cell) in sheet.cells() {
+                if let Cell::Formula(expr) = cell {
+                    dependencies.add((index, at), self.reads(index, expr));
+                }
+            }
+        }
+        dependencies
+    }
+
+    /// The blocks of cells a formula on sheet `index` reads, directly or
+    /// through names. References to missing sheets read nothing: adding the
+    /// sheet recalculates everything.
+    fn reads(&self, index: usize, expr: &Expr) -> Vec<(usize, Range)> {
+        let names =
+            expr.names()
+                .into_iter()
+                .filter_map(|name| match self.definition(index, name)? {
+                    Name::Range { sheet, range } => Some((sheet.as_deref(), *range)),
+                    Name::Constant(_) => None,
+                });
+        expr.references()
+            .into_iter()
+            .chain(names)
+            .filter_map(|(sheet, range)| {
+                let sheet = match sheet {
+                    None => index,
+                    Some(name) => self.index(name)?,
+                };
+                Some((sheet, range))
+            })
+            .collect()
+    }
+}
+
+/// Resolves what a formula on `sheet` reads in the workbook.
+struct Lookup<'a> {
+    workbook: &'a mut Workbook,
+    sheet: usize,
+}
+
+impl Resolve for Lookup<'_> {
+    fn cell(&mut self, sheet: Option<&str>, at: CellRef) -> Value {
+        let sheet = match sheet {
+            None => Some(self.sheet),
+            Some(name) => self.workbook.index(name),
+        };
+        match sheet {
+            Some(sheet) => self.workbook.value((sheet, at)),
+            None => Value::Error(CellError::Ref),
+        }
+    }
+
+    fn name(&self, name: &str) -> Option<Name> {
+        self.workbook.definition(self.sheet, name).cloned()
+    }
+
+    fn rows(&self, sheet: Option<&str>) -> Option<usize> {
+        let sheet = match sheet {
+            None => self.sheet,
+            Some(name) => self.workbook.index(name)?,
+        };
+        self.workbook.sheets[sheet].1.rows(None)
+    }
+
+    fn arithmetic(&self) -> Arithmetic {
+        self.workbook.arithmetic
+    }
+}
+
+fn check_sheet_name(name: &str) -> Result<(), WorkbookError> {
+    if name.is_empty() || name.contains(['\'', '!', '[', ']', ':', '*', '?', '/', '\\']) {
+        Err(WorkbookError::InvalidSheetName(name.to_string()))
+    } else {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::decimal::{Decimal, Rounding};
+
+    fn workbook() -> Workbook {
+        let mut workbook = Workbook::new();
+        for name in ["Inputs", "Calculation", "Report"] {
+            workbook.add_sheet(name, Sheet::new()).unwrap();
+        }
+        set(&mut workbook, "Inputs", "A1", "2");
+        set(&mut workbook, "Inputs", "A2", "3");
+        set(&mut workbook, "Inputs", "A3", "4");
+        set(&mut workbook, "Calculation", "A1", "=SUM(Inputs!A1:A3)");
+        set(&mut workbook, "Calculation", "A2", "=A1*inputs!A1");
+        set(&mut workbook, "Report", "B2", "=Calculation!A2+1");
+        workbook
+    }
+
+    fn set(workbook: &mut Workbook, sheet: &str, at: &str, input: &str) {
+        let cell = Cell::parse(input).unwrap();
+        workbook
+            .set(sheet, CellRef::parse(at).unwrap(), cell)
+            .unwrap();
+    }
+
+    fn calculate(workbook: &mut Workbook, sheet: &str, at: &str) -> Value {
+        workbook
+            .calculate(sheet, CellRef::parse(at).unwrap())
+            .unwrap()
+    }
+
+    fn input(workbook: &Workbook, sheet: &str, at: &str) -> String {
+        workbook
+            .sheet(sheet)
+            .unwrap()
+            .get(CellRef::parse(at).unwrap())
+            .unwrap()
+            .to_string()
+    }
+
+    #[test]
+    fn cross_sheet_references() {
+        let mut workbook = workbook();
+
+        assert_eq!(calculate(&mut workbook, "Calculation", "A1"), 9.0.into());
+        assert_eq!(calculate(&mut workbook, "Report", "B2"), 19.0.into());
+        assert_eq!(calculate(&mut workbook, "report", "A1"), Value::Empty);
+    }
+
+    #[test]
+    fn edits_recalculate_dependents_on_other_sheets() {
+        let mut workbook = workbook();
+        set(&mut workbook, "Report", "C1", "=Inputs!B1");
+        assert_eq!(calculate(&mut workbook, "Report", "B2"), 19.0.into());
+        assert_eq!(calculate(&mut workbook, "Report", "C1"), Value::Empty);
+
+        // inside the summed range, and read directly
+        set(&mut workbook, "Inputs", "A1", "1");
+        assert_eq!(calculate(&mut workbook, "Report", "B2"), 9.0.into());
+        set(&mut workbook, "Inputs", "A3", "10");
+        assert_eq!(calculate(&mut workbook, "Report", "B2"), 15.0.into());
+
+        set(&mut workbook, "Report", "C1", "=Calculation!A1");
+        assert_eq!(calculate(&mut workbook, "Report", "C1"), 14.0.into());
+        // C1 no longer reads Inputs!B1
+        set(&mut workbook, "Inputs", "B1", "x");
+        assert_eq!(calculate(&mut workbook, "Report", "C1"), 14.0.into());
+    }
+
+    #[test]
+    fn circular_references_across_sheets() {
+        let mut workbook = workbook();
+        set(&mut workbook, "Inputs", "A1", "=Report!B2");
+
+        assert_eq!(
+            calculate(&mut workbook, "Report", "B2"),
+            CellError::Ref.into()
+        );
+    }
+
+    #[test]
+    fn missing_sheets() {
+        let mut workbook = workbook();
+        set(&mut workbook, "Report", "A1", "='Q1 Notes'!A1+1");
+        assert_eq!(
+            calculate(&mut workbook, "Report", "A1"),
+            CellError::Ref.into()
+        );
+
+        let mut notes = Sheet::new();
+        notes.set(CellRef::new(0, 0), Cell::parse("41").unwrap());
+        workbook.add_sheet("Q1 Notes", notes).unwrap();
+        assert_eq!(calculate(&mut workbook, "Report", "A1"), 42.0.into());
+
+        assert_eq!(
+            workbook.calculate("Nope", CellRef::new(0, 0)),
+            Err(WorkbookError::UnknownSheet("Nope".to_string()))
+        );
+    }
+
+    #[test]
+    fn renames_rewrite_references() {
+        let mut workbook = workbook();
+        workbook.rename_sheet("inputs", "Data 2024").unwrap();
+
+        assert_eq!(
+            input(&workbook, "Calculation", "A1"),
+            "=SUM('Data 2024'!A1:A3)"
+        );
+        assert_eq!(input(&workbook, "Calculation", "A2"), "=A1*'Data 2024'!A1");
+        assert_eq!(calculate(&mut workbook, "Report", "B2"), 19.0.into());
+        assert_eq!(
+            workbook.sheet_names().collect::<Vec<_>>(),
+            ["Data 2024", "Calculation", "Report"]
+        );
+    }
+
+    #[test]
+    fn removed_sheets_become_ref_errors() {
+        let mut workbook = workbook();
+        workbook.remove_sheet("Calculation").unwrap();
+
+        assert_eq!(input(&workbook, "Report", "B2"), "=#REF!+1");
+        assert_eq!(
+            calculate(&mut workbook, "Report", "B2"),
+            CellError::Ref.into()
+        );
+    }
+
+    #[test]
+    fn sheet_names_are_checked() {
+        let mut workbook = workbook();
+
+        assert_eq!(
+            workbook.add_sheet("REPORT", Sheet::new()),
+            Err(WorkbookError::DuplicateSheet("REPORT".to_string()))
+        );
+        assert_eq!(
+            workbook.rename_sheet("Report", "a/b"),
+            Err(WorkbookError::InvalidSheetName("a/b".to_string()))
+        );
+        assert!(workbook.rename_sheet("Report", "REPORT").is_ok());
+    }
+
+    #[test]
+    fn workbook_and_sheet_names() {
+        let mut workbook = workbook();
+        let prices = Name::Range {
+            sheet: Some("Inputs".to_string()),
+            range: Range::parse("A1:A3").unwrap(),
+        };
+        workbook.define_name("Prices", prices).unwrap();
+        workbook.define_name("Rate", Value::Number(2.0)).unwrap();
+        set(&mut workbook, "Report", "A1", "=SUM(Prices)*Rate");
+        set(&mut workbook, "Calculation", "B1", "=Rate");
+        let calculation = workbook.sheet_mut("Calculation").unwrap();
+        calculation.define_name("Rate", Value::Number(3.0)).unwrap();
+
+        assert_eq!(calculate(&mut workbook, "Report", "A1"), 18.0.into());
+        assert_eq!(calculate(&mut workbook, "Calculation", "B1"), 3.0.into());
+
+        // the name's cells are tracked like references
+        set(&mut workbook, "Inputs", "A2", "13");
+        assert_eq!(calculate(&mut workbook, "Report", "A1"), 38.0.into());
+
+        workbook.remove_name("rate").unwrap();
+        assert_eq!(
+            calculate(&mut workbook, "Report", "A1"),
+            CellError::Name.into()
+        );
+        assert_eq!(calculate(&mut workbook, "Calculation", "B1"), 3.0.into());
+    }
+
+    #[test]
+    fn workbook_name_renames() {
+        let mut workbook = workbook();
+        workbook.define_name("Rate", Value::Number(2.0)).unwrap();
+        set(&mut workbook, "Report", "A1", "=Rate*2");
+        set(&mut workbook, "Calculation", "B1", "=Rate");
+        let calculation = workbook.sheet_mut("Calculation").unwrap();
+        calculation.define_name("Rate", Value::Number(3.0)).unwrap();
+
+        workbook.rename_name("RATE", "Vat").unwrap();
+        assert_eq!(input(&workbook, "Report", "A1"), "=Vat*2");
+        // hidden by the sheet's own name
+        assert_eq!(input(&workbook, "Calculation", "B1"), "=Rate");
+        assert_eq!(calculate(&mut workbook, "Report", "A1"), 4.0.into());
+    }
+
+    #[test]
+    fn names_follow_sheet_renames() {
+        let mut workbook = workbook();
+        let first = Name::Range {
+            sheet: Some("Inputs".to_string()),
+            range: Range::parse("A1").unwrap(),
+        };
+        workbook.define_name("First", first).unwrap();
+        set(&mut workbook, "Report", "A1", "=First");
+
+        workbook.rename_sheet("Inputs", "Data").unwrap();
+        assert_eq!(workbook.name("first").unwrap().to_string(), "Data!A1");
+        assert_eq!(calculate(&mut workbook, "Report", "A1"), 2.0.into());
+
+        workbook.remove_sheet("Data").unwrap();
+        assert_eq!(
+            calculate(&mut workbook, "Report", "A1"),
+            CellError::Ref.into()
+        );
+    }
+
+    #[test]
+    fn workbook_names_need_a_sheet() {
+        let mut workbook = workbook();
+
+        assert_eq!(
+            workbook.define_name("Prices", Range::parse("A1:A3").unwrap()),
+            Err(WorkbookError::Name(NameError::MissingSheet(
+                "Prices".to_string()
+            )))
+        );
+        assert_eq!(
+            workbook.define_name("B2", Value::Number(1.0)),
+            Err(WorkbookError::Name(NameError::InvalidName(
+                "B2".to_string()
+            )))
+        );
+    }
+
+    #[test]
+    fn structural_edits_move_references_on_every_sheet() {
+        let mut workbook = workbook();
+        let prices = Name::Range {
+            sheet: Some("Inputs".to_string()),
+            range: Range::parse("A2:A3").unwrap(),
+        };
+        workbook.define_name("Prices", prices).unwrap();
+        set(&mut workbook, "Inputs", "B1", "=Inputs!A3*10");
+        set(&mut workbook, "Report", "A1", "=SUM(Prices)");
+
+        workbook.insert("inputs", Axis::Row, 0, 2).unwrap();
+        assert_eq!(input(&workbook, "Inputs", "A3"), "2");
+        assert_eq!(input(&workbook, "Inputs", "B3"), "=Inputs!A5*10");
+        assert_eq!(input(&workbook, "Calculation", "A1"), "=SUM(Inputs!A3:A5)");
+        assert_eq!(input(&workbook, "Calculation", "A2"), "=A1*inputs!A3");
+        assert_eq!(workbook.name("prices").unwrap().to_string(), "Inputs!A4:A5");
+        assert_eq!(calculate(&mut workbook, "Report", "B2"), 19.0.into());
+        assert_eq!(calculate(&mut workbook, "Report", "A1"), 7.0.into());
+
+        workbook.delete("Inputs", Axis::Row, 2, 1).unwrap();
+        assert_eq!(input(&workbook, "Calculation", "A1"), "=SUM(Inputs!A3:A4)");
+        assert_eq!(input(&workbook, "Calculation", "A2"), "=A1*#REF!");
+        assert_eq!(calculate(&mut workbook, "Calculation", "A1"), 7.0.into());
+
+        workbook.delete("Inputs", Axis::Column, 0, 1).unwrap();
+        assert_eq!(input(&workbook, "Calculation", "A1"), "=SUM(#REF!)");
+        assert!(workbook.name("Prices").is_none());
+        assert_eq!(
+            workbook.insert("Nope", Axis::Row, 0, 1),
+            Err(WorkbookError::UnknownSheet("Nope".to_string()))
+        );
+    }
+
+    #[test]
+    fn direct_sheet_edits_recalculate() {
+        let mut workbook = workbook();
+        assert_eq!(calculate(&mut workbook, "Report", "B2"), 19.0.into());
+
+        let inputs = workbook.sheet_mut("Inputs").unwrap();
+        inputs.set(CellRef::new(0, 0), Cell::parse("0").unwrap());
+        assert_eq!(calculate(&mut workbook, "Report", "B2"), 1.0.into());
+    }
+
+    #[test]
+    fn decimal_results_are_bounded() {
+        let mut workbook = Workbook::new();
+        workbook.add_sheet("Sheet1", Sheet::new()).unwrap();
+        workbook.set_arithmetic(Arithmetic::Decimal {
+            scale: 4,
+            rounding: Rounding::HalfUp,
+        });
+        set(&mut workbook, "Sheet1", "A1", "3");
+        for row in 2..=30 {
+            let input = format!("=A{}*A{}", row - 1, row - 1);
+            set(&mut workbook, "Sheet1", &format!("A{}", row), &input);
+        }
+
+        let a4 = Value::Decimal(Decimal::from(6561));
+        assert_eq!(calculate(&mut workbook, "Sheet1", "A4"), a4);
+        assert_eq!(
+            calculate(&mut workbook, "Sheet1", "A30"),
+            CellError::Num.into()
+        );
+    }
+
+    #[test]
+    fn decimal_arithmetic() {
+        let mut workbook = Workbook::new();
+        workbook.add_sheet("Sheet1", Sheet::new()).unwrap();
+        set(&mut workbook, "Sheet1", "A1", "0.1");
+        set(&mut workbook, "Sheet1", "A2", "0.2");
+        set(&mut workbook, "Sheet1", "B1", "=A1+A2");
+        set(&mut workbook, "Sheet1", "B2", "=2/3");
+        set(&mut workbook, "Sheet1", "B3", "=AVERAGE(A1:A2, 0.15)");
+        set(&mut workbook, "Sheet1", "B4", "=-SUM(A1:A2)*10");
+        set(&mut workbook, "Sheet1", "B5", "=1/0");
+        set(&mut workbook, "Sheet1", "A3", "0.1000000000000000055");
+        set(&mut workbook, "Sheet1", "A4", "1e999999999");
+        set(&mut workbook, "Sheet1", "B6", "=A3*10");
+        set(&mut workbook, "Sheet1", "B7", "=A4+1");
+        set(&mut workbook, "Sheet1", "A5", "1e-9000");
+        set(&mut workbook, "Sheet1", "B8", "=A5*A5/3");
+        let text =
+            |workbook: &mut Workbook, at: &str| calculate(workbook, "Sheet1", at).to_string();
+        assert_eq!(text(&mut workbook, "B1"), "0.30000000000000004");
+
+        workbook.set_arithmetic(Arithmetic::Decimal {
+            scale: 4,
+            rounding: Rounding::HalfUp,
+        });
+        assert_eq!(text(&mut workbook, "B1"), "0.3");
+        assert_eq!(text(&mut workbook, "B2"), "0.6667");
+        assert_eq!(text(&mut workbook, "B3"), "0.15");
+        assert_eq!(text(&mut workbook, "B4"), "-3");
+        assert_eq!(text(&mut workbook, "B5"), "#DIV/0!");
+        // the digits as they were typed
+        assert_eq!(text(&mut workbook, "B6"), "1.000000000000000055");
+        assert_eq!(text(&mut workbook, "B7"), "#VALUE!");
+        assert_eq!(text(&mut workbook, "B8"), "#NUM!");
+
+        workbook.set_arithmetic(Arithmetic::Decimal {
+            scale: 2,
+            rounding: Rounding::Down,
+        });
+        assert_eq!(text(&mut workbook, "B2"), "0.66");
+    }
+}
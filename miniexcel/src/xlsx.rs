@@ -0,0 +1,376 @@
+//! Reading and writing the XLSX format of other spreadsheet programs: a zip
+//! archive of XML parts, see [`Workbook::from_xlsx`] and
+//! [`Workbook::to_xlsx`].
+
+use std::error::Error;
+use std::fmt;
+use std::mem;
+
+use zip::result::ZipError;
+
+use crate::reference::{column_index, column_name, CellRef};
+use crate::sheet::Sheet;
+use crate::workbook::{Workbook, WorkbookError};
+
+mod read;
+mod write;
+
+#[derive(Debug)]
+pub enum XlsxError {
+    /// Not a zip archive, or a damaged one.
+    Zip(ZipError),
+    /// A part that isn't well-formed XML.
+    Xml {
+        part: String,
+        error: quick_xml::Error,
+    },
+    /// A part every workbook has, e.g. `xl/workbook.xml`.
+    MissingPart(String),
+    /// A part that doesn't hold what it should, e.g. a cell without position.
+    InvalidPart { part: String, message: String },
+    /// Sheets the workbook can't hold, e.g. because of their names.
+    Workbook(WorkbookError),
+}
+
+impl fmt::Display for XlsxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            XlsxError::Zip(err) => write!(f, "invalid archive: {}", err),
+            XlsxError::Xml { part, error } => write!(f, "{}: {}", part, error),
+            XlsxError::MissingPart(part) => write!(f, "missing part {}", part),
+            XlsxError::InvalidPart { part, message } => write!(f, "{}: {}", part, message),
+            XlsxError::Workbook(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl Error for XlsxError {}
+
+impl From<ZipError> for XlsxError {
+    fn from(err: ZipError) -> XlsxError {
+        XlsxError::Zip(err)
+    }
+}
+
+impl From<WorkbookError> for XlsxError {
+    fn from(err: WorkbookError) -> XlsxError {
+        XlsxError::Workbook(err)
+    }
+}
+
+/// A formula or name from an XLSX file that uses what isn't supported here,
+/// like an unknown function or operator. Formula cells keep the value the
+/// file had stored for them, names are left out.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Unsupported {
+    /// Sheet of the cell or of the name, `None` for workbook names.
+    pub sheet: Option<String>,
+    /// The formula's cell, or the name.
+    pub at: Location,
+    /// The formula or definition, without `=` and with references written
+    /// the way formulas here write them.
+    pub formula: String,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Location {
+    Cell(CellRef),
+    Name(String),
+}
+
+impl fmt::Display for Unsupported {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(sheet) = &self.sheet {
+            write!(f, "{}!", sheet)?;
+        }
+        match &self.at {
+            Location::Cell(at) => write!(f, "{}", at)?,
+            Location::Name(name) => write!(f, "{}", name)?,
+        }
+        write!(f, ": ={} ({})", self.formula, self.reason)
+    }
+}
+
+impl Workbook {
+    /// Reads the sheets, cells and names of an XLSX file. Formulas are
+    /// translated where they only use what is supported here, the others are
+    /// listed and their cells keep the values stored in the file.
+    pub fn from_xlsx(bytes: &[u8]) -> Result<(Workbook, Vec<Unsupported>), XlsxError> {
+        read::workbook(bytes)
+    }
+
+    /// Writes the workbook as an XLSX file, with the calculated value of
+    /// every formula stored along with it.
+    pub fn to_xlsx(&mut self) -> Result<Vec<u8>, XlsxError> {
+        write::workbook(self)
+    }
+}
+
+impl Sheet {
+    /// Reads the first sheet of an XLSX file along with the names defined
+    /// for it, see [`Workbook::from_xlsx`].
+    pub fn from_xlsx(bytes: &[u8]) -> Result<(Sheet, Vec<Unsupported>), XlsxError> {
+        let (mut workbook, unsupported) = read::workbook(bytes)?;
+        let Some(first) = workbook.sheet_names().next().map(str::to_string) else {
+            return Ok((Sheet::new(), unsupported));
+        };
+        let unsupported = unsupported
+            .into_iter()
+            .filter(|item| item.sheet.as_deref() == Some(first.as_str()))
+            .collect();
+        Ok((workbook.remove_sheet(&first)?, unsupported))
+    }
+
+    /// Writes the sheet as an XLSX file with a single sheet, `Sheet1`.
+    pub fn to_xlsx(&mut self) -> Result<Vec<u8>, XlsxError> {
+        let mut workbook = Workbook::new();
+        workbook.add_sheet("Sheet1", mem::take(self))?;
+        let bytes = write::workbook(&mut workbook);
+        *self = workbook.remove_sheet("Sheet1")?;
+        bytes
+    }
+}
+
+/// A cell reference in an XLSX formula, where `$` marks the parts that stay
+/// put when the formula is copied, `$A1`.
+struct Reference {
+    row: usize,
+    col: usize,
+    absolute_row: bool,
+    absolute_col: bool,
+}
+
+/// Rewrites the cell references in a formula in XLSX syntax, leaving text,
+/// quoted sheet names and everything else as it is. `rewrite` returns the
+/// reference to write, or `None` for one that no longer exists.
+fn map_references(formula: &str, mut rewrite: impl FnMut(Reference) -> Option<String>) -> String {
+    let chars: Vec<char> = formula.chars().collect();
+    let word = |c: char| c.is_alphanumeric() || c == '_' || c == '.' || c == '$';
+    let mut out = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        if c == '"' || c == '\'' {
+            // text and quoted sheet names, where the quote is doubled to
+            // escape it
+            let start = i;
+            i += 1;
+            while i < chars.len() {
+                if chars[i] == c && chars.get(i + 1) == Some(&c) {
+                    i += 2;
+                } else if chars[i] == c {
+                    i += 1;
+                    break;
+                } else {
+                    i += 1;
+                }
+            }
+            out.extend(&chars[start..i]);
+        } else if word(c) {
+            let start = i;
+            while i < chars.len() && word(chars[i]) {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            // `LOG10(` is a function and `A1!` a sheet, not references
+            let reference = match chars.get(i) {
+                Some('(' | '!') => None,
+                _ => parse_reference(&text),
+            };
+            match reference {
+                Some(reference) => match rewrite(reference) {
+                    Some(reference) => out.push_str(&reference),
+                    None => out.push_str("#REF!"),
+                },
+                None => out.push_str(&text),
+            }
+        } else {
+            out.push(c);
+            i += 1;
+        }
+    }
+    out
+}
+
+fn parse_reference(text: &str) -> Option<Reference> {
+    let (absolute_col, text) = match text.strip_prefix('$') {
+        Some(text) => (true, text),
+        None => (false, text),
+    };
+    let split = text.find(|c: char| !c.is_ascii_alphabetic())?;
+    let (letters, rest) = text.split_at(split);
+    let (absolute_row, digits) = match rest.strip_prefix('$') {
+        Some(digits) => (true, digits),
+        None => (false, rest),
+    };
+    if letters.is_empty() || letters.len() > 3 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let row = digits.parse::<usize>().ok()?.checked_sub(1)?;
+    Some(Reference {
+        row,
+        col: column_index(letters)?,
+        absolute_row,
+        absolute_col,
+    })
+}
+
+/// Translates the references of an XLSX formula, given without `=`, to
+/// the ones formulas here use. `offset` moves the relative references, for
+/// formulas shared by a block of cells.
+fn from_xlsx_formula(formula: &str, offset: (isize, isize)) -> String {
+    map_references(formula, |reference| {
+        let mut row = reference.row as isize;
+        let mut col = reference.col as isize;
+        if !reference.absolute_row {
+            row += offset.0;
+        }
+        if !reference.absolute_col {
+            col += offset.1;
+        }
+        if row < 0 || col < 0 {
+            return None;
+        }
+        Some(CellRef::new(row as usize, col as usize).to_string())
+    })
+}
+
+/// Marks every reference of a formula as absolute, as XLSX names need them
+/// to stand for the same cells wherever they are used.
+fn absolute(formula: &str) -> String {
+    map_references(formula, |reference| {
+        Some(format!(
+            "${}${}",
+            column_name(reference.col),
+            reference.row + 1
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::formula::Expr;
+    use crate::name::Name;
+    use crate::reference::Range;
+    use crate::sheet::Cell;
+    use crate::value::{CellError, Value};
+
+    fn set(workbook: &mut Workbook, sheet: &str, at: &str, input: &str) {
+        workbook
+            .set(
+                sheet,
+                CellRef::parse(at).unwrap(),
+                Cell::parse(input).unwrap(),
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn formulas_from_xlsx() {
+        let translate = |formula: &str| {
+            Expr::parse(&from_xlsx_formula(formula, (0, 0)))
+                .map(|expr| expr.to_string())
+                .map_err(|err| err.message)
+        };
+
+        assert_eq!(translate("$A$1+B$2*$C3"), Ok("A1+B2*C3".to_string()));
+        assert_eq!(
+            translate("SUM('Q1 Report'!$A1:A$9,\"$A$1\")"),
+            Ok("SUM('Q1 Report'!A1:A9,\"$A$1\")".to_string())
+        );
+        assert_eq!(
+            translate("LOG10(1)"),
+            Err("unknown function 'LOG10'".to_string())
+        );
+        assert_eq!(
+            translate("A1&\"x\""),
+            Err("unexpected character '&'".to_string())
+        );
+
+        assert_eq!(from_xlsx_formula("A1+$A1+A$1+$A$1", (2, 1)), "B3+A3+B1+A1");
+        assert_eq!(from_xlsx_formula("A2*2", (-2, 0)), "#REF!*2");
+    }
+
+    #[test]
+    fn absolute_references() {
+        assert_eq!(absolute("Data!B2:C10"), "Data!$B$2:$C$10");
+        assert_eq!(absolute("'A1 x'!A1"), "'A1 x'!$A$1");
+        assert_eq!(absolute("0.2"), "0.2");
+    }
+
+    #[test]
+    fn workbook_round_trip() {
+        let mut workbook = Workbook::new();
+        workbook.add_sheet("Inputs", Sheet::new()).unwrap();
+        workbook.add_sheet("Q1 Report", Sheet::new()).unwrap();
+        set(&mut workbook, "Inputs", "A1", "10");
+        set(&mut workbook, "Inputs", "A2", "<b> & \"text\"");
+        set(&mut workbook, "Inputs", "A3", "TRUE");
+        set(&mut workbook, "Inputs", "B1", "=A1*Rate");
+        set(&mut workbook, "Q1 Report", "A1", "=SUM(Inputs!A1:B1)+Local");
+        set(&mut workbook, "Q1 Report", "B2", "=1/0");
+        workbook.define_name("Rate", Value::Number(0.5)).unwrap();
+        workbook
+            .sheet_mut("Q1 Report")
+            .unwrap()
+            .define_name("Local", Range::parse("B3").unwrap())
+            .unwrap();
+        workbook
+            .sheet_mut("Inputs")
+            .unwrap()
+            .set_column_width(1, Some(20.0));
+
+        let bytes = workbook.to_xlsx().unwrap();
+        let (mut read, unsupported) = Workbook::from_xlsx(&bytes).unwrap();
+
+        assert_eq!(unsupported, []);
+        assert_eq!(
+            read.sheet_names().collect::<Vec<_>>(),
+            ["Inputs", "Q1 Report"]
+        );
+        for (sheet, at) in [("Inputs", "A2"), ("Inputs", "A3"), ("Q1 Report", "A1")] {
+            let at = CellRef::parse(at).unwrap();
+            assert_eq!(
+                read.sheet(sheet).unwrap().get(at),
+                workbook.sheet(sheet).unwrap().get(at)
+            );
+        }
+        let value = |workbook: &mut Workbook, at| {
+            workbook
+                .calculate("Q1 Report", CellRef::parse(at).unwrap())
+                .unwrap()
+        };
+        assert_eq!(value(&mut read, "A1"), Value::Number(15.0));
+        assert_eq!(value(&mut read, "B2"), CellError::DivByZero.into());
+        assert_eq!(read.name("rate"), Some(&Name::Constant(Value::Number(0.5))));
+        assert_eq!(
+            read.sheet("Q1 Report").unwrap().name("Local"),
+            Some(&Name::from(Range::parse("B3").unwrap()))
+        );
+        assert_eq!(read.sheet("Inputs").unwrap().column_width(1), Some(20.0));
+    }
+
+    #[test]
+    fn sheet_round_trip() {
+        let mut sheet = Sheet::new();
+        sheet.set(CellRef::new(0, 0), Cell::parse("2").unwrap());
+        sheet.set(CellRef::new(1, 0), Cell::parse("=A1*3").unwrap());
+
+        let bytes = sheet.to_xlsx().unwrap();
+        assert_eq!(sheet.calculate(CellRef::new(1, 0)), Value::Number(6.0));
+        let (mut read, unsupported) = Sheet::from_xlsx(&bytes).unwrap();
+
+        assert!(unsupported.is_empty());
+        assert_eq!(read.calculate(CellRef::new(1, 0)), Value::Number(6.0));
+    }
+
+    #[test]
+    fn not_an_archive() {
+        assert!(matches!(
+            Workbook::from_xlsx(b"a,b\n1,2\n"),
+            Err(XlsxError::Zip(_))
+        ));
+    }
+}
@@ -0,0 +1,608 @@
+use std::collections::HashMap;
+use std::io::{Cursor, Read};
+
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use zip::result::ZipError;
+use zip::ZipArchive;
+
+use super::{from_xlsx_formula, Location, Unsupported, XlsxError};
+use crate::formula::Expr;
+use crate::name::Name;
+use crate::reference::{same_name, CellRef, MAX_COLUMNS, MAX_ROWS};
+use crate::sheet::{Cell, Sheet};
+use crate::value::{CellError, Value};
+use crate::workbook::Workbook;
+
+type Archive<'a> = ZipArchive<Cursor<&'a [u8]>>;
+
+/// An XML element with the text directly inside it. Names are local, without
+/// their namespace prefix, which is all the parts of a workbook need.
+#[derive(Default)]
+struct Element {
+    name: String,
+    attributes: Vec<(String, String)>,
+    children: Vec<Element>,
+    text: String,
+}
+
+impl Element {
+    fn attribute(&self, name: &str) -> Option<&str> {
+        self.attributes
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    }
+
+    fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|child| child.name == name)
+    }
+
+    fn children<'a>(&'a self, name: &'a str) -> impl Iterator<Item = &'a Element> {
+        self.children.iter().filter(move |child| child.name == name)
+    }
+
+    /// Text of a string that may be split into formatted runs, leaving out
+    /// phonetic hints.
+    fn rich_text(&self) -> String {
+        match self.name.as_str() {
+            "t" => self.text.clone(),
+            "rPh" => String::new(),
+            _ => self.children.iter().map(Element::rich_text).collect(),
+        }
+    }
+}
+
+fn parse(part: &str, xml: &str) -> Result<Element, XlsxError> {
+    let error = |error| XlsxError::Xml {
+        part: part.to_string(),
+        error,
+    };
+    let mut reader = Reader::from_str(xml);
+    // the document element ends up as the only child of the first element
+    let mut open = vec![Element::default()];
+    loop {
+        match reader.read_event().map_err(error)? {
+            Event::Start(start) => open.push(element(&start).map_err(error)?),
+            Event::Empty(start) => {
+                let element = element(&start).map_err(error)?;
+                open.last_mut().unwrap().children.push(element);
+            }
+            Event::End(_) if open.len() > 1 => {
+                let element = open.pop().unwrap();
+                open.last_mut().unwrap().children.push(element);
+            }
+            Event::Text(text) => {
+                open.last_mut().unwrap().text += &text.unescape().map_err(error)?
+            }
+            Event::CData(data) => open.last_mut().unwrap().text += &String::from_utf8_lossy(&data),
+            Event::Eof => break,
+            _ => {}
+        }
+    }
+    match open.pop() {
+        Some(document) if open.is_empty() => document.children.into_iter().next(),
+        _ => None,
+    }
+    .ok_or_else(|| XlsxError::InvalidPart {
+        part: part.to_string(),
+        message: "incomplete document".to_string(),
+    })
+}
+
+fn element(start: &BytesStart) -> Result<Element, quick_xml::Error> {
+    let mut attributes = Vec::new();
+    for attribute in start.attributes() {
+        let attribute = attribute?;
+        attributes.push((
+            String::from_utf8_lossy(attribute.key.local_name().as_ref()).into_owned(),
+            attribute.unescape_value()?.into_owned(),
+        ));
+    }
+    Ok(Element {
+        name: String::from_utf8_lossy(start.local_name().as_ref()).into_owned(),
+        attributes,
+        ..Element::default()
+    })
+}
+
+/// A part of the archive, `None` when there is no such part.
+fn part(archive: &mut Archive, path: &str) -> Result<Option<Element>, XlsxError> {
+    let mut file = match archive.by_name(path) {
+        Ok(file) => file,
+        Err(ZipError::FileNotFound) => return Ok(None),
+        Err(err) => return Err(err.into()),
+    };
+    let mut xml = String::new();
+    file.read_to_string(&mut xml).map_err(ZipError::Io)?;
+    parse(path, &xml).map(Some)
+}
+
+fn required_part(archive: &mut Archive, path: &str) -> Result<Element, XlsxError> {
+    part(archive, path)?.ok_or_else(|| XlsxError::MissingPart(path.to_string()))
+}
+
+fn invalid(part: &str, message: String) -> XlsxError {
+    XlsxError::InvalidPart {
+        part: part.to_string(),
+        message,
+    }
+}
+
+pub(super) fn workbook(bytes: &[u8]) -> Result<(Workbook, Vec<Unsupported>), XlsxError> {
+    let mut archive = ZipArchive::new(Cursor::new(bytes))?;
+    let book = required_part(&mut archive, "xl/workbook.xml")?;
+    let relationships = required_part(&mut archive, "xl/_rels/workbook.xml.rels")?;
+    let targets: HashMap<&str, String> = relationships
+        .children("Relationship")
+        .filter_map(|relationship| {
+            let target = relationship.attribute("Target")?;
+            let path = match target.strip_prefix('/') {
+                Some(path) => path.to_string(),
+                None => format!("xl/{}", target),
+            };
+            Some((relationship.attribute("Id")?, path))
+        })
+        .collect();
+    let strings: Vec<String> = match part(&mut archive, "xl/sharedStrings.xml")? {
+        Some(strings) => strings.children("si").map(Element::rich_text).collect(),
+        None => Vec::new(),
+    };
+
+    let mut workbook = Workbook::new();
+    let mut unsupported = Vec::new();
+    let mut sheet_names = Vec::new();
+    for sheet in book
+        .child("sheets")
+        .into_iter()
+        .flat_map(|s| s.children("sheet"))
+    {
+        let (Some(name), Some(path)) = (
+            sheet.attribute("name"),
+            sheet.attribute("id").and_then(|id| targets.get(id)),
+        ) else {
+            return Err(invalid(
+                "xl/workbook.xml",
+                "sheet without name or part".to_string(),
+            ));
+        };
+        let xml = required_part(&mut archive, path)?;
+        let sheet = worksheet(&xml, path, name, &strings, &mut unsupported)?;
+        workbook.add_sheet(name, sheet)?;
+        sheet_names.push(name);
+    }
+
+    let defined_names = book.child("definedNames").into_iter();
+    for defined in defined_names.flat_map(|names| names.children("definedName")) {
+        let Some(name) = defined.attribute("name") else {
+            continue;
+        };
+        let sheet = defined
+            .attribute("localSheetId")
+            .and_then(|index| sheet_names.get(index.parse::<usize>().ok()?).copied());
+        let formula = from_xlsx_formula(&defined.text, (0, 0));
+        if let Err(reason) = define_name(&mut workbook, sheet, name, &formula) {
+            unsupported.push(Unsupported {
+                sheet: sheet.map(str::to_string),
+                at: Location::Name(name.to_string()),
+                formula,
+                reason,
+            });
+        }
+    }
+    Ok((workbook, unsupported))
+}
+
+/// Defines a name for the workbook, or for `sheet`, where cells on the sheet
+/// itself are written without it.
+fn define_name(
+    workbook: &mut Workbook,
+    sheet: Option<&str>,
+    name: &str,
+    formula: &str,
+) -> Result<(), String> {
+    let definition = Name::parse(formula).ok_or("not a reference or a value")?;
+    match sheet {
+        Some(sheet) => {
+            let definition = match definition {
+                Name::Range {
+                    sheet: Some(own),
+                    range,
+                } if same_name(&own, sheet) => Name::from(range),
+                definition => definition,
+            };
+            let sheet = workbook.sheet_mut(sheet).unwrap();
+            sheet
+                .define_name(name, definition)
+                .map_err(|err| err.to_string())
+        }
+        None => workbook
+            .define_name(name, definition)
+            .map_err(|err| err.to_string()),
+    }
+}
+
+fn worksheet(
+    xml: &Element,
+    part: &str,
+    name: &str,
+    strings: &[String],
+    unsupported: &mut Vec<Unsupported>,
+) -> Result<Sheet, XlsxError> {
+    let mut sheet = Sheet::new();
+    for col in xml
+        .child("cols")
+        .into_iter()
+        .flat_map(|c| c.children("col"))
+    {
+        if !matches!(col.attribute("customWidth"), Some("1" | "true")) {
+            continue;
+        }
+        let number = |key| col.attribute(key).and_then(|n| n.parse::<usize>().ok());
+        let width = col.attribute("width").and_then(|w| w.parse::<f64>().ok());
+        if let (Some(min), Some(max), Some(width)) = (number("min"), number("max"), width) {
+            if min > max {
+                return Err(invalid(
+                    part,
+                    format!("invalid column range {}:{}", min, max),
+                ));
+            }
+            for col in min.max(1)..=max.min(MAX_COLUMNS) {
+                sheet.set_column_width(col - 1, Some(width));
+            }
+        }
+    }
+
+    // formulas shared by a block of cells are only written out for the
+    // first one, the others refer to it by `si`
+    let mut shared: HashMap<&str, (CellRef, &str)> = HashMap::new();
+    let mut next_row = 0;
+    let rows = xml
+        .child("sheetData")
+        .into_iter()
+        .flat_map(|d| d.children("row"));
+    for row in rows {
+        let row_index = match row.attribute("r") {
+            Some(r) => r
+                .parse::<usize>()
+                .ok()
+                .and_then(|r| r.checked_sub(1))
+                .filter(|row| *row < MAX_ROWS)
+                .ok_or_else(|| invalid(part, format!("invalid row number '{}'", r)))?,
+            None if next_row < MAX_ROWS => next_row,
+            None => return Err(invalid(part, "row after the last row".to_string())),
+        };
+        next_row = row_index + 1;
+        let mut next_col = 0;
+        for c in row.children("c") {
+            let at = match c.attribute("r") {
+                Some(r) => CellRef::parse(r)
+                    .ok_or_else(|| invalid(part, format!("invalid cell reference '{}'", r)))?,
+                None if next_col < MAX_COLUMNS => CellRef::new(row_index, next_col),
+                None => {
+                    let message = format!("cell after the last column in row {}", row_index + 1);
+                    return Err(invalid(part, message));
+                }
+            };
+            next_col = at.col + 1;
+            let value = value(c, strings).map_err(|message| invalid(part, message))?;
+
+            let Some(f) = c.child("f") else {
+                if let Some(value) = value {
+                    sheet.set(at, Cell::Literal(value));
+                }
+                continue;
+            };
+            let (formula, offset) = match (f.attribute("t"), f.attribute("si")) {
+                (Some("shared"), Some(si)) if f.text.is_empty() => {
+                    let (master, formula) = shared
+                        .get(si)
+                        .ok_or_else(|| invalid(part, format!("unknown shared formula {}", si)))?;
+                    let offset = (
+                        at.row as isize - master.row as isize,
+                        at.col as isize - master.col as isize,
+                    );
+                    (*formula, offset)
+                }
+                (Some("shared"), Some(si)) => {
+                    shared.insert(si, (at, &f.text));
+                    (f.text.as_str(), (0, 0))
+                }
+                _ => (f.text.as_str(), (0, 0)),
+            };
+            let formula = from_xlsx_formula(formula, offset);
+            let expr = match f.attribute("t") {
+                Some("dataTable") => Err("data tables aren't supported".to_string()),
+                _ => Expr::parse(&formula).map_err(|err| err.message),
+            };
+            match expr {
+                Ok(expr) => sheet.set(at, Cell::Formula(expr)),
+                Err(reason) => {
+                    unsupported.push(Unsupported {
+                        sheet: Some(name.to_string()),
+                        at: Location::Cell(at),
+                        formula,
+                        reason,
+                    });
+                    if let Some(value) = value {
+                        sheet.set(at, Cell::Literal(value));
+                    }
+                }
+            }
+        }
+    }
+    Ok(sheet)
+}
+
+/// The value stored in a cell, which for formulas is their last result.
+fn value(c: &Element, strings: &[String]) -> Result<Option<Value>, String> {
+    if c.attribute("t") == Some("inlineStr") {
+        return Ok(c.child("is").map(|is| Value::Text(is.rich_text())));
+    }
+    let Some(v) = c.child("v").map(|v| v.text.as_str()) else {
+        return Ok(None);
+    };
+    let value = match c.attribute("t").unwrap_or("n") {
+        "n" => match v.trim().parse::<f64>() {
+            Ok(n) if n.is_finite() => Value::Number(n),
+            _ => return Err(format!("invalid number '{}'", v)),
+        },
+        "s" => v
+            .trim()
+            .parse::<usize>()
+            .ok()
+            .and_then(|i| strings.get(i))
+            .map(|text| Value::Text(text.clone()))
+            .ok_or_else(|| format!("unknown shared string '{}'", v))?,
+        "str" | "d" => Value::Text(v.to_string()),
+        "b" => Value::Bool(v.trim() == "1"),
+        // errors of newer versions, like `#SPILL!`, are values gone wrong
+        "e" => Value::Error(CellError::from_code(v.trim()).unwrap_or(CellError::Value)),
+        kind => return Err(format!("unknown cell type '{}'", kind)),
+    };
+    Ok(Some(value))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use zip::write::FileOptions;
+    use zip::ZipWriter;
+
+    use super::*;
+    use crate::reference::Range;
+
+    /// An archive with the parts of a workbook of one sheet, `Data`, as
+    /// another program would write it: shared strings and formulas, `$`
+    /// references, functions that aren't supported here.
+    fn archive(sheet: &str, names: &str) -> Vec<u8> {
+        let parts = [
+            (
+                "xl/workbook.xml",
+                format!(
+                    r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+                    <workbook xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"
+                        xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships">
+                      <sheets><sheet name="Data" sheetId="1" r:id="rId3"/></sheets>
+                      <definedNames>{}</definedNames>
+                    </workbook>"#,
+                    names
+                ),
+            ),
+            (
+                "xl/_rels/workbook.xml.rels",
+                r#"<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">
+                  <Relationship Id="rId3" Type="worksheet" Target="/xl/worksheets/data.xml"/>
+                </Relationships>"#
+                    .to_string(),
+            ),
+            (
+                "xl/sharedStrings.xml",
+                r#"<sst xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main">
+                  <si><t>apple</t></si>
+                  <si><r><rPr><b/></rPr><t xml:space="preserve">rich </t></r><r><t>text</t></r><rPh><t>x</t></rPh></si>
+                </sst>"#
+                    .to_string(),
+            ),
+            ("xl/worksheets/data.xml", sheet.to_string()),
+        ];
+        let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+        for (path, contents) in parts {
+            zip.start_file(path, FileOptions::default()).unwrap();
+            zip.write_all(contents.as_bytes()).unwrap();
+        }
+        zip.finish().unwrap().into_inner()
+    }
+
+    fn sheet_data(rows: &str) -> String {
+        format!(
+            r#"<worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><sheetData>{}</sheetData></worksheet>"#,
+            rows
+        )
+    }
+
+    fn cell(workbook: &Workbook, at: &str) -> String {
+        let cell = workbook
+            .sheet("Data")
+            .unwrap()
+            .get(CellRef::parse(at).unwrap());
+        cell.map_or(String::new(), |cell| cell.to_string())
+    }
+
+    #[test]
+    fn values_and_formulas() {
+        let bytes = archive(
+            &sheet_data(
+                r#"<row r="1">
+                  <c r="A1" t="s"><v>0</v></c><c t="s"><v>1</v></c><c r="D1" t="b"><v>1</v></c>
+                </row>
+                <row r="2"><c r="A2"><v>2.5</v></c><c r="B2" t="e"><v>#N/A</v></c>
+                  <c r="C2" t="inlineStr"><is><t>inline &amp; &lt;escaped&gt;</t></is></c></row>
+                <row r="3">
+                  <c r="A3"><f>$A$2*2+SUM(A1:A2)</f><v>7.5</v></c>
+                  <c r="B3" t="str"><f>CONCAT(A1,"s")</f><v>apples</v></c>
+                </row>"#,
+            ),
+            "",
+        );
+        let (mut workbook, unsupported) = Workbook::from_xlsx(&bytes).unwrap();
+
+        assert_eq!(cell(&workbook, "A1"), "apple");
+        assert_eq!(cell(&workbook, "B1"), "rich text");
+        assert_eq!(cell(&workbook, "D1"), "TRUE");
+        assert_eq!(cell(&workbook, "B2"), "#N/A");
+        assert_eq!(cell(&workbook, "C2"), "inline & <escaped>");
+        assert_eq!(cell(&workbook, "A3"), "=A2*2+SUM(A1:A2)");
+        assert_eq!(
+            workbook.calculate("Data", CellRef::new(2, 0)),
+            Ok(Value::Number(7.5))
+        );
+        // the unsupported formula leaves its last value
+        assert_eq!(cell(&workbook, "B3"), "apples");
+        assert_eq!(
+            unsupported,
+            [Unsupported {
+                sheet: Some("Data".to_string()),
+                at: Location::Cell(CellRef::new(2, 1)),
+                formula: "CONCAT(A1,\"s\")".to_string(),
+                reason: "unknown function 'CONCAT'".to_string(),
+            }]
+        );
+        assert_eq!(
+            unsupported[0].to_string(),
+            "Data!B3: =CONCAT(A1,\"s\") (unknown function 'CONCAT')"
+        );
+    }
+
+    #[test]
+    fn shared_formulas() {
+        let bytes = archive(
+            &sheet_data(
+                r#"<row r="1"><c r="A1"><v>1</v></c><c r="B1"><v>2</v></c></row>
+                <row r="2">
+                  <c r="A2"><f t="shared" ref="A2:B3" si="0">A1*$A$1+A$1</f><v>2</v></c>
+                  <c r="B2"><f t="shared" si="0"/><v>4</v></c>
+                </row>
+                <row r="3"><c r="B3"><f t="shared" si="0"/><v>10</v></c></row>"#,
+            ),
+            "",
+        );
+        let (workbook, unsupported) = Workbook::from_xlsx(&bytes).unwrap();
+
+        assert!(unsupported.is_empty());
+        assert_eq!(cell(&workbook, "A2"), "=A1*A1+A1");
+        assert_eq!(cell(&workbook, "B2"), "=B1*A1+B1");
+        assert_eq!(cell(&workbook, "B3"), "=B2*A1+B1");
+    }
+
+    #[test]
+    fn defined_names() {
+        let bytes = archive(
+            &sheet_data(""),
+            r#"<definedName name="Prices">Data!$B$2:$B$9</definedName>
+            <definedName name="Local" localSheetId="0">Data!$C$1</definedName>
+            <definedName name="Rate">0.2</definedName>
+            <definedName name="Complex">Data!$A$1*2</definedName>"#,
+        );
+        let (workbook, unsupported) = Workbook::from_xlsx(&bytes).unwrap();
+
+        assert_eq!(
+            workbook.name("Prices"),
+            Some(&Name::Range {
+                sheet: Some("Data".to_string()),
+                range: Range::parse("B2:B9").unwrap(),
+            })
+        );
+        assert_eq!(
+            workbook.name("Rate"),
+            Some(&Name::Constant(Value::Number(0.2)))
+        );
+        assert_eq!(
+            workbook.sheet("Data").unwrap().name("Local"),
+            Some(&Name::from(Range::parse("C1").unwrap()))
+        );
+        assert_eq!(unsupported.len(), 1);
+        assert_eq!(unsupported[0].at, Location::Name("Complex".to_string()));
+        assert_eq!(unsupported[0].reason, "not a reference or a value");
+    }
+
+    #[test]
+    fn broken_parts() {
+        let bytes = archive("<worksheet><sheetData>", "");
+        assert!(matches!(
+            Workbook::from_xlsx(&bytes),
+            Err(XlsxError::InvalidPart { .. } | XlsxError::Xml { .. })
+        ));
+        let bytes = archive(
+            &sheet_data(r#"<row r="1"><c r="1A"><v>1</v></c></row>"#),
+            "",
+        );
+        let Err(err) = Workbook::from_xlsx(&bytes) else {
+            panic!("read an invalid cell reference");
+        };
+        assert_eq!(
+            err.to_string(),
+            "xl/worksheets/data.xml: invalid cell reference '1A'"
+        );
+    }
+
+    #[test]
+    fn positions_stay_in_the_grid() {
+        let message = |rows: &str| {
+            let bytes = archive(&sheet_data(rows), "");
+            match Workbook::from_xlsx(&bytes) {
+                Ok(_) => panic!("read a position outside the grid"),
+                Err(err) => err.to_string(),
+            }
+        };
+        assert_eq!(
+            message(r#"<row r="1048577"><c><v>1</v></c></row>"#),
+            "xl/worksheets/data.xml: invalid row number '1048577'"
+        );
+        assert_eq!(
+            message(r#"<row r="1048576"/><row><c><v>1</v></c></row>"#),
+            "xl/worksheets/data.xml: row after the last row"
+        );
+        assert_eq!(
+            message(r#"<row r="2"><c r="XFD2"><v>1</v></c><c><v>2</v></c></row>"#),
+            "xl/worksheets/data.xml: cell after the last column in row 2"
+        );
+
+        let bytes = archive(
+            &sheet_data(r#"<row r="1048576"><c r="XFC1048576"><v>1</v></c><c><v>2</v></c></row>"#),
+            "",
+        );
+        let (workbook, _) = Workbook::from_xlsx(&bytes).unwrap();
+        assert_eq!(cell(&workbook, "XFD1048576"), "2");
+    }
+
+    #[test]
+    fn column_widths_stay_in_the_grid() {
+        let sheet = |cols: &str| {
+            format!(
+                r#"<worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><cols>{}</cols><sheetData/></worksheet>"#,
+                cols
+            )
+        };
+        let bytes = archive(
+            &sheet(r#"<col min="16383" max="18446744073709551615" customWidth="1" width="5"/>"#),
+            "",
+        );
+        let (workbook, _) = Workbook::from_xlsx(&bytes).unwrap();
+        let widths: Vec<_> = workbook.sheet("Data").unwrap().column_widths().collect();
+        assert_eq!(widths, [(16382, 5.0), (16383, 5.0)]);
+
+        let bytes = archive(
+            &sheet(r#"<col min="3" max="2" customWidth="1" width="5"/>"#),
+            "",
+        );
+        let Err(err) = Workbook::from_xlsx(&bytes) else {
+            panic!("read an invalid column range");
+        };
+        assert_eq!(
+            err.to_string(),
+            "xl/worksheets/data.xml: invalid column range 3:2"
+        );
+    }
+}
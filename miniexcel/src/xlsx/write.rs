@@ -0,0 +1,242 @@
+use std::io::{Cursor, Write};
+
+use quick_xml::escape::escape;
+use zip::write::FileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use super::{absolute, XlsxError};
+use crate::formula::Expr;
+use crate::name::Name;
+use crate::reference::CellRef;
+use crate::sheet::Cell;
+use crate::value::Value;
+use crate::workbook::Workbook;
+
+const MAIN: &str = "http://schemas.openxmlformats.org/spreadsheetml/2006/main";
+const RELATIONSHIPS: &str = "http://schemas.openxmlformats.org/officeDocument/2006/relationships";
+const PACKAGE_RELATIONSHIPS: &str = "http://schemas.openxmlformats.org/package/2006/relationships";
+const CONTENT_TYPE: &str = "application/vnd.openxmlformats-officedocument.spreadsheetml";
+
+pub(super) fn workbook(workbook: &mut Workbook) -> Result<Vec<u8>, XlsxError> {
+    let names: Vec<String> = workbook.sheet_names().map(str::to_string).collect();
+    let mut parts = vec![
+        (
+            "[Content_Types].xml".to_string(),
+            content_types(names.len()),
+        ),
+        ("_rels/.rels".to_string(), root_relationships()),
+        ("xl/workbook.xml".to_string(), workbook_part(workbook)),
+        (
+            "xl/_rels/workbook.xml.rels".to_string(),
+            workbook_relationships(names.len()),
+        ),
+        ("xl/styles.xml".to_string(), styles()),
+    ];
+    for (i, name) in names.iter().enumerate() {
+        parts.push((
+            format!("xl/worksheets/sheet{}.xml", i + 1),
+            worksheet(workbook, name),
+        ));
+    }
+
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+    let options = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    for (path, contents) in parts {
+        zip.start_file(path, options)?;
+        zip.write_all(contents.as_bytes())
+            .map_err(zip::result::ZipError::Io)?;
+    }
+    Ok(zip.finish()?.into_inner())
+}
+
+fn content_types(sheets: usize) -> String {
+    let mut xml = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n\
+         <Types xmlns=\"http://schemas.openxmlformats.org/package/2006/content-types\">\
+         <Default Extension=\"rels\" ContentType=\"application/vnd.openxmlformats-package.relationships+xml\"/>\
+         <Default Extension=\"xml\" ContentType=\"application/xml\"/>",
+    );
+    xml += &format!(
+        "<Override PartName=\"/xl/workbook.xml\" ContentType=\"{}.sheet.main+xml\"/>\
+         <Override PartName=\"/xl/styles.xml\" ContentType=\"{}.styles+xml\"/>",
+        CONTENT_TYPE, CONTENT_TYPE
+    );
+    for i in 1..=sheets {
+        xml += &format!(
+            "<Override PartName=\"/xl/worksheets/sheet{}.xml\" ContentType=\"{}.worksheet+xml\"/>",
+            i, CONTENT_TYPE
+        );
+    }
+    xml + "</Types>"
+}
+
+fn root_relationships() -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n\
+         <Relationships xmlns=\"{}\">\
+         <Relationship Id=\"rId1\" Type=\"{}/officeDocument\" Target=\"xl/workbook.xml\"/>\
+         </Relationships>",
+        PACKAGE_RELATIONSHIPS, RELATIONSHIPS
+    )
+}
+
+fn workbook_relationships(sheets: usize) -> String {
+    let mut xml = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n\
+         <Relationships xmlns=\"{}\">",
+        PACKAGE_RELATIONSHIPS
+    );
+    for i in 1..=sheets {
+        xml += &format!(
+            "<Relationship Id=\"rId{}\" Type=\"{}/worksheet\" Target=\"worksheets/sheet{}.xml\"/>",
+            i, RELATIONSHIPS, i
+        );
+    }
+    xml += &format!(
+        "<Relationship Id=\"rId{}\" Type=\"{}/styles\" Target=\"styles.xml\"/>",
+        sheets + 1,
+        RELATIONSHIPS
+    );
+    xml + "</Relationships>"
+}
+
+/// The least styling spreadsheet programs expect to find: one font, fill,
+/// border and cell format, all of them the default.
+fn styles() -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n\
+         <styleSheet xmlns=\"{}\">\
+         <fonts count=\"1\"><font><sz val=\"11\"/><name val=\"Calibri\"/></font></fonts>\
+         <fills count=\"2\"><fill><patternFill patternType=\"none\"/></fill>\
+         <fill><patternFill patternType=\"gray125\"/></fill></fills>\
+         <borders count=\"1\"><border><left/><right/><top/><bottom/><diagonal/></border></borders>\
+         <cellStyleXfs count=\"1\"><xf numFmtId=\"0\" fontId=\"0\" fillId=\"0\" borderId=\"0\"/></cellStyleXfs>\
+         <cellXfs count=\"1\"><xf numFmtId=\"0\" fontId=\"0\" fillId=\"0\" borderId=\"0\" xfId=\"0\"/></cellXfs>\
+         </styleSheet>",
+        MAIN
+    )
+}
+
+fn workbook_part(workbook: &Workbook) -> String {
+    let mut xml = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n\
+         <workbook xmlns=\"{}\" xmlns:r=\"{}\"><sheets>",
+        MAIN, RELATIONSHIPS
+    );
+    for (i, name) in workbook.sheet_names().enumerate() {
+        xml += &format!(
+            "<sheet name=\"{}\" sheetId=\"{}\" r:id=\"rId{}\"/>",
+            escape(name),
+            i + 1,
+            i + 1
+        );
+    }
+    xml += "</sheets>";
+
+    let mut names = String::new();
+    for (name, definition) in workbook.names() {
+        names += &defined_name(name, definition, None);
+    }
+    for (i, sheet_name) in workbook.sheet_names().enumerate() {
+        let sheet = workbook.sheet(sheet_name).unwrap();
+        for (name, definition) in sheet.names() {
+            names += &defined_name(name, definition, Some((i, sheet_name)));
+        }
+    }
+    if !names.is_empty() {
+        xml += &format!("<definedNames>{}</definedNames>", names);
+    }
+    xml + "</workbook>"
+}
+
+/// A name, with the sheet it is defined for when it isn't a workbook name.
+/// XLSX names always say which sheet their cells are on.
+fn defined_name(name: &str, definition: &Name, sheet: Option<(usize, &str)>) -> String {
+    let definition = match (definition, sheet) {
+        (Name::Range { sheet: None, range }, Some((_, sheet))) => Expr::Range {
+            sheet: Some(sheet.to_string()),
+            range: *range,
+        }
+        .to_string(),
+        _ => definition.to_expr().to_string(),
+    };
+    let local = match sheet {
+        Some((index, _)) => format!(" localSheetId=\"{}\"", index),
+        None => String::new(),
+    };
+    format!(
+        "<definedName name=\"{}\"{}>{}</definedName>",
+        escape(name),
+        local,
+        escape(&absolute(&definition))
+    )
+}
+
+fn worksheet(workbook: &mut Workbook, name: &str) -> String {
+    let sheet = workbook.sheet(name).unwrap();
+    let cells: Vec<(CellRef, Cell)> = sheet.cells().map(|(at, cell)| (at, cell.clone())).collect();
+    let widths: Vec<(usize, f64)> = sheet.column_widths().collect();
+
+    let mut xml = format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n<worksheet xmlns=\"{}\">",
+        MAIN
+    );
+    if !widths.is_empty() {
+        xml += "<cols>";
+        for (col, width) in widths {
+            xml += &format!(
+                "<col min=\"{}\" max=\"{}\" width=\"{}\" customWidth=\"1\"/>",
+                col + 1,
+                col + 1,
+                width
+            );
+        }
+        xml += "</cols>";
+    }
+    xml += "<sheetData>";
+    let mut row = None;
+    for (at, cell) in cells {
+        if row != Some(at.row) {
+            if row.is_some() {
+                xml += "</row>";
+            }
+            xml += &format!("<row r=\"{}\">", at.row + 1);
+            row = Some(at.row);
+        }
+        let reference = at.to_string();
+        xml += &match cell {
+            Cell::Literal(Value::Text(text)) => format!(
+                "<c r=\"{}\" t=\"inlineStr\"><is><t xml:space=\"preserve\">{}</t></is></c>",
+                reference,
+                escape(&text)
+            ),
+            Cell::Literal(value) => value_cell(&reference, "", &value),
+            Cell::Formula(expr) => {
+                let value = workbook.calculate(name, at).unwrap();
+                let formula = format!("<f>{}</f>", escape(&expr.to_string()));
+                value_cell(&reference, &formula, &value)
+            }
+        };
+    }
+    if row.is_some() {
+        xml += "</row>";
+    }
+    xml + "</sheetData></worksheet>"
+}
+
+/// A cell holding a value, after its formula when it has one.
+fn value_cell(reference: &str, formula: &str, value: &Value) -> String {
+    let (kind, value) = match value {
+        Value::Empty => return format!("<c r=\"{}\">{}</c>", reference, formula),
+        Value::Number(n) => ("", n.to_string()),
+        Value::Decimal(d) => ("", d.to_string()),
+        // only formulas have plain text values, literals are inline
+        Value::Text(text) => (" t=\"str\"", escape(text).into_owned()),
+        Value::Bool(b) => (" t=\"b\"", (*b as u8).to_string()),
+        Value::Error(err) => (" t=\"e\"", err.to_string()),
+    };
+    format!(
+        "<c r=\"{}\"{}>{}<v>{}</v></c>",
+        reference, kind, formula, value
+    )
+}
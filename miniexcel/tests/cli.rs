@@ -0,0 +1,36 @@
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+/// Runs the binary on a script given on stdin, returning what it printed.
+fn run(script: &str) -> String {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_miniexcel"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(script.as_bytes())
+        .unwrap();
+    let output = child.wait_with_output().unwrap();
+    assert!(
+        output.status.success(),
+        "{}",
+        String::from_utf8_lossy(&output.stderr)
+    );
+    String::from_utf8(output.stdout).unwrap()
+}
+
+#[test]
+fn long_chains_of_formulas() {
+    let mut script = String::from("set A1 1\n");
+    for row in 2..=100_000 {
+        script += &format!("set A{} =A{}+1\n", row, row - 1);
+    }
+    script += "get A100000\nset A1 2\nget A99999:A100000\n";
+
+    assert_eq!(run(&script), "100000\n100000\n100001\n");
+}